@@ -5,6 +5,7 @@
  * It handles:
  * - Native messaging connection to the Rust native host
  * - Request/response queue management with ID matching
+ * - Forwarding progress messages of long-running commands to the tab
  * - Message validation and error handling
  * - Reconnection logic on failure
  */
@@ -13,6 +14,7 @@ const NATIVE_HOST_NAME = 'com.feitian.sk_manager';
 
 let nativePort = null;
 let requestQueue = new Map(); // Map of request ID to callback
+let progressHandlers = new Map(); // Map of request ID to progress callback
 let requestIdCounter = 0;
 let isConnected = false;
 
@@ -47,10 +49,20 @@ function handleNativeMessage(message) {
     return;
   }
   
+  // Progress messages precede the final response for the same ID
+  if (message.status === 'progress') {
+    const onProgress = progressHandlers.get(message.id);
+    if (onProgress) {
+      onProgress(message.result);
+    }
+    return;
+  }
+  
   const callback = requestQueue.get(message.id);
   if (callback) {
     callback(message);
     requestQueue.delete(message.id);
+    progressHandlers.delete(message.id);
   } else {
     console.warn('[Background] No callback found for message ID:', message.id);
   }
@@ -78,6 +90,7 @@ function handleNativeDisconnect() {
     });
   });
   requestQueue.clear();
+  progressHandlers.clear();
   
   // Attempt to reconnect after 5 seconds
   setTimeout(() => {
@@ -89,7 +102,7 @@ function handleNativeDisconnect() {
 /**
  * Send a message to the native host
  */
-function sendToNativeHost(command, params = {}) {
+function sendToNativeHost(command, params = {}, onProgress = null) {
   return new Promise((resolve, reject) => {
     if (!isConnected || !nativePort) {
       reject({
@@ -105,6 +118,9 @@ function sendToNativeHost(command, params = {}) {
     const id = ++requestIdCounter;
     const message = { id, command, params };
     
+    if (onProgress) {
+      progressHandlers.set(id, onProgress);
+    }
     requestQueue.set(id, (response) => {
       if (response.status === 'error') {
         reject(response);
@@ -118,6 +134,7 @@ function sendToNativeHost(command, params = {}) {
       console.log('[Background] Sent to native host:', message);
     } catch (error) {
       requestQueue.delete(id);
+      progressHandlers.delete(id);
       reject({
         status: 'error',
         error: {
@@ -146,7 +163,16 @@ chrome.runtime.onMessage.addListener((request, sender, sendResponse) => {
     return true;
   }
   
-  sendToNativeHost(request.command, request.params)
+  const tabId = sender.tab && sender.tab.id;
+  const onProgress = typeof tabId === 'number'
+    ? (step) => chrome.tabs.sendMessage(tabId, {
+        type: 'FEITIAN_SK_MANAGER_PROGRESS',
+        command: request.command,
+        step
+      })
+    : null;
+  
+  sendToNativeHost(request.command, request.params, onProgress)
     .then(response => sendResponse(response))
     .catch(error => sendResponse(error));
   
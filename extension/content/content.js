@@ -90,5 +90,18 @@
     }
   });
   
+  /**
+   * Relay progress of long-running commands from the background worker
+   */
+  chrome.runtime.onMessage.addListener((message) => {
+    if (message && message.type === 'FEITIAN_SK_MANAGER_PROGRESS') {
+      window.postMessage({
+        type: 'FEITIAN_SK_MANAGER_PROGRESS',
+        command: message.command,
+        step: message.step
+      }, '*');
+    }
+  });
+  
   console.log('[Content] Message listener initialized');
 })();
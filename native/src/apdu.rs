@@ -0,0 +1,588 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::device::DeviceManager;
+use crate::error::Error;
+use crate::transport;
+
+// ISO 7816 instructions shared by every application
+const INS_SELECT: u8 = 0xA4;
+const INS_GET_RESPONSE: u8 = 0xC0;
+
+// Command chaining: CLA bit set on every APDU but the last
+const CLA_CHAINING: u8 = 0x10;
+const MAX_APDU_DATA: usize = 0xFF;
+
+// Retry counters are a single byte, so this many wrong attempts always blocks
+const MAX_BLOCK_ATTEMPTS: u32 = 255;
+
+/// APDU command result for logging
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApduLog {
+    pub command: String,
+    pub command_hex: String,
+    pub response_hex: String,
+    pub sw1: u8,
+    pub sw2: u8,
+    pub status: String,
+    pub description: String,
+}
+
+/// Which part of an exchange the activity log must hide
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redact {
+    /// Log the exchange in full
+    Nothing,
+    /// The command data field, such as a PIN or private key
+    Command,
+    /// The response data, such as decrypted plaintext or a shared secret
+    Response,
+}
+
+/// How the rest of a response is fetched after 61 XX
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Continuation {
+    /// ISO 7816 GET RESPONSE (C0)
+    GetResponse,
+    /// An application-specific instruction, such as YKOATH SEND REMAINING (A5)
+    Instruction { ins: u8, name: &'static str },
+}
+
+impl Continuation {
+    fn build_apdu(self, le: u8) -> Vec<u8> {
+        match self {
+            Continuation::GetResponse => build_get_response_apdu(le),
+            Continuation::Instruction { ins, .. } => vec![0x00, ins, 0x00, 0x00, le],
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Continuation::GetResponse => "GET RESPONSE",
+            Continuation::Instruction { name, .. } => name,
+        }
+    }
+}
+
+/// Response data and the final status word of an exchange
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApduResponse {
+    pub data: Vec<u8>,
+    pub sw1: u8,
+    pub sw2: u8,
+}
+
+impl ApduResponse {
+    pub fn is_success(&self) -> bool {
+        self.sw1 == 0x90 && self.sw2 == 0x00
+    }
+
+    /// The response data, or the status word as an error
+    pub fn into_data(self, command_name: &str) -> Result<Vec<u8>> {
+        if self.is_success() {
+            return Ok(self.data);
+        }
+        Err(
+            anyhow::Error::new(Error::from_status_word(self.sw1, self.sw2)).context(format!(
+                "{} failed: {}",
+                command_name,
+                status_word_description(self.sw1, self.sw2)
+            )),
+        )
+    }
+}
+
+/// One step of a multi-step operation, reported as soon as it completes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgressStep {
+    pub stage: String,
+    pub message: String,
+    /// The APDU exchange behind the step, if any
+    pub apdu: Option<ApduLog>,
+}
+
+/// Records progress steps and forwards each one to a listener as it happens
+pub struct ProgressReporter<'a> {
+    on_step: &'a mut dyn FnMut(&ProgressStep),
+    steps: Vec<ProgressStep>,
+}
+
+impl<'a> ProgressReporter<'a> {
+    pub fn new(on_step: &'a mut dyn FnMut(&ProgressStep)) -> Self {
+        ProgressReporter {
+            on_step,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Report a step, attaching the most recent APDU exchange
+    pub fn report(&mut self, stage: &str, message: String, activity_log: &[ApduLog]) {
+        let step = ProgressStep {
+            stage: stage.to_string(),
+            message,
+            apdu: activity_log.last().cloned(),
+        };
+        (self.on_step)(&step);
+        self.steps.push(step);
+    }
+
+    /// The steps reported so far
+    pub fn into_steps(self) -> Vec<ProgressStep> {
+        self.steps
+    }
+}
+
+/// Block a PIN or password by sending wrong guesses until none are left
+///
+/// `send_wrong` gets a different eight-digit guess each time, in case one
+/// happens to be correct, and reports a rejection as `PinInvalid` or
+/// `PinBlocked`. Cards that never report a count keep being tried until they
+/// answer 6983 or `MAX_BLOCK_ATTEMPTS` is reached.
+pub fn block_with_wrong_guesses(
+    name: &str,
+    stage: &str,
+    mut send_wrong: impl FnMut(&str, u32, &mut Vec<ApduLog>) -> Result<()>,
+    progress: &mut ProgressReporter,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<()> {
+    for attempt in 0..MAX_BLOCK_ATTEMPTS {
+        let guess = format!("{:08}", attempt);
+        let error = match send_wrong(&guess, attempt, activity_log) {
+            Ok(()) => {
+                progress.report(
+                    stage,
+                    format!("{} guess was accepted, trying another", name),
+                    activity_log,
+                );
+                continue;
+            }
+            Err(e) => e,
+        };
+        match crate::error::classify(&error) {
+            Some(Error::PinBlocked) | Some(Error::PinInvalid { retries: Some(0) }) => {
+                progress.report(stage, format!("{} blocked", name), activity_log);
+                return Ok(());
+            }
+            Some(Error::PinInvalid { retries }) => {
+                let left = retries.map_or("unknown".to_string(), |r| r.to_string());
+                progress.report(
+                    stage,
+                    format!("Wrong {} sent, {} attempts left", name, left),
+                    activity_log,
+                );
+            }
+            _ => return Err(error.context(format!("Failed to block {}", name))),
+        }
+    }
+
+    Err(anyhow!(
+        "{} still not blocked after {} attempts",
+        name,
+        MAX_BLOCK_ATTEMPTS
+    ))
+}
+
+/// Format bytes as hex string
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Parse status word to human-readable description
+fn status_word_description(sw1: u8, sw2: u8) -> String {
+    match (sw1, sw2) {
+        (0x90, 0x00) => "Success".to_string(),
+        (0x61, _) => format!("{} bytes of response data available", sw2),
+        (0x62, 0x81) => "Part of returned data may be corrupted".to_string(),
+        (0x62, 0x82) => "End of file reached before reading Le bytes".to_string(),
+        (0x62, 0x85) => "Selected file in termination state".to_string(),
+        (0x63, 0x00) => "Verification failed".to_string(),
+        (0x63, n) if n >= 0xC0 => format!("Verification failed, {} retries remaining", n & 0x0F),
+        (0x64, _) => "Execution error".to_string(),
+        (0x65, _) => "Memory failure".to_string(),
+        (0x67, 0x00) => "Wrong length".to_string(),
+        (0x68, 0x81) => "Logical channel not supported".to_string(),
+        (0x68, 0x82) => "Secure messaging not supported".to_string(),
+        (0x69, 0x81) => "Command incompatible with file structure".to_string(),
+        (0x69, 0x82) => "Security status not satisfied".to_string(),
+        (0x69, 0x83) => "Authentication method blocked".to_string(),
+        (0x69, 0x84) => "Referenced data invalidated".to_string(),
+        (0x69, 0x85) => "Conditions of use not satisfied".to_string(),
+        (0x69, 0x86) => "Command not allowed (no current EF)".to_string(),
+        (0x6A, 0x80) => "Incorrect parameters in data field".to_string(),
+        (0x6A, 0x81) => "Function not supported".to_string(),
+        (0x6A, 0x82) => "File not found / Data object not found".to_string(),
+        (0x6A, 0x83) => "Record not found".to_string(),
+        (0x6A, 0x84) => "Not enough memory space".to_string(),
+        (0x6A, 0x86) => "Incorrect parameters P1-P2".to_string(),
+        (0x6A, 0x88) => "Referenced data not found".to_string(),
+        (0x6B, 0x00) => "Wrong parameter(s) P1-P2".to_string(),
+        (0x6C, n) => format!("Wrong Le field; {} bytes available", n),
+        (0x6D, 0x00) => "Instruction code not supported or invalid".to_string(),
+        (0x6E, 0x00) => "Class not supported".to_string(),
+        (0x6F, 0x00) => "No precise diagnosis".to_string(),
+        _ => format!("Unknown status: {:02X} {:02X}", sw1, sw2),
+    }
+}
+
+
+/// Format a command for the log, hiding its data field if it is secret
+pub fn command_log_hex(apdu: &[u8], redact: Redact) -> String {
+    if redact != Redact::Command || apdu.len() <= 5 {
+        return bytes_to_hex(apdu);
+    }
+    format!(
+        "{} [{} bytes redacted]",
+        bytes_to_hex(&apdu[..5]),
+        apdu.len() - 5
+    )
+}
+
+/// Format a response (data and status word) for the log
+fn response_log_hex(response: &[u8], redact: Redact) -> String {
+    if redact != Redact::Response || response.len() <= 2 {
+        return bytes_to_hex(response);
+    }
+    let data_len = response.len() - 2;
+    format!(
+        "[{} bytes redacted] {}",
+        data_len,
+        bytes_to_hex(&response[data_len..])
+    )
+}
+
+fn log_status(sw1: u8, sw2: u8) -> String {
+    if sw1 == 0x90 && sw2 == 0x00 {
+        "OK".to_string()
+    } else if sw1 == 0x61 {
+        "MORE_DATA".to_string()
+    } else {
+        "ERROR".to_string()
+    }
+}
+
+/// Build SELECT APDU command
+pub fn build_select_apdu(aid: &[u8]) -> Vec<u8> {
+    let mut apdu = vec![
+        0x00, // CLA
+        INS_SELECT, // INS
+        0x04, // P1 = Select by name
+        0x00, // P2 = First or only occurrence
+        aid.len() as u8, // Lc
+    ];
+    apdu.extend_from_slice(aid);
+    apdu
+}
+
+/// Build GET RESPONSE APDU
+fn build_get_response_apdu(le: u8) -> Vec<u8> {
+    vec![
+        0x00, // CLA
+        INS_GET_RESPONSE, // INS
+        0x00, // P1
+        0x00, // P2
+        le, // Le
+    ]
+}
+
+/// Split a command into chained APDUs (CLA 10 on all but the last)
+pub fn build_chained_apdus(ins: u8, p1: u8, p2: u8, data: &[u8]) -> Vec<Vec<u8>> {
+    let chunks: Vec<&[u8]> = if data.is_empty() {
+        vec![&[]]
+    } else {
+        data.chunks(MAX_APDU_DATA).collect()
+    };
+    let last = chunks.len() - 1;
+
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let cla = if i == last { 0x00 } else { CLA_CHAINING };
+            let mut apdu = vec![cla, ins, p1, p2, chunk.len() as u8];
+            apdu.extend_from_slice(chunk);
+            apdu
+        })
+        .collect()
+}
+
+/// Transmit APDU and handle response chaining (61 XX)
+pub fn transmit(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    apdu: &[u8],
+    redact: Redact,
+    command_name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    transmit_with_continuation(
+        device_manager,
+        device_id,
+        apdu,
+        Continuation::GetResponse,
+        redact,
+        command_name,
+        activity_log,
+    )
+}
+
+/// Transmit APDU, fetching chained response data with `continuation`
+pub fn transmit_with_continuation(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    apdu: &[u8],
+    continuation: Continuation,
+    redact: Redact,
+    command_name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    exchange(
+        device_manager,
+        device_id,
+        apdu,
+        continuation,
+        redact,
+        command_name,
+        activity_log,
+    )?
+    .into_data(command_name)
+}
+
+/// Send a chained command, expecting 9000 for every intermediate APDU
+pub fn transmit_chain(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    apdus: &[Vec<u8>],
+    redact: Redact,
+    command_name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    let mut response = Vec::new();
+    for (i, apdu) in apdus.iter().enumerate() {
+        let name = if apdus.len() > 1 {
+            format!("{} [{}/{}]", command_name, i + 1, apdus.len())
+        } else {
+            command_name.to_string()
+        };
+        response = transmit(device_manager, device_id, apdu, redact, &name, activity_log)?;
+    }
+    Ok(response)
+}
+
+/// Transmit an APDU, fetching chained response data with `continuation`
+///
+/// The final status word is returned rather than turned into an error, for
+/// callers that treat some statuses as answers. Only a failure while
+/// fetching the rest of a chained response is an error.
+pub fn exchange(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    apdu: &[u8],
+    continuation: Continuation,
+    redact: Redact,
+    command_name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<ApduResponse> {
+    let logged_hex = command_log_hex(apdu, redact);
+    log::debug!("Transmitting APDU: {} - {}", command_name, logged_hex);
+
+    // Add timeout for device operations
+    let response = device_manager.with_ccid_card(device_id, |card| {
+        transport::transmit_apdu(card, apdu)
+    }).map_err(|e| {
+        log::error!("Failed to transmit APDU to device {}: {}", device_id, e);
+        e
+    })?;
+
+    if response.len() < 2 {
+        return Err(Error::MalformedResponse("Response too short".to_string()).into());
+    }
+
+    let sw1 = response[response.len() - 2];
+    let sw2 = response[response.len() - 1];
+    let data = response[..response.len() - 2].to_vec();
+
+    // Log the initial command
+    activity_log.push(ApduLog {
+        command: command_name.to_string(),
+        command_hex: logged_hex,
+        response_hex: response_log_hex(&response, redact),
+        sw1,
+        sw2,
+        status: log_status(sw1, sw2),
+        description: status_word_description(sw1, sw2),
+    });
+
+    if sw1 != 0x61 {
+        return Ok(ApduResponse { data, sw1, sw2 });
+    }
+
+    // Handle response chaining (61 XX = more data available)
+    let mut full_response = data;
+    let mut remaining = sw2;
+
+    loop {
+        let get_response = continuation.build_apdu(remaining);
+        log::debug!("{}: {}", continuation.name(), bytes_to_hex(&get_response));
+
+        let chunk = device_manager.with_ccid_card(device_id, |card| {
+            transport::transmit_apdu(card, &get_response)
+        }).map_err(|e| {
+            log::error!(
+                "Failed to transmit {} to device {}: {}",
+                continuation.name(),
+                device_id,
+                e
+            );
+            e
+        })?;
+
+        if chunk.len() < 2 {
+            return Err(
+                Error::MalformedResponse(format!("{} too short", continuation.name())).into(),
+            );
+        }
+
+        let chunk_sw1 = chunk[chunk.len() - 2];
+        let chunk_sw2 = chunk[chunk.len() - 1];
+        let chunk_data = &chunk[..chunk.len() - 2];
+
+        activity_log.push(ApduLog {
+            command: format!("{} ({})", command_name, continuation.name()),
+            command_hex: bytes_to_hex(&get_response),
+            response_hex: response_log_hex(&chunk, redact),
+            sw1: chunk_sw1,
+            sw2: chunk_sw2,
+            status: log_status(chunk_sw1, chunk_sw2),
+            description: status_word_description(chunk_sw1, chunk_sw2),
+        });
+
+        full_response.extend_from_slice(chunk_data);
+
+        if chunk_sw1 == 0x90 && chunk_sw2 == 0x00 {
+            break;
+        } else if chunk_sw1 == 0x61 {
+            remaining = chunk_sw2;
+        } else {
+            return Err(
+                anyhow::Error::new(Error::from_status_word(chunk_sw1, chunk_sw2))
+                    .context(format!("{} failed", continuation.name())),
+            );
+        }
+    }
+
+    Ok(ApduResponse {
+        data: full_response,
+        sw1: 0x90,
+        sw2: 0x00,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_word_description() {
+        assert_eq!(status_word_description(0x90, 0x00), "Success");
+        assert_eq!(status_word_description(0x6A, 0x82), "File not found / Data object not found");
+        assert!(status_word_description(0x63, 0xC3).contains("3 retries"));
+    }
+
+    #[test]
+    fn test_bytes_to_hex() {
+        assert_eq!(bytes_to_hex(&[0x00, 0xA4, 0x04]), "00 A4 04");
+    }
+
+    #[test]
+    fn test_log_redaction() {
+        let apdu = [0x00, 0x20, 0x00, 0x80, 0x02, 0x31, 0x32];
+        assert_eq!(
+            command_log_hex(&apdu, Redact::Command),
+            "00 20 00 80 02 [2 bytes redacted]"
+        );
+        assert_eq!(
+            command_log_hex(&apdu, Redact::Response),
+            "00 20 00 80 02 31 32"
+        );
+        assert_eq!(command_log_hex(&apdu[..4], Redact::Command), "00 20 00 80");
+
+        let response = [0x01, 0x02, 0x03, 0x90, 0x00];
+        assert_eq!(
+            response_log_hex(&response, Redact::Response),
+            "[3 bytes redacted] 90 00"
+        );
+        assert_eq!(
+            response_log_hex(&response, Redact::Command),
+            "01 02 03 90 00"
+        );
+        assert_eq!(response_log_hex(&[0x6A, 0x82], Redact::Response), "6A 82");
+    }
+
+    #[test]
+    fn test_response_into_data() {
+        let ok = ApduResponse {
+            data: vec![0x01],
+            sw1: 0x90,
+            sw2: 0x00,
+        };
+        assert_eq!(ok.into_data("TEST").unwrap(), vec![0x01]);
+
+        let missing = ApduResponse {
+            data: Vec::new(),
+            sw1: 0x6A,
+            sw2: 0x82,
+        };
+        let err = missing.into_data("TEST").unwrap_err();
+        assert_eq!(
+            crate::error::classify(&err),
+            Some(&Error::StatusWord {
+                sw1: 0x6A,
+                sw2: 0x82
+            })
+        );
+    }
+
+    #[test]
+    fn test_block_with_wrong_guesses() {
+        let mut guesses = Vec::new();
+        let card = |guess: &str, _: u32, _: &mut Vec<ApduLog>| -> Result<()> {
+            guesses.push(guess.to_string());
+            let retries = 3 - guesses.len() as u8;
+            Err(Error::PinInvalid {
+                retries: Some(retries),
+            }
+            .into())
+        };
+        let mut on_step = |_: &ProgressStep| {};
+        let mut progress = ProgressReporter::new(&mut on_step);
+        block_with_wrong_guesses("PIN", "blockPin", card, &mut progress, &mut Vec::new()).unwrap();
+        assert_eq!(progress.steps.last().unwrap().message, "PIN blocked");
+        drop(progress);
+        assert_eq!(guesses, vec!["00000000", "00000001", "00000002"]);
+    }
+
+    #[test]
+    fn test_progress_reporter() {
+        let mut seen = Vec::new();
+        let mut on_step = |step: &ProgressStep| seen.push(step.stage.clone());
+        let mut progress = ProgressReporter::new(&mut on_step);
+
+        progress.report("select", "selected".to_string(), &[]);
+        let log = vec![ApduLog {
+            command: "RESET".to_string(),
+            command_hex: command_log_hex(&[0x00, 0xFB, 0x00, 0x00], Redact::Nothing),
+            response_hex: String::new(),
+            sw1: 0x90,
+            sw2: 0x00,
+            status: "9000".to_string(),
+            description: "Success".to_string(),
+        }];
+        progress.report("reset", "reset".to_string(), &log);
+
+        let steps = progress.steps;
+        assert_eq!(steps.len(), 2);
+        assert!(steps[0].apdu.is_none());
+        assert_eq!(steps[1].apdu.as_ref().unwrap().command_hex, "00 FB 00 00");
+        assert_eq!(seen, vec!["select", "reset"]);
+    }
+}
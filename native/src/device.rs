@@ -1,6 +1,9 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+use crate::error::Error;
+use crate::piv::PivSession;
 
 /// Device type enumeration
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -21,8 +24,13 @@ pub struct Device {
     pub product_name: Option<String>,
     pub serial_number: Option<String>,
     pub path: String,
+    /// Keyboard interface of a security key, which only carries OTP
+    pub otp_only: bool,
 }
 
+/// Vendors whose keyboard interface is an OTP interface (Feitian, Yubico)
+const OTP_KEYBOARD_VENDORS: [u16; 2] = [0x096E, 0x1050];
+
 /// Enumerate all HID devices
 fn enumerate_hid_devices() -> Result<Vec<Device>> {
     log::debug!("Enumerating HID devices...");
@@ -45,8 +53,11 @@ fn enumerate_hid_devices() -> Result<Vec<Device>> {
         );
 
         // Skip obvious non-FIDO interfaces (keyboard=0x01/0x06, mouse=0x01/0x02)
-        // But keep everything else including unknown usage pages
-        if usage_page == 0x01 && (usage == 0x02 || usage == 0x06) {
+        // But keep everything else including unknown usage pages, and the
+        // keyboard interface of security keys since it carries OTP
+        let otp_keyboard =
+            usage == 0x06 && OTP_KEYBOARD_VENDORS.contains(&device_info.vendor_id());
+        if usage_page == 0x01 && (usage == 0x02 || usage == 0x06) && !otp_keyboard {
             log::debug!("Skipping keyboard/mouse interface (usage page 0x{:04x}, usage 0x{:04x})", usage_page, usage);
             continue;
         }
@@ -91,6 +102,7 @@ fn enumerate_hid_devices() -> Result<Vec<Device>> {
             product_name,
             serial_number,
             path: device_info.path().to_string_lossy().to_string(),
+            otp_only: otp_keyboard,
         };
 
         log::info!(
@@ -169,6 +181,7 @@ fn enumerate_ccid_devices() -> Result<Vec<Device>> {
                     product_name: Some(reader_str.to_string()),
                     serial_number: None,
                     path: reader_str.to_string(),
+                    otp_only: false,
                 };
 
                 log::info!(
@@ -250,19 +263,25 @@ pub struct DeviceManager {
     hid_api: std::sync::Arc<std::sync::Mutex<hidapi::HidApi>>,
     pcsc_context: std::sync::Arc<std::sync::Mutex<pcsc::Context>>,
     open_devices: std::sync::Arc<std::sync::Mutex<HashMap<String, OpenDevice>>>,
+    piv_sessions: std::sync::Arc<std::sync::Mutex<HashMap<String, PivSession>>>,
+    /// Open devices that are OTP keyboard interfaces
+    otp_only_devices: std::sync::Arc<std::sync::Mutex<HashSet<String>>>,
 }
 
 impl DeviceManager {
     /// Create a new device manager
     pub fn new() -> Result<Self> {
-        let hid_api = hidapi::HidApi::new().context("Failed to initialize HID API")?;
+        let hid_api = hidapi::HidApi::new()
+            .map_err(|e| Error::from_hid(e, "Failed to initialize HID API"))?;
         let pcsc_context = pcsc::Context::establish(pcsc::Scope::User)
-            .context("Failed to establish PC/SC context")?;
+            .map_err(|e| Error::from_pcsc(e, "Failed to establish PC/SC context"))?;
 
         Ok(Self {
             hid_api: std::sync::Arc::new(std::sync::Mutex::new(hid_api)),
             pcsc_context: std::sync::Arc::new(std::sync::Mutex::new(pcsc_context)),
             open_devices: std::sync::Arc::new(std::sync::Mutex::new(HashMap::new())),
+            piv_sessions: std::sync::Arc::new(std::sync::Mutex::new(HashMap::new())),
+            otp_only_devices: std::sync::Arc::new(std::sync::Mutex::new(HashSet::new())),
         })
     }
 
@@ -272,7 +291,7 @@ impl DeviceManager {
 
         // Check if device is already open
         if open_devices.contains_key(device_id) {
-            return Err(anyhow::anyhow!("Device {} is already open", device_id));
+            return Err(Error::DeviceAlreadyOpen(device_id.to_string()).into());
         }
 
         // Get all devices
@@ -280,7 +299,7 @@ impl DeviceManager {
         let device = all_devices
             .iter()
             .find(|d| d.id == device_id)
-            .ok_or_else(|| anyhow::anyhow!("Device {} not found", device_id))?;
+            .ok_or_else(|| Error::DeviceNotFound(device_id.to_string()))?;
 
         log::info!("Opening device: {} ({:?})", device_id, device.device_type);
 
@@ -301,6 +320,7 @@ impl DeviceManager {
                         // Fallback to VID/PID only if path fails
                         hid_api
                             .open(device.vendor_id, device.product_id)
+                            .map_err(|e| Error::from_hid(e, "Failed to open HID device"))
                             .context(format!(
                                 "Failed to open HID device. Tried path {} and VID/PID {:04x}:{:04x}. \
                                  Error: {}. The device may be in use by another application, or you may need to grant \
@@ -314,6 +334,12 @@ impl DeviceManager {
                 };
 
                 open_devices.insert(device_id.to_string(), OpenDevice::Hid(hid_device));
+                if device.otp_only {
+                    self.otp_only_devices
+                        .lock()
+                        .unwrap()
+                        .insert(device_id.to_string());
+                }
                 log::info!("Successfully opened HID device: {}", device_id);
             }
             DeviceType::Ccid => {
@@ -323,6 +349,7 @@ impl DeviceManager {
 
                 let card = pcsc_context
                     .connect(&reader_name, pcsc::ShareMode::Shared, pcsc::Protocols::ANY)
+                    .map_err(|e| Error::from_pcsc(e, "Failed to connect to CCID card"))
                     .context(format!("Failed to connect to CCID card at {}", device.path))?;
 
                 open_devices.insert(device_id.to_string(), OpenDevice::Ccid(card));
@@ -338,11 +365,13 @@ impl DeviceManager {
         let mut open_devices = self.open_devices.lock().unwrap();
 
         if !open_devices.contains_key(device_id) {
-            return Err(anyhow::anyhow!("Device {} is not open", device_id));
+            return Err(Error::DeviceNotOpen(device_id.to_string()).into());
         }
 
         log::info!("Closing device: {}", device_id);
         open_devices.remove(device_id);
+        self.piv_sessions.lock().unwrap().remove(device_id);
+        self.otp_only_devices.lock().unwrap().remove(device_id);
         log::info!("Successfully closed device: {}", device_id);
 
         Ok(())
@@ -357,14 +386,47 @@ impl DeviceManager {
 
         match open_devices.get(device_id) {
             Some(OpenDevice::Hid(device)) => f(device),
-            Some(OpenDevice::Ccid(_)) => Err(anyhow::anyhow!(
-                "Device {} is a CCID device, not HID",
-                device_id
-            )),
-            None => Err(anyhow::anyhow!("Device {} is not open", device_id)),
+            Some(OpenDevice::Ccid(_)) => Err(Error::DeviceTypeMismatch {
+                device_id: device_id.to_string(),
+                expected: "HID",
+            }
+            .into()),
+            None => Err(Error::DeviceNotOpen(device_id.to_string()).into()),
+        }
+    }
+
+    /// Get the type of an open device
+    pub fn device_type(&self, device_id: &str) -> Result<DeviceType> {
+        match self.open_devices.lock().unwrap().get(device_id) {
+            Some(OpenDevice::Hid(_)) => Ok(DeviceType::Hid),
+            Some(OpenDevice::Ccid(_)) => Ok(DeviceType::Ccid),
+            None => Err(Error::DeviceNotOpen(device_id.to_string()).into()),
         }
     }
 
+    /// Whether an open device is an OTP keyboard interface
+    pub fn is_otp_only(&self, device_id: &str) -> bool {
+        self.otp_only_devices.lock().unwrap().contains(device_id)
+    }
+
+    /// Get the PIV session for a device (empty if none was established)
+    pub fn piv_session(&self, device_id: &str) -> PivSession {
+        self.piv_sessions
+            .lock()
+            .unwrap()
+            .get(device_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Replace the PIV session for a device
+    pub fn set_piv_session(&self, device_id: &str, session: PivSession) {
+        self.piv_sessions
+            .lock()
+            .unwrap()
+            .insert(device_id.to_string(), session);
+    }
+
     /// Execute an operation with a CCID card
     pub fn with_ccid_card<F, R>(&self, device_id: &str, f: F) -> Result<R>
     where
@@ -374,11 +436,12 @@ impl DeviceManager {
 
         match open_devices.get(device_id) {
             Some(OpenDevice::Ccid(card)) => f(card),
-            Some(OpenDevice::Hid(_)) => Err(anyhow::anyhow!(
-                "Device {} is a HID device, not CCID",
-                device_id
-            )),
-            None => Err(anyhow::anyhow!("Device {} is not open", device_id)),
+            Some(OpenDevice::Hid(_)) => Err(Error::DeviceTypeMismatch {
+                device_id: device_id.to_string(),
+                expected: "CCID",
+            }
+            .into()),
+            None => Err(Error::DeviceNotOpen(device_id.to_string()).into()),
         }
     }
 }
@@ -398,11 +461,13 @@ mod tests {
             product_name: Some("Test Device".to_string()),
             serial_number: Some("ABC123".to_string()),
             path: "/dev/hidraw0".to_string(),
+            otp_only: false,
         };
 
         let json = serde_json::to_string(&device).unwrap();
         assert!(json.contains("\"vendor_id\":2414"));
         assert!(json.contains("\"device_type\":\"Hid\""));
+        assert!(json.contains("\"otp_only\":false"));
     }
 
     #[test]
@@ -0,0 +1,325 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Broad class of an error, used by the UI to decide how to present it
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCategory {
+    Transport,
+    Protocol,
+    User,
+    InvalidParams,
+    Internal,
+}
+
+/// Crate-wide error taxonomy
+///
+/// Modules keep returning `anyhow::Result`, but wrap failures they can classify
+/// in one of these variants. `classify` recovers the variant from an
+/// `anyhow::Error` chain so responses carry a stable code, a retryable flag
+/// and structured details.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    // Transport errors
+    DeviceNotFound(String),
+    DeviceNotOpen(String),
+    DeviceAlreadyOpen(String),
+    DeviceTypeMismatch {
+        device_id: String,
+        expected: &'static str,
+    },
+    DeviceDisconnected(String),
+    PermissionDenied(String),
+    PcscUnavailable(String),
+    ReaderBusy(String),
+    Timeout(String),
+    TransportFailure(String),
+
+    // Protocol errors
+    StatusWord {
+        sw1: u8,
+        sw2: u8,
+    },
+    CtapStatus(u8),
+    CtapHid(u8),
+    PinAuthInvalid,
+    MalformedResponse(String),
+    NotSupported(String),
+
+    // User errors
+    PinInvalid {
+        retries: Option<u8>,
+    },
+    PinBlocked,
+    PinAuthBlocked,
+    PinRequired,
+    PinNotSet,
+    PinPolicyViolation(String),
+    SecurityStatusNotSatisfied,
+    OperationDenied(String),
+
+    // Invalid parameters
+    InvalidParams(String),
+}
+
+impl Error {
+    /// Map an ISO 7816 status word to the most specific error
+    pub fn from_status_word(sw1: u8, sw2: u8) -> Self {
+        match (sw1, sw2) {
+            (0x63, n) if n & 0xF0 == 0xC0 => Error::PinInvalid {
+                retries: Some(n & 0x0F),
+            },
+            (0x63, 0x00) => Error::PinInvalid { retries: None },
+            (0x69, 0x82) => Error::SecurityStatusNotSatisfied,
+            (0x69, 0x83) => Error::PinBlocked,
+            _ => Error::StatusWord { sw1, sw2 },
+        }
+    }
+
+    /// Map a PC/SC error, keeping the context of the failed operation
+    pub fn from_pcsc(err: pcsc::Error, context: &str) -> Self {
+        let message = format!("{}: {}", context, err);
+        match err {
+            pcsc::Error::NoService
+            | pcsc::Error::ServiceStopped
+            | pcsc::Error::NoReadersAvailable => Error::PcscUnavailable(message),
+            pcsc::Error::RemovedCard
+            | pcsc::Error::ResetCard
+            | pcsc::Error::NoSmartcard
+            | pcsc::Error::ReaderUnavailable
+            | pcsc::Error::UnpoweredCard
+            | pcsc::Error::UnknownReader => Error::DeviceDisconnected(message),
+            pcsc::Error::SharingViolation | pcsc::Error::ServerTooBusy => {
+                Error::ReaderBusy(message)
+            }
+            pcsc::Error::NoAccess | pcsc::Error::SecurityViolation => {
+                Error::PermissionDenied(message)
+            }
+            pcsc::Error::Timeout | pcsc::Error::WaitedTooLong => Error::Timeout(message),
+            _ => Error::TransportFailure(message),
+        }
+    }
+
+    /// Map a HID API error, keeping the context of the failed operation
+    pub fn from_hid(err: hidapi::HidError, context: &str) -> Self {
+        let message = format!("{}: {}", context, err);
+        let lower = message.to_lowercase();
+        if lower.contains("permission denied") || lower.contains("access denied") {
+            Error::PermissionDenied(message)
+        } else if lower.contains("no such device") || lower.contains("disconnected") {
+            Error::DeviceDisconnected(message)
+        } else {
+            Error::TransportFailure(message)
+        }
+    }
+
+    /// Stable machine-readable code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::DeviceNotFound(_) => "DEVICE_NOT_FOUND",
+            Error::DeviceNotOpen(_) => "DEVICE_NOT_OPEN",
+            Error::DeviceAlreadyOpen(_) => "DEVICE_ALREADY_OPEN",
+            Error::DeviceTypeMismatch { .. } => "DEVICE_TYPE_MISMATCH",
+            Error::DeviceDisconnected(_) => "DEVICE_DISCONNECTED",
+            Error::PermissionDenied(_) => "PERMISSION_DENIED",
+            Error::PcscUnavailable(_) => "PCSC_UNAVAILABLE",
+            Error::ReaderBusy(_) => "READER_BUSY",
+            Error::Timeout(_) => "TIMEOUT",
+            Error::TransportFailure(_) => "TRANSPORT_FAILURE",
+            Error::StatusWord { .. } => "APDU_STATUS_ERROR",
+            Error::CtapStatus(_) => "CTAP_STATUS_ERROR",
+            Error::CtapHid(_) => "CTAPHID_ERROR",
+            Error::PinAuthInvalid => "PIN_AUTH_INVALID",
+            Error::MalformedResponse(_) => "MALFORMED_RESPONSE",
+            Error::NotSupported(_) => "NOT_SUPPORTED",
+            Error::PinInvalid { .. } => "PIN_INVALID",
+            Error::PinBlocked => "PIN_BLOCKED",
+            Error::PinAuthBlocked => "PIN_AUTH_BLOCKED",
+            Error::PinRequired => "PIN_REQUIRED",
+            Error::PinNotSet => "PIN_NOT_SET",
+            Error::PinPolicyViolation(_) => "PIN_POLICY_VIOLATION",
+            Error::SecurityStatusNotSatisfied => "SECURITY_STATUS_NOT_SATISFIED",
+            Error::OperationDenied(_) => "OPERATION_DENIED",
+            Error::InvalidParams(_) => "INVALID_PARAMS",
+        }
+    }
+
+    /// Category of the error
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::DeviceNotFound(_)
+            | Error::DeviceNotOpen(_)
+            | Error::DeviceAlreadyOpen(_)
+            | Error::DeviceTypeMismatch { .. }
+            | Error::DeviceDisconnected(_)
+            | Error::PermissionDenied(_)
+            | Error::PcscUnavailable(_)
+            | Error::ReaderBusy(_)
+            | Error::Timeout(_)
+            | Error::TransportFailure(_) => ErrorCategory::Transport,
+            Error::StatusWord { .. }
+            | Error::CtapStatus(_)
+            | Error::CtapHid(_)
+            | Error::PinAuthInvalid
+            | Error::MalformedResponse(_)
+            | Error::NotSupported(_) => ErrorCategory::Protocol,
+            Error::PinInvalid { .. }
+            | Error::PinBlocked
+            | Error::PinAuthBlocked
+            | Error::PinRequired
+            | Error::PinNotSet
+            | Error::PinPolicyViolation(_)
+            | Error::SecurityStatusNotSatisfied
+            | Error::OperationDenied(_) => ErrorCategory::User,
+            Error::InvalidParams(_) => ErrorCategory::InvalidParams,
+        }
+    }
+
+    /// Whether repeating the same request may succeed
+    pub fn retryable(&self) -> bool {
+        match self {
+            Error::ReaderBusy(_) | Error::Timeout(_) | Error::DeviceDisconnected(_) => true,
+            Error::PcscUnavailable(_) | Error::TransportFailure(_) => true,
+            // Blocked PIN auth clears after the key is re-plugged
+            Error::PinAuthBlocked => true,
+            // A fresh PIN token usually verifies
+            Error::PinAuthInvalid => true,
+            Error::PinInvalid { retries } => retries.map(|r| r > 0).unwrap_or(true),
+            Error::OperationDenied(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Structured details for the error, if any
+    pub fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            Error::DeviceNotFound(device_id)
+            | Error::DeviceNotOpen(device_id)
+            | Error::DeviceAlreadyOpen(device_id) => {
+                Some(serde_json::json!({ "deviceId": device_id }))
+            }
+            Error::DeviceTypeMismatch {
+                device_id,
+                expected,
+            } => Some(serde_json::json!({
+                "deviceId": device_id,
+                "expected": expected
+            })),
+            Error::StatusWord { sw1, sw2 } => Some(serde_json::json!({
+                "sw1": sw1,
+                "sw2": sw2,
+                "sw": format!("{:02X}{:02X}", sw1, sw2)
+            })),
+            Error::CtapStatus(status) | Error::CtapHid(status) => Some(serde_json::json!({
+                "status": status,
+                "statusHex": format!("0x{:02X}", status)
+            })),
+            Error::PinInvalid { retries } => Some(serde_json::json!({ "retries": retries })),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DeviceNotFound(device_id) => write!(f, "Device {} not found", device_id),
+            Error::DeviceNotOpen(device_id) => write!(f, "Device {} is not open", device_id),
+            Error::DeviceAlreadyOpen(device_id) => {
+                write!(f, "Device {} is already open", device_id)
+            }
+            Error::DeviceTypeMismatch {
+                device_id,
+                expected,
+            } => {
+                write!(f, "Device {} is not a {} device", device_id, expected)
+            }
+            Error::DeviceDisconnected(message)
+            | Error::PermissionDenied(message)
+            | Error::PcscUnavailable(message)
+            | Error::ReaderBusy(message)
+            | Error::Timeout(message)
+            | Error::TransportFailure(message)
+            | Error::MalformedResponse(message)
+            | Error::NotSupported(message)
+            | Error::PinPolicyViolation(message)
+            | Error::OperationDenied(message)
+            | Error::InvalidParams(message) => write!(f, "{}", message),
+            Error::StatusWord { sw1, sw2 } => write!(f, "APDU error: {:02X} {:02X}", sw1, sw2),
+            Error::CtapStatus(status) => write!(f, "CTAP2 error: 0x{:02X}", status),
+            Error::CtapHid(code) => write!(f, "CTAPHID error: 0x{:02X}", code),
+            Error::PinAuthInvalid => write!(f, "PIN/UV auth parameter failed verification"),
+            Error::PinInvalid {
+                retries: Some(retries),
+            } => {
+                write!(f, "Incorrect PIN, {} retries remaining", retries)
+            }
+            Error::PinInvalid { retries: None } => write!(f, "Incorrect PIN"),
+            Error::PinBlocked => write!(f, "PIN is blocked"),
+            Error::PinAuthBlocked => {
+                write!(
+                    f,
+                    "PIN authentication blocked, re-insert the device to try again"
+                )
+            }
+            Error::PinRequired => write!(f, "PIN required"),
+            Error::PinNotSet => write!(f, "PIN is not set"),
+            Error::SecurityStatusNotSatisfied => write!(f, "Security status not satisfied"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Find the taxonomy error in an `anyhow` error chain
+pub fn classify(err: &anyhow::Error) -> Option<&Error> {
+    err.chain().find_map(|cause| cause.downcast_ref::<Error>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_status_word_mapping() {
+        assert_eq!(
+            Error::from_status_word(0x63, 0xC2),
+            Error::PinInvalid { retries: Some(2) }
+        );
+        assert_eq!(Error::from_status_word(0x69, 0x83), Error::PinBlocked);
+        assert_eq!(
+            Error::from_status_word(0x6A, 0x80),
+            Error::StatusWord {
+                sw1: 0x6A,
+                sw2: 0x80
+            }
+        );
+    }
+
+    #[test]
+    fn test_categories_and_retryable() {
+        assert_eq!(
+            Error::ReaderBusy("busy".to_string()).category(),
+            ErrorCategory::Transport
+        );
+        assert!(Error::ReaderBusy("busy".to_string()).retryable());
+        assert_eq!(Error::PinBlocked.category(), ErrorCategory::User);
+        assert!(!Error::PinBlocked.retryable());
+        assert!(!Error::PinInvalid { retries: Some(0) }.retryable());
+        assert!(!Error::InvalidParams("x".to_string()).retryable());
+        let unsupported = Error::NotSupported("NDEF Application not found".to_string());
+        assert_eq!(unsupported.category(), ErrorCategory::Protocol);
+        assert_eq!(unsupported.code(), "NOT_SUPPORTED");
+    }
+
+    #[test]
+    fn test_classify_through_context() {
+        let err: anyhow::Result<()> = Err(Error::PinNotSet.into());
+        let err = err.context("Failed to get PIN token").unwrap_err();
+        assert_eq!(classify(&err), Some(&Error::PinNotSet));
+
+        let plain = anyhow::anyhow!("unclassified");
+        assert!(classify(&plain).is_none());
+    }
+}
@@ -11,6 +11,7 @@ use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 use crate::device::DeviceManager;
+use crate::error::Error;
 use crate::transport;
 
 type Aes256CbcEnc = Encryptor<Aes256>;
@@ -39,6 +40,9 @@ const CTAP2_ERR_PIN_BLOCKED: u8 = 0x32;
 const CTAP2_ERR_PIN_AUTH_INVALID: u8 = 0x33;
 const CTAP2_ERR_PIN_AUTH_BLOCKED: u8 = 0x34;
 const CTAP2_ERR_PIN_NOT_SET: u8 = 0x35;
+const CTAP2_ERR_PIN_POLICY_VIOLATION: u8 = 0x37;
+const CTAP2_ERR_OPERATION_DENIED: u8 = 0x27;
+const CTAP2_ERR_USER_ACTION_TIMEOUT: u8 = 0x2F;
 
 /// Client PIN subcommands
 const PIN_GET_RETRIES: u8 = 0x01;
@@ -105,6 +109,26 @@ pub struct Credential {
     pub cred_protect: Option<u8>,
 }
 
+/// Map a CTAP2 status byte to the crate error taxonomy
+fn ctap_status_error(status: u8) -> Error {
+    match status {
+        CTAP2_ERR_PIN_INVALID => Error::PinInvalid { retries: None },
+        CTAP2_ERR_PIN_AUTH_INVALID => Error::PinAuthInvalid,
+        CTAP2_ERR_PIN_BLOCKED => Error::PinBlocked,
+        CTAP2_ERR_PIN_AUTH_BLOCKED => Error::PinAuthBlocked,
+        CTAP2_ERR_PIN_NOT_SET => Error::PinNotSet,
+        CTAP2_ERR_PIN_REQUIRED => Error::PinRequired,
+        CTAP2_ERR_PIN_POLICY_VIOLATION => {
+            Error::PinPolicyViolation("PIN does not satisfy the authenticator policy".to_string())
+        }
+        CTAP2_ERR_OPERATION_DENIED => {
+            Error::OperationDenied("Operation denied by user".to_string())
+        }
+        CTAP2_ERR_USER_ACTION_TIMEOUT => Error::Timeout("User action timed out".to_string()),
+        _ => Error::CtapStatus(status),
+    }
+}
+
 /// Initialize CTAPHID by getting a channel ID
 fn ctaphid_init(device_manager: &DeviceManager, device_id: &str) -> Result<[u8; 4]> {
     let mut init_packet = [0u8; 64];
@@ -130,7 +154,7 @@ fn ctaphid_init(device_manager: &DeviceManager, device_id: &str) -> Result<[u8;
                     &nonce,
                     &init_response[7..15]
                 );
-                return Err(anyhow!("INIT nonce mismatch"));
+                return Err(Error::MalformedResponse("INIT nonce mismatch".to_string()).into());
             }
 
             // Extract new CID (bytes 15-18)
@@ -144,7 +168,7 @@ fn ctaphid_init(device_manager: &DeviceManager, device_id: &str) -> Result<[u8;
             log::debug!("CTAPHID INIT successful, CID: {:02x?}", cid);
             Ok(cid)
         } else {
-            Err(anyhow!("Invalid INIT response"))
+            Err(Error::MalformedResponse("Invalid INIT response".to_string()).into())
         }
     })
 }
@@ -201,13 +225,13 @@ fn ctap2_command(
         // Parse response
         // Response format: [CID(4)] [CMD(1)] [BCNTH(1)] [BCNTL(1)] [DATA...]
         if response.len() < 7 {
-            return Err(anyhow!("Response too short"));
+            return Err(Error::MalformedResponse("Response too short".to_string()).into());
         }
 
         // Check if it's an error response
         if response[4] == CTAPHID_ERROR {
             let error_code = response[7];
-            return Err(anyhow!("CTAPHID error: 0x{:02X}", error_code));
+            return Err(Error::CtapHid(error_code).into());
         }
 
         // Check for keepalive
@@ -217,7 +241,10 @@ fn ctap2_command(
             // For now, just try to receive again
             let response = transport::receive_hid(device, 5000)?;
             if response.len() < 7 {
-                return Err(anyhow!("Response too short after keepalive"));
+                return Err(Error::MalformedResponse(
+                    "Response too short after keepalive".to_string(),
+                )
+                .into());
             }
         }
 
@@ -237,17 +264,24 @@ fn ctap2_command(
             let cont_response = transport::receive_hid(device, 5000)?;
 
             if cont_response.len() < 5 {
-                return Err(anyhow!("Continuation packet too short"));
+                return Err(
+                    Error::MalformedResponse("Continuation packet too short".to_string()).into(),
+                );
             }
 
             // Verify CID matches
             if &cont_response[0..4] != cid {
-                return Err(anyhow!("CID mismatch in continuation packet"));
+                return Err(Error::MalformedResponse(
+                    "CID mismatch in continuation packet".to_string(),
+                )
+                .into());
             }
 
             // Verify sequence number
             if cont_response[4] != expected_seq {
-                return Err(anyhow!("Sequence number mismatch"));
+                return Err(
+                    Error::MalformedResponse("Sequence number mismatch".to_string()).into(),
+                );
             }
 
             let chunk_len = std::cmp::min(data_len - received, 59);
@@ -258,12 +292,12 @@ fn ctap2_command(
 
         // Check CTAP2 status code
         if response_data.is_empty() {
-            return Err(anyhow!("Empty response"));
+            return Err(Error::MalformedResponse("Empty response".to_string()).into());
         }
 
         let status = response_data[0];
         if status != CTAP2_OK {
-            return Err(anyhow!("CTAP2 error: 0x{:02X}", status));
+            return Err(ctap_status_error(status).into());
         }
 
         // Return data after status byte
@@ -738,11 +772,15 @@ pub fn set_pin(device_manager: &DeviceManager, device_id: &str, new_pin: &str) -
     log::debug!("Setting PIN...");
 
     if new_pin.len() < 4 {
-        return Err(anyhow!("PIN must be at least 4 characters"));
+        return Err(
+            Error::PinPolicyViolation("PIN must be at least 4 characters".to_string()).into(),
+        );
     }
 
     if new_pin.len() > 63 {
-        return Err(anyhow!("PIN must be at most 63 characters"));
+        return Err(
+            Error::PinPolicyViolation("PIN must be at most 63 characters".to_string()).into(),
+        );
     }
 
     let cid = ctaphid_init(device_manager, device_id)?;
@@ -818,11 +856,15 @@ pub fn change_pin(
     log::debug!("Changing PIN...");
 
     if new_pin.len() < 4 {
-        return Err(anyhow!("PIN must be at least 4 characters"));
+        return Err(
+            Error::PinPolicyViolation("PIN must be at least 4 characters".to_string()).into(),
+        );
     }
 
     if new_pin.len() > 63 {
-        return Err(anyhow!("PIN must be at most 63 characters"));
+        return Err(
+            Error::PinPolicyViolation("PIN must be at most 63 characters".to_string()).into(),
+        );
     }
 
     let cid = ctaphid_init(device_manager, device_id)?;
@@ -1354,14 +1396,14 @@ pub fn delete_credential(
 
     let cid = ctaphid_init(device_manager, device_id)?;
 
-    let pin = pin.ok_or_else(|| anyhow!("PIN required for credential deletion"))?;
+    let pin = pin.ok_or(Error::PinRequired)?;
 
     // Get PIN token
     let pin_token = get_pin_token(device_manager, device_id, &cid, pin)?;
 
     // Decode credential ID from hex
-    let cred_id_bytes =
-        hex::decode(credential_id).map_err(|e| anyhow!("Invalid credential ID: {}", e))?;
+    let cred_id_bytes = hex::decode(credential_id)
+        .map_err(|e| Error::InvalidParams(format!("Invalid credential ID: {}", e)))?;
 
     // Build subCommandParams
     let cred_descriptor = vec![
@@ -1471,4 +1513,17 @@ mod tests {
         let json = serde_json::to_string(&info).unwrap();
         assert!(json.contains("FIDO_2_0"));
     }
+
+    #[test]
+    fn test_ctap_status_error() {
+        assert_eq!(
+            ctap_status_error(CTAP2_ERR_PIN_INVALID),
+            Error::PinInvalid { retries: None }
+        );
+        assert_eq!(
+            ctap_status_error(CTAP2_ERR_PIN_AUTH_INVALID),
+            Error::PinAuthInvalid
+        );
+        assert_eq!(ctap_status_error(0x7F), Error::CtapStatus(0x7F));
+    }
 }
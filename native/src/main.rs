@@ -1,11 +1,23 @@
 use serde::{Deserialize, Serialize};
 use std::io::{self, Read, Write};
+use zeroize::Zeroizing;
 
+use error::{Error, ErrorCategory};
+
+mod apdu;
 mod device;
+mod error;
 mod fido2;
+mod ndef;
+mod oath;
+mod openpgp;
+mod otp;
 mod piv;
+mod pkcs1;
 mod protocol;
+mod tlv;
 mod transport;
+mod x509;
 
 /// Request structure for JSON-RPC messages
 #[derive(Debug, Deserialize)]
@@ -32,7 +44,11 @@ struct Response {
 #[derive(Debug, Serialize)]
 struct ErrorInfo {
     code: String,
+    category: ErrorCategory,
+    retryable: bool,
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<serde_json::Value>,
 }
 
 impl Response {
@@ -45,17 +61,77 @@ impl Response {
         }
     }
 
+    /// Build an intermediate progress message for a long-running request
+    fn progress(id: u32, result: serde_json::Value) -> Self {
+        Response {
+            id,
+            status: "progress".to_string(),
+            result: Some(result),
+            error: None,
+        }
+    }
+
     fn error(id: u32, code: &str, message: &str) -> Self {
+        Self::error_with(id, code, ErrorCategory::Internal, false, message, None)
+    }
+
+    fn error_with(
+        id: u32,
+        code: &str,
+        category: ErrorCategory,
+        retryable: bool,
+        message: &str,
+        details: Option<serde_json::Value>,
+    ) -> Self {
         Response {
             id,
             status: "error".to_string(),
             result: None,
             error: Some(ErrorInfo {
                 code: code.to_string(),
+                category,
+                retryable,
                 message: message.to_string(),
+                details,
             }),
         }
     }
+
+    /// Build an error response from a classified error
+    fn from_error(id: u32, error: &Error) -> Self {
+        Self::error_with(
+            id,
+            error.code(),
+            error.category(),
+            error.retryable(),
+            &error.to_string(),
+            error.details(),
+        )
+    }
+
+    /// Build an error response for a missing or malformed parameter
+    fn invalid_params(id: u32, message: &str) -> Self {
+        Self::from_error(id, &Error::InvalidParams(message.to_string()))
+    }
+
+    /// Build an error response for a failed operation
+    ///
+    /// Uses the taxonomy code when the error chain carries one, otherwise
+    /// falls back to the operation-specific code.
+    fn failure(id: u32, fallback_code: &str, context: &str, err: &anyhow::Error) -> Self {
+        let message = format!("{}: {}", context, err);
+        match error::classify(err) {
+            Some(classified) => Self::error_with(
+                id,
+                classified.code(),
+                classified.category(),
+                classified.retryable(),
+                &message,
+                classified.details(),
+            ),
+            None => Self::error(id, fallback_code, &message),
+        }
+    }
 }
 
 /// Read a message length (4 bytes, native endian)
@@ -114,10 +190,11 @@ fn handle_list_devices(id: u32) -> Response {
                 "devices": devices
             }),
         ),
-        Err(e) => Response::error(
+        Err(e) => Response::failure(
             id,
             "DEVICE_ENUMERATION_FAILED",
-            &format!("Failed to enumerate devices: {}", e),
+            "Failed to enumerate devices",
+            &e,
         ),
     }
 }
@@ -133,7 +210,7 @@ fn handle_open_device(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
@@ -145,11 +222,7 @@ fn handle_open_device(
                 "deviceId": device_id
             }),
         ),
-        Err(e) => Response::error(
-            id,
-            "DEVICE_OPEN_FAILED",
-            &format!("Failed to open device: {}", e),
-        ),
+        Err(e) => Response::failure(id, "DEVICE_OPEN_FAILED", "Failed to open device", &e),
     }
 }
 
@@ -164,7 +237,7 @@ fn handle_close_device(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
@@ -176,11 +249,7 @@ fn handle_close_device(
                 "deviceId": device_id
             }),
         ),
-        Err(e) => Response::error(
-            id,
-            "DEVICE_CLOSE_FAILED",
-            &format!("Failed to close device: {}", e),
-        ),
+        Err(e) => Response::failure(id, "DEVICE_CLOSE_FAILED", "Failed to close device", &e),
     }
 }
 
@@ -195,14 +264,14 @@ fn handle_send_hid(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
     let data_array = match params.get("data").and_then(|v| v.as_array()) {
         Some(arr) => arr,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing or invalid data parameter");
+            return Response::invalid_params(id, "Missing or invalid data parameter");
         }
     };
 
@@ -213,11 +282,7 @@ fn handle_send_hid(
         .collect();
 
     if data_bytes.len() != data_array.len() {
-        return Response::error(
-            id,
-            "INVALID_PARAMS",
-            "Data array contains invalid byte values",
-        );
+        return Response::invalid_params(id, "Data array contains invalid byte values");
     }
 
     // Execute HID send with the device
@@ -231,11 +296,7 @@ fn handle_send_hid(
                 "bytesSent": bytes_sent
             }),
         ),
-        Err(e) => Response::error(
-            id,
-            "HID_SEND_FAILED",
-            &format!("Failed to send HID packet: {}", e),
-        ),
+        Err(e) => Response::failure(id, "HID_SEND_FAILED", "Failed to send HID packet", &e),
     }
 }
 
@@ -250,7 +311,7 @@ fn handle_receive_hid(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
@@ -270,11 +331,7 @@ fn handle_receive_hid(
                 "data": data
             }),
         ),
-        Err(e) => Response::error(
-            id,
-            "HID_RECEIVE_FAILED",
-            &format!("Failed to receive HID packet: {}", e),
-        ),
+        Err(e) => Response::failure(id, "HID_RECEIVE_FAILED", "Failed to receive HID packet", &e),
     }
 }
 
@@ -289,14 +346,14 @@ fn handle_transmit_apdu(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
     let apdu_array = match params.get("apdu").and_then(|v| v.as_array()) {
         Some(arr) => arr,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing or invalid apdu parameter");
+            return Response::invalid_params(id, "Missing or invalid apdu parameter");
         }
     };
 
@@ -307,11 +364,7 @@ fn handle_transmit_apdu(
         .collect();
 
     if apdu_bytes.len() != apdu_array.len() {
-        return Response::error(
-            id,
-            "INVALID_PARAMS",
-            "APDU array contains invalid byte values",
-        );
+        return Response::invalid_params(id, "APDU array contains invalid byte values");
     }
 
     // Execute APDU transmit with the device
@@ -325,11 +378,7 @@ fn handle_transmit_apdu(
                 "response": response
             }),
         ),
-        Err(e) => Response::error(
-            id,
-            "APDU_TRANSMIT_FAILED",
-            &format!("Failed to transmit APDU: {}", e),
-        ),
+        Err(e) => Response::failure(id, "APDU_TRANSMIT_FAILED", "Failed to transmit APDU", &e),
     }
 }
 
@@ -344,7 +393,7 @@ fn handle_detect_protocols(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
@@ -357,10 +406,11 @@ fn handle_detect_protocols(
                 "protocols": support
             }),
         ),
-        Err(e) => Response::error(
+        Err(e) => Response::failure(
             id,
             "PROTOCOL_DETECTION_FAILED",
-            &format!("Failed to detect protocols: {}", e),
+            "Failed to detect protocols",
+            &e,
         ),
     }
 }
@@ -376,7 +426,7 @@ fn handle_fido2_get_info(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
@@ -388,11 +438,7 @@ fn handle_fido2_get_info(
                 "info": info
             }),
         ),
-        Err(e) => Response::error(
-            id,
-            "FIDO2_GET_INFO_FAILED",
-            &format!("Failed to get FIDO2 info: {}", e),
-        ),
+        Err(e) => Response::failure(id, "FIDO2_GET_INFO_FAILED", "Failed to get FIDO2 info", &e),
     }
 }
 
@@ -407,7 +453,7 @@ fn handle_fido2_get_pin_retries(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
@@ -419,10 +465,11 @@ fn handle_fido2_get_pin_retries(
                 "retries": retries
             }),
         ),
-        Err(e) => Response::error(
+        Err(e) => Response::failure(
             id,
             "FIDO2_GET_PIN_RETRIES_FAILED",
-            &format!("Failed to get PIN retries: {}", e),
+            "Failed to get PIN retries",
+            &e,
         ),
     }
 }
@@ -438,14 +485,14 @@ fn handle_fido2_set_pin(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
     let new_pin = match params.get("newPin").and_then(|v| v.as_str()) {
         Some(pin) => pin,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing newPin parameter");
+            return Response::invalid_params(id, "Missing newPin parameter");
         }
     };
 
@@ -457,11 +504,7 @@ fn handle_fido2_set_pin(
                 "message": "PIN set successfully"
             }),
         ),
-        Err(e) => Response::error(
-            id,
-            "FIDO2_SET_PIN_FAILED",
-            &format!("Failed to set PIN: {}", e),
-        ),
+        Err(e) => Response::failure(id, "FIDO2_SET_PIN_FAILED", "Failed to set PIN", &e),
     }
 }
 
@@ -476,21 +519,21 @@ fn handle_fido2_change_pin(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
     let current_pin = match params.get("currentPin").and_then(|v| v.as_str()) {
         Some(pin) => pin,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing currentPin parameter");
+            return Response::invalid_params(id, "Missing currentPin parameter");
         }
     };
 
     let new_pin = match params.get("newPin").and_then(|v| v.as_str()) {
         Some(pin) => pin,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing newPin parameter");
+            return Response::invalid_params(id, "Missing newPin parameter");
         }
     };
 
@@ -502,11 +545,7 @@ fn handle_fido2_change_pin(
                 "message": "PIN changed successfully"
             }),
         ),
-        Err(e) => Response::error(
-            id,
-            "FIDO2_CHANGE_PIN_FAILED",
-            &format!("Failed to change PIN: {}", e),
-        ),
+        Err(e) => Response::failure(id, "FIDO2_CHANGE_PIN_FAILED", "Failed to change PIN", &e),
     }
 }
 
@@ -521,7 +560,7 @@ fn handle_fido2_list_credentials(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
@@ -536,10 +575,11 @@ fn handle_fido2_list_credentials(
                 "credentials": credentials
             }),
         ),
-        Err(e) => Response::error(
+        Err(e) => Response::failure(
             id,
             "FIDO2_LIST_CREDENTIALS_FAILED",
-            &format!("Failed to list credentials: {}", e),
+            "Failed to list credentials",
+            &e,
         ),
     }
 }
@@ -555,14 +595,14 @@ fn handle_fido2_delete_credential(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
     let credential_id = match params.get("credentialId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing credentialId parameter");
+            return Response::invalid_params(id, "Missing credentialId parameter");
         }
     };
 
@@ -577,10 +617,11 @@ fn handle_fido2_delete_credential(
                 "message": "Credential deleted successfully"
             }),
         ),
-        Err(e) => Response::error(
+        Err(e) => Response::failure(
             id,
             "FIDO2_DELETE_CREDENTIAL_FAILED",
-            &format!("Failed to delete credential: {}", e),
+            "Failed to delete credential",
+            &e,
         ),
     }
 }
@@ -596,7 +637,7 @@ fn handle_fido2_reset_device(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
@@ -608,10 +649,11 @@ fn handle_fido2_reset_device(
                 "message": "Device reset successfully"
             }),
         ),
-        Err(e) => Response::error(
+        Err(e) => Response::failure(
             id,
             "FIDO2_RESET_DEVICE_FAILED",
-            &format!("Failed to reset device: {}", e),
+            "Failed to reset device",
+            &e,
         ),
     }
 }
@@ -627,12 +669,17 @@ fn handle_piv_get_data(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
+    let deep_scan = params
+        .get("deepScan")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
     // Send command regardless of device type
-    match piv::get_piv_data(device_manager, device_id) {
+    match piv::get_piv_data(device_manager, device_id, deep_scan) {
         Ok(result) => Response::success(
             id,
             serde_json::json!({
@@ -641,11 +688,7 @@ fn handle_piv_get_data(
                 "activityLog": result.activity_log
             }),
         ),
-        Err(e) => Response::error(
-            id,
-            "PIV_GET_DATA_FAILED",
-            &format!("Failed to get PIV data: {}", e),
-        ),
+        Err(e) => Response::failure(id, "PIV_GET_DATA_FAILED", "Failed to get PIV data", &e),
     }
 }
 
@@ -660,7 +703,7 @@ fn handle_piv_select(
     let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
         Some(id) => id,
         None => {
-            return Response::error(id, "INVALID_PARAMS", "Missing deviceId parameter");
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
     };
 
@@ -671,27 +714,26 @@ fn handle_piv_select(
             match device {
                 Some(d) => {
                     if d.device_type != device::DeviceType::Ccid {
-                        return Response::error(
+                        return Response::from_error(
                             id,
-                            "DEVICE_TYPE_MISMATCH",
-                            "PIV operations require a CCID device. The specified device is not a CCID device."
+                            &Error::DeviceTypeMismatch {
+                                device_id: device_id.to_string(),
+                                expected: "CCID",
+                            },
                         );
                     }
                 }
                 None => {
-                    return Response::error(
-                        id,
-                        "DEVICE_NOT_FOUND",
-                        &format!("Device with ID {} not found", device_id)
-                    );
+                    return Response::from_error(id, &Error::DeviceNotFound(device_id.to_string()));
                 }
             }
         }
         Err(e) => {
-            return Response::error(
+            return Response::failure(
                 id,
                 "DEVICE_ENUMERATION_FAILED",
-                &format!("Failed to enumerate devices: {}", e)
+                "Failed to enumerate devices",
+                &e,
             );
         }
     }
@@ -704,173 +746,2925 @@ fn handle_piv_select(
                 "selected": selected
             }),
         ),
-        Err(e) => Response::error(
+        Err(e) => Response::failure(
             id,
             "PIV_SELECT_FAILED",
-            &format!("Failed to select PIV application: {}", e),
+            "Failed to select PIV application",
+            &e,
         ),
     }
 }
 
-/// Process a single request
-fn process_request(request: Request, device_manager: &device::DeviceManager) -> Response {
-    log::info!(
-        "Processing command: {} (id: {})",
-        request.command,
-        request.id
-    );
+/// Handle a pivGetPinRetries command
+fn handle_piv_get_pin_retries(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivGetPinRetries command");
 
-    match request.command.as_str() {
-        "ping" => handle_ping(request.id),
-        "getVersion" => handle_get_version(request.id),
-        "listDevices" => handle_list_devices(request.id),
-        "openDevice" => handle_open_device(request.id, &request.params, device_manager),
-        "closeDevice" => handle_close_device(request.id, &request.params, device_manager),
-        "sendHid" => handle_send_hid(request.id, &request.params, device_manager),
-        "receiveHid" => handle_receive_hid(request.id, &request.params, device_manager),
-        "transmitApdu" => handle_transmit_apdu(request.id, &request.params, device_manager),
-        "detectProtocols" => handle_detect_protocols(request.id, &request.params, device_manager),
-        "fido2GetInfo" => handle_fido2_get_info(request.id, &request.params, device_manager),
-        "fido2GetPinRetries" => {
-            handle_fido2_get_pin_retries(request.id, &request.params, device_manager)
-        }
-        "fido2SetPin" => handle_fido2_set_pin(request.id, &request.params, device_manager),
-        "fido2ChangePin" => handle_fido2_change_pin(request.id, &request.params, device_manager),
-        "fido2ListCredentials" => {
-            handle_fido2_list_credentials(request.id, &request.params, device_manager)
-        }
-        "fido2DeleteCredential" => {
-            handle_fido2_delete_credential(request.id, &request.params, device_manager)
-        }
-        "fido2ResetDevice" => {
-            handle_fido2_reset_device(request.id, &request.params, device_manager)
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
-        "pivGetData" => handle_piv_get_data(request.id, &request.params, device_manager),
-        "pivSelect" => handle_piv_select(request.id, &request.params, device_manager),
-        _ => Response::error(
-            request.id,
-            "UNKNOWN_COMMAND",
-            &format!("Unknown command: {}", request.command),
+    };
+
+    match piv::get_pin_retries(device_manager, device_id) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "pinStatus": result.status,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "PIV_GET_PIN_RETRIES_FAILED",
+            "Failed to get PIV PIN retries",
+            &e,
         ),
     }
 }
 
-fn main() -> io::Result<()> {
-    // Initialize logger
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-        .target(env_logger::Target::Stderr)
-        .init();
-
-    log::info!("Feitian SK Manager Native Host starting...");
-    log::info!("Version: {}", env!("CARGO_PKG_VERSION"));
+/// Handle a pivVerifyPin command
+fn handle_piv_verify_pin(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivVerifyPin command");
 
-    // Initialize device manager
-    let device_manager = match device::DeviceManager::new() {
-        Ok(manager) => {
-            log::info!("Device manager initialized successfully");
-            manager
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
-        Err(e) => {
-            log::error!("Failed to initialize device manager: {}", e);
-            log::error!("The native host will still run, but device operations may fail");
-            // Continue anyway - some commands like ping and getVersion will still work
-            device::DeviceManager::new().unwrap_or_else(|_| {
-                panic!("Critical: Could not initialize device manager");
-            })
+    };
+
+    let pin = match params.get("pin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing pin parameter");
         }
     };
 
-    // Main message loop
-    loop {
-        // Read message length
-        let length = match read_message_length() {
-            Ok(len) => len,
-            Err(e) => {
-                if e.kind() == io::ErrorKind::UnexpectedEof {
-                    log::info!("Connection closed by client");
-                    break;
-                }
-                log::error!("Failed to read message length: {}", e);
-                continue;
-            }
-        };
+    match piv::verify_pin(device_manager, device_id, pin) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "pinStatus": result.status,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "PIV_VERIFY_PIN_FAILED", "Failed to verify PIV PIN", &e),
+    }
+}
 
-        // Validate message length
-        if length == 0 || length > 1024 * 1024 {
-            log::error!("Invalid message length: {}", length);
-            continue;
+/// Handle a pivChangePin command
+fn handle_piv_change_pin(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivChangePin command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
+    };
 
-        // Read message content
-        let message = match read_message(length) {
-            Ok(msg) => msg,
-            Err(e) => {
-                log::error!("Failed to read message: {}", e);
-                continue;
-            }
-        };
+    let current_pin = match params.get("currentPin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing currentPin parameter");
+        }
+    };
 
-        log::debug!("Received message: {}", message);
+    let new_pin = match params.get("newPin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing newPin parameter");
+        }
+    };
 
-        // Parse request
-        let request: Request = match serde_json::from_str(&message) {
-            Ok(req) => req,
-            Err(e) => {
-                log::error!("Failed to parse request: {}", e);
-                // Send error response with id 0 if we can't parse the request
-                let error_response = Response::error(0, "INVALID_JSON", &e.to_string());
-                if let Ok(json) = serde_json::to_string(&error_response) {
-                    let _ = write_message(&json);
-                }
-                continue;
-            }
-        };
+    match piv::change_pin(device_manager, device_id, current_pin, new_pin) {
+        Ok(activity_log) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "PIN changed successfully",
+                "activityLog": activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "PIV_CHANGE_PIN_FAILED", "Failed to change PIV PIN", &e),
+    }
+}
 
-        // Process request with device manager
-        let response = process_request(request, &device_manager);
+/// Handle a pivChangePuk command
+fn handle_piv_change_puk(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivChangePuk command");
 
-        // Send response
-        match serde_json::to_string(&response) {
-            Ok(json) => {
-                log::debug!("Sending response: {}", json);
-                if let Err(e) = write_message(&json) {
-                    log::error!("Failed to send response: {}", e);
-                    break;
-                }
-            }
-            Err(e) => {
-                log::error!("Failed to serialize response: {}", e);
-            }
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
-    }
+    };
 
-    log::info!("Native host shutting down");
-    Ok(())
-}
+    let current_puk = match params.get("currentPuk").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing currentPuk parameter");
+        }
+    };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    let new_puk = match params.get("newPuk").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing newPuk parameter");
+        }
+    };
 
-    #[test]
-    fn test_ping_command() {
-        let response = handle_ping(1);
-        assert_eq!(response.status, "ok");
-        assert!(response.result.is_some());
-        assert!(response.error.is_none());
+    match piv::change_puk(device_manager, device_id, current_puk, new_puk) {
+        Ok(activity_log) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "PUK changed successfully",
+                "activityLog": activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "PIV_CHANGE_PUK_FAILED", "Failed to change PIV PUK", &e),
     }
+}
 
-    #[test]
-    fn test_get_version_command() {
-        let response = handle_get_version(2);
-        assert_eq!(response.status, "ok");
-        assert!(response.result.is_some());
+/// Handle a pivUnblockPin command
+fn handle_piv_unblock_pin(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivUnblockPin command");
 
-        if let Some(result) = response.result {
-            assert!(result.get("version").is_some());
-            assert!(result.get("name").is_some());
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
         }
-    }
+    };
+
+    let puk = match params.get("puk").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing puk parameter");
+        }
+    };
+
+    let new_pin = match params.get("newPin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing newPin parameter");
+        }
+    };
+
+    match piv::unblock_pin(device_manager, device_id, puk, new_pin) {
+        Ok(activity_log) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "PIN unblocked successfully",
+                "activityLog": activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "PIV_UNBLOCK_PIN_FAILED",
+            "Failed to unblock PIV PIN",
+            &e,
+        ),
+    }
+}
+
+/// Parse an optional management key algorithm parameter
+fn parse_management_key_algorithm(
+    params: &serde_json::Value,
+) -> Result<Option<piv::ManagementKeyAlgorithm>, String> {
+    match params.get("algorithm").and_then(|v| v.as_str()) {
+        Some(name) => piv::ManagementKeyAlgorithm::from_name(name)
+            .map(Some)
+            .ok_or_else(|| format!("Unsupported management key algorithm: {}", name)),
+        None => Ok(None),
+    }
+}
+
+/// Handle a pivAuthenticate command
+fn handle_piv_authenticate(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivAuthenticate command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let management_key = match params.get("managementKey").and_then(|v| v.as_str()) {
+        Some(key) => key,
+        None => {
+            return Response::invalid_params(id, "Missing managementKey parameter");
+        }
+    };
+
+    let algorithm = match parse_management_key_algorithm(params) {
+        Ok(algorithm) => algorithm,
+        Err(message) => return Response::invalid_params(id, &message),
+    };
+
+    match piv::authenticate(device_manager, device_id, management_key, algorithm) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "authenticated": result.authenticated,
+                "algorithm": result.algorithm,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "PIV_AUTHENTICATE_FAILED",
+            "Failed to authenticate management key",
+            &e,
+        ),
+    }
+}
+
+/// Handle a pivChangeManagementKey command
+fn handle_piv_change_management_key(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivChangeManagementKey command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let new_key = match params.get("newManagementKey").and_then(|v| v.as_str()) {
+        Some(key) => key,
+        None => {
+            return Response::invalid_params(id, "Missing newManagementKey parameter");
+        }
+    };
+
+    // Current key is optional when the session is already authenticated
+    let current_key = params.get("currentManagementKey").and_then(|v| v.as_str());
+
+    let algorithm = match parse_management_key_algorithm(params) {
+        Ok(algorithm) => algorithm,
+        Err(message) => return Response::invalid_params(id, &message),
+    };
+
+    let variant = match params.get("variant").and_then(|v| v.as_str()) {
+        Some("yubico") => Some(piv::ManagementKeyVariant::Yubico),
+        Some("feitian") => Some(piv::ManagementKeyVariant::Feitian),
+        Some(other) => {
+            return Response::invalid_params(id, &format!("Unknown variant: {}", other));
+        }
+        None => None,
+    };
+
+    let require_touch = params
+        .get("requireTouch")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    match piv::change_management_key(
+        device_manager,
+        device_id,
+        current_key,
+        new_key,
+        algorithm,
+        variant,
+        require_touch,
+    ) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "Management key changed successfully",
+                "algorithm": result.algorithm,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "PIV_CHANGE_MANAGEMENT_KEY_FAILED",
+            "Failed to change management key",
+            &e,
+        ),
+    }
+}
+
+/// Handle a pivGenerateKey command
+fn handle_piv_generate_key(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivGenerateKey command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match params.get("slot").and_then(|v| v.as_str()) {
+        Some(slot) => slot,
+        None => {
+            return Response::invalid_params(id, "Missing slot parameter");
+        }
+    };
+
+    let algorithm = match params.get("algorithm").and_then(|v| v.as_str()) {
+        Some(name) => match piv::PivKeyAlgorithm::from_name(name) {
+            Some(algorithm) => algorithm,
+            None => {
+                return Response::invalid_params(id, &format!("Unknown algorithm: {}", name));
+            }
+        },
+        None => {
+            return Response::invalid_params(id, "Missing algorithm parameter");
+        }
+    };
+
+    let pin_policy = match params.get("pinPolicy").and_then(|v| v.as_str()) {
+        Some(name) => match piv::PinPolicy::from_name(name) {
+            Some(policy) => policy,
+            None => {
+                return Response::invalid_params(id, &format!("Unknown PIN policy: {}", name));
+            }
+        },
+        None => piv::PinPolicy::Default,
+    };
+
+    let touch_policy = match params.get("touchPolicy").and_then(|v| v.as_str()) {
+        Some(name) => match piv::TouchPolicy::from_name(name) {
+            Some(policy) => policy,
+            None => {
+                return Response::invalid_params(id, &format!("Unknown touch policy: {}", name));
+            }
+        },
+        None => piv::TouchPolicy::Default,
+    };
+
+    let management_key = params.get("managementKey").and_then(|v| v.as_str());
+
+    match piv::generate_key(
+        device_manager,
+        device_id,
+        slot,
+        algorithm,
+        pin_policy,
+        touch_policy,
+        management_key,
+    ) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "slot": result.slot,
+                "algorithm": result.algorithm,
+                "pinPolicy": result.pin_policy,
+                "touchPolicy": result.touch_policy,
+                "publicKeyPem": result.public_key_pem,
+                "publicKeyDer": result.public_key_der,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "PIV_GENERATE_KEY_FAILED", "Failed to generate key", &e),
+    }
+}
+
+/// Handle a pivImportCertificate command
+fn handle_piv_import_certificate(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivImportCertificate command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match params.get("slot").and_then(|v| v.as_str()) {
+        Some(slot) => slot,
+        None => {
+            return Response::invalid_params(id, "Missing slot parameter");
+        }
+    };
+
+    // PEM text or hex-encoded DER
+    let certificate = match params.get("certificate").and_then(|v| v.as_str()) {
+        Some(certificate) => certificate,
+        None => {
+            return Response::invalid_params(id, "Missing certificate parameter");
+        }
+    };
+
+    let compress = params
+        .get("compress")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let management_key = params.get("managementKey").and_then(|v| v.as_str());
+
+    match piv::import_certificate(
+        device_manager,
+        device_id,
+        slot,
+        certificate,
+        compress,
+        management_key,
+    ) {
+        Ok(activity_log) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "Certificate imported successfully",
+                "activityLog": activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "PIV_IMPORT_CERTIFICATE_FAILED",
+            "Failed to import certificate",
+            &e,
+        ),
+    }
+}
+
+/// Handle a pivExportCertificate command
+fn handle_piv_export_certificate(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivExportCertificate command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match params.get("slot").and_then(|v| v.as_str()) {
+        Some(slot) => slot,
+        None => {
+            return Response::invalid_params(id, "Missing slot parameter");
+        }
+    };
+
+    let format = match params.get("format").and_then(|v| v.as_str()) {
+        Some("pem") | None => piv::CertificateFormat::Pem,
+        Some("der") => piv::CertificateFormat::Der,
+        Some(other) => {
+            return Response::invalid_params(id, &format!("Unknown format: {}", other));
+        }
+    };
+
+    match piv::export_certificate(device_manager, device_id, slot, format) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "slot": result.slot,
+                "present": result.present,
+                "format": result.format,
+                "certificate": result.certificate,
+                "compressed": result.compressed,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "PIV_EXPORT_CERTIFICATE_FAILED",
+            "Failed to export certificate",
+            &e,
+        ),
+    }
+}
+
+/// Handle a pivDeleteCertificate command
+fn handle_piv_delete_certificate(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivDeleteCertificate command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match params.get("slot").and_then(|v| v.as_str()) {
+        Some(slot) => slot,
+        None => {
+            return Response::invalid_params(id, "Missing slot parameter");
+        }
+    };
+
+    let management_key = params.get("managementKey").and_then(|v| v.as_str());
+
+    match piv::delete_certificate(device_manager, device_id, slot, management_key) {
+        Ok(activity_log) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "Certificate deleted successfully",
+                "activityLog": activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "PIV_DELETE_CERTIFICATE_FAILED",
+            "Failed to delete certificate",
+            &e,
+        ),
+    }
+}
+
+/// Parse an optional PIV key algorithm parameter
+fn parse_key_algorithm(params: &serde_json::Value) -> Result<Option<piv::PivKeyAlgorithm>, String> {
+    match params.get("algorithm").and_then(|v| v.as_str()) {
+        Some(name) => piv::PivKeyAlgorithm::from_name(name)
+            .map(Some)
+            .ok_or_else(|| format!("Unknown algorithm: {}", name)),
+        None => Ok(None),
+    }
+}
+
+/// Parse a required hex-encoded parameter
+fn parse_hex_param(params: &serde_json::Value, name: &str) -> Result<Vec<u8>, String> {
+    match params.get(name).and_then(|v| v.as_str()) {
+        Some(value) => {
+            let cleaned: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+            hex::decode(cleaned).map_err(|e| format!("Invalid {} parameter: {}", name, e))
+        }
+        None => Err(format!("Missing {} parameter", name)),
+    }
+}
+
+/// Handle a pivSign command
+fn handle_piv_sign(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivSign command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match params.get("slot").and_then(|v| v.as_str()) {
+        Some(slot) => slot,
+        None => {
+            return Response::invalid_params(id, "Missing slot parameter");
+        }
+    };
+
+    let data = match parse_hex_param(params, "data") {
+        Ok(data) => data,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let algorithm = match parse_key_algorithm(params) {
+        Ok(algorithm) => algorithm,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let hash = match params.get("hash").and_then(|v| v.as_str()) {
+        Some(name) => match pkcs1::HashAlgorithm::from_name(name) {
+            Some(hash) => hash,
+            None => {
+                return Response::invalid_params(id, &format!("Unknown hash: {}", name));
+            }
+        },
+        None => pkcs1::HashAlgorithm::default(),
+    };
+
+    let padding = match params.get("padding").and_then(|v| v.as_str()) {
+        Some("pkcs1") | None => pkcs1::RsaPadding::Pkcs1,
+        Some("pss") => pkcs1::RsaPadding::Pss,
+        Some(other) => {
+            return Response::invalid_params(id, &format!("Unknown padding: {}", other));
+        }
+    };
+
+    let options = piv::SignOptions {
+        hash,
+        padding,
+        prehashed: params
+            .get("prehashed")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+    };
+    let pin = params.get("pin").and_then(|v| v.as_str());
+
+    match piv::sign(
+        device_manager,
+        device_id,
+        slot,
+        algorithm,
+        &data,
+        options,
+        pin,
+    ) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "slot": result.slot,
+                "algorithm": result.algorithm,
+                "signature": result.output,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "PIV_SIGN_FAILED", "Failed to sign data", &e),
+    }
+}
+
+/// Handle a pivDecrypt command
+fn handle_piv_decrypt(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivDecrypt command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match params.get("slot").and_then(|v| v.as_str()) {
+        Some(slot) => slot,
+        None => {
+            return Response::invalid_params(id, "Missing slot parameter");
+        }
+    };
+
+    let ciphertext = match parse_hex_param(params, "ciphertext") {
+        Ok(ciphertext) => ciphertext,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let algorithm = match parse_key_algorithm(params) {
+        Ok(algorithm) => algorithm,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let unpad = params
+        .get("unpad")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    let pin = params.get("pin").and_then(|v| v.as_str());
+
+    match piv::decrypt(
+        device_manager,
+        device_id,
+        slot,
+        algorithm,
+        &ciphertext,
+        unpad,
+        pin,
+    ) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "slot": result.slot,
+                "algorithm": result.algorithm,
+                "plaintext": result.output,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "PIV_DECRYPT_FAILED", "Failed to decrypt data", &e),
+    }
+}
+
+/// Handle a pivEcdh command
+fn handle_piv_ecdh(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivEcdh command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match params.get("slot").and_then(|v| v.as_str()) {
+        Some(slot) => slot,
+        None => {
+            return Response::invalid_params(id, "Missing slot parameter");
+        }
+    };
+
+    let peer_public_key = match parse_hex_param(params, "peerPublicKey") {
+        Ok(key) => key,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let algorithm = match parse_key_algorithm(params) {
+        Ok(algorithm) => algorithm,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let pin = params.get("pin").and_then(|v| v.as_str());
+
+    match piv::ecdh(
+        device_manager,
+        device_id,
+        slot,
+        algorithm,
+        &peer_public_key,
+        pin,
+    ) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "slot": result.slot,
+                "algorithm": result.algorithm,
+                "sharedSecret": result.output,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "PIV_ECDH_FAILED", "Failed to derive shared secret", &e),
+    }
+}
+
+/// Handle a pivAttestSlot command
+fn handle_piv_attest_slot(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivAttestSlot command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match params.get("slot").and_then(|v| v.as_str()) {
+        Some(slot) => slot,
+        None => {
+            return Response::invalid_params(id, "Missing slot parameter");
+        }
+    };
+
+    let trust_anchor = params.get("trustAnchor").and_then(|v| v.as_str());
+
+    match piv::attest_slot(device_manager, device_id, slot, trust_anchor) {
+        Ok(attestation) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "slot": attestation.slot,
+                "generatedOnCard": attestation.generated_on_card,
+                "chainVerified": attestation.chain_verified,
+                "anchorVerified": attestation.anchor_verified,
+                "verificationError": attestation.verification_error,
+                "matchesSlotCertificate": attestation.matches_slot_certificate,
+                "firmwareVersion": attestation.properties.firmware_version,
+                "serialNumber": attestation.properties.serial_number,
+                "pinPolicy": attestation.properties.pin_policy,
+                "touchPolicy": attestation.properties.touch_policy,
+                "formFactor": attestation.properties.form_factor,
+                "fips": attestation.properties.fips,
+                "cspn": attestation.properties.cspn,
+                "publicKeyPem": attestation.public_key_pem,
+                "attestationCertificate": attestation.attestation_certificate,
+                "intermediateCertificate": attestation.intermediate_certificate,
+                "intermediateSubject": attestation.intermediate_subject,
+                "activityLog": attestation.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "PIV_ATTEST_FAILED", "Failed to attest slot", &e),
+    }
+}
+
+/// Parse the optional subjectAltNames parameter ({upn, email, dns} string arrays)
+fn parse_subject_alt_names(params: &serde_json::Value) -> Result<x509::SubjectAltNames, String> {
+    let names = match params.get("subjectAltNames") {
+        Some(names) => names,
+        None => return Ok(x509::SubjectAltNames::default()),
+    };
+    let list = |key: &str| -> Result<Vec<String>, String> {
+        match names.get(key) {
+            Some(values) => serde_json::from_value(values.clone())
+                .map_err(|_| format!("subjectAltNames.{} must be a list of strings", key)),
+            None => Ok(Vec::new()),
+        }
+    };
+    Ok(x509::SubjectAltNames {
+        upn: list("upn")?,
+        email: list("email")?,
+        dns: list("dns")?,
+    })
+}
+
+/// Handle a pivGenerateCsr command
+fn handle_piv_generate_csr(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivGenerateCsr command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match params.get("slot").and_then(|v| v.as_str()) {
+        Some(slot) => slot,
+        None => {
+            return Response::invalid_params(id, "Missing slot parameter");
+        }
+    };
+
+    let subject = match params.get("subject").and_then(|v| v.as_str()) {
+        Some(subject) => subject,
+        None => {
+            return Response::invalid_params(id, "Missing subject parameter");
+        }
+    };
+
+    let alt_names = match parse_subject_alt_names(params) {
+        Ok(names) => names,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let algorithm = match parse_key_algorithm(params) {
+        Ok(algorithm) => algorithm,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let options = piv::CsrOptions {
+        subject: subject.to_string(),
+        alt_names,
+        public_key: params
+            .get("publicKey")
+            .and_then(|v| v.as_str())
+            .map(String::from),
+    };
+    let pin = params.get("pin").and_then(|v| v.as_str());
+
+    match piv::generate_csr(device_manager, device_id, slot, algorithm, &options, pin) {
+        Ok(csr) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "slot": csr.slot,
+                "algorithm": csr.algorithm,
+                "subject": csr.subject,
+                "csr": csr.csr_pem,
+                "activityLog": csr.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "PIV_GENERATE_CSR_FAILED",
+            "Failed to generate certificate request",
+            &e,
+        ),
+    }
+}
+
+/// Parse an optional list-of-strings parameter
+fn parse_string_list(
+    params: &serde_json::Value,
+    name: &str,
+) -> Result<Option<Vec<String>>, String> {
+    match params.get(name) {
+        Some(values) => serde_json::from_value(values.clone())
+            .map(Some)
+            .map_err(|_| format!("{} must be a list of strings", name)),
+        None => Ok(None),
+    }
+}
+
+/// Handle a pivSelfSignCertificate command
+fn handle_piv_self_sign_certificate(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivSelfSignCertificate command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match params.get("slot").and_then(|v| v.as_str()) {
+        Some(slot) => slot,
+        None => {
+            return Response::invalid_params(id, "Missing slot parameter");
+        }
+    };
+
+    let subject = match params.get("subject").and_then(|v| v.as_str()) {
+        Some(subject) => subject,
+        None => {
+            return Response::invalid_params(id, "Missing subject parameter");
+        }
+    };
+
+    let alt_names = match parse_subject_alt_names(params) {
+        Ok(names) => names,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let algorithm = match parse_key_algorithm(params) {
+        Ok(algorithm) => algorithm,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let mut options = piv::SelfSignOptions {
+        subject: subject.to_string(),
+        alt_names,
+        public_key: params
+            .get("publicKey")
+            .and_then(|v| v.as_str())
+            .map(String::from),
+        ..piv::SelfSignOptions::default()
+    };
+
+    if let Some(days) = params.get("validDays") {
+        match days.as_u64().and_then(|d| u32::try_from(d).ok()) {
+            Some(days) => options.valid_days = days,
+            None => {
+                return Response::invalid_params(id, "validDays must be a positive integer");
+            }
+        }
+    }
+
+    match parse_string_list(params, "keyUsage") {
+        Ok(Some(usage)) => options.key_usage = usage,
+        Ok(None) => {}
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    }
+
+    match parse_string_list(params, "extendedKeyUsage") {
+        Ok(Some(usage)) => options.extended_key_usage = usage,
+        Ok(None) => {}
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    }
+
+    let pin = params.get("pin").and_then(|v| v.as_str());
+    let management_key = params.get("managementKey").and_then(|v| v.as_str());
+
+    match piv::self_sign_certificate(
+        device_manager,
+        device_id,
+        slot,
+        algorithm,
+        &options,
+        pin,
+        management_key,
+    ) {
+        Ok(issued) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "certificate": issued.certificate,
+                "certificatePem": issued.certificate_pem,
+                "activityLog": issued.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "PIV_SELF_SIGN_FAILED",
+            "Failed to issue self-signed certificate",
+            &e,
+        ),
+    }
+}
+
+/// Move a secret string parameter out of the request so it is wiped after use
+fn take_secret_param(params: &mut serde_json::Value, name: &str) -> Option<Zeroizing<String>> {
+    match params.get_mut(name).map(serde_json::Value::take) {
+        Some(serde_json::Value::String(value)) => Some(Zeroizing::new(value)),
+        _ => None,
+    }
+}
+
+/// Handle a pivImportKey command
+fn handle_piv_import_key(
+    id: u32,
+    params: &mut serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivImportKey command");
+
+    let private_key = match take_secret_param(params, "privateKey") {
+        Some(key) => key,
+        None => {
+            return Response::invalid_params(id, "Missing privateKey parameter");
+        }
+    };
+    let params = &*params;
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match params.get("slot").and_then(|v| v.as_str()) {
+        Some(slot) => slot,
+        None => {
+            return Response::invalid_params(id, "Missing slot parameter");
+        }
+    };
+
+    let pin_policy = match params.get("pinPolicy").and_then(|v| v.as_str()) {
+        Some(name) => match piv::PinPolicy::from_name(name) {
+            Some(policy) => policy,
+            None => {
+                return Response::invalid_params(id, &format!("Unknown PIN policy: {}", name));
+            }
+        },
+        None => piv::PinPolicy::Default,
+    };
+
+    let touch_policy = match params.get("touchPolicy").and_then(|v| v.as_str()) {
+        Some(name) => match piv::TouchPolicy::from_name(name) {
+            Some(policy) => policy,
+            None => {
+                return Response::invalid_params(id, &format!("Unknown touch policy: {}", name));
+            }
+        },
+        None => piv::TouchPolicy::Default,
+    };
+
+    let management_key = params.get("managementKey").and_then(|v| v.as_str());
+
+    match piv::import_key(
+        device_manager,
+        device_id,
+        slot,
+        &private_key,
+        pin_policy,
+        touch_policy,
+        management_key,
+    ) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "slot": result.slot,
+                "algorithm": result.algorithm,
+                "pinPolicy": result.pin_policy,
+                "touchPolicy": result.touch_policy,
+                "publicKeyPem": result.public_key_pem,
+                "publicKeyDer": result.public_key_der,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "PIV_IMPORT_KEY_FAILED", "Failed to import key", &e),
+    }
+}
+
+/// Stream a progress step to the extension before the final response
+fn send_progress(id: u32, step: &apdu::ProgressStep) {
+    let progress = Response::progress(
+        id,
+        serde_json::json!({
+            "stage": step.stage,
+            "message": step.message,
+            "apdu": step.apdu
+        }),
+    );
+    match serde_json::to_string(&progress) {
+        Ok(message) => {
+            if let Err(e) = write_message(&message) {
+                log::warn!("Failed to send progress: {}", e);
+            }
+        }
+        Err(e) => log::warn!("Failed to serialize progress: {}", e),
+    }
+}
+
+/// Handle pivReset command
+fn handle_piv_reset(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivReset command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let confirmed = params
+        .get("confirm")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let mut on_step = |step: &apdu::ProgressStep| send_progress(id, step);
+
+    match piv::reset(device_manager, device_id, confirmed, &mut on_step) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "pinRetries": result.pin_retries,
+                "defaultPin": result.default_pin,
+                "defaultPuk": result.default_puk,
+                "defaultManagementKey": result.default_management_key,
+                "managementKeyAlgorithm": result.management_key_algorithm,
+                "credentialMetadata": result.credential_metadata,
+                "warnings": result.warnings,
+                "steps": result.steps,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "PIV_RESET_FAILED", "Failed to reset PIV", &e),
+    }
+}
+
+/// Handle pivInitialize command
+fn handle_piv_initialize(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling pivInitialize command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    // Credentials to set are grouped under an optional profile object
+    let empty = serde_json::json!({});
+    let profile = params.get("profile").unwrap_or(&empty);
+    let text = |name: &str| {
+        profile
+            .get(name)
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+    };
+
+    let new_management_key_algorithm = match parse_management_key_algorithm(profile) {
+        Ok(algorithm) => algorithm,
+        Err(message) => return Response::invalid_params(id, &message),
+    };
+
+    let mut init_profile = piv::PivInitProfile {
+        management_key: params
+            .get("managementKey")
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string()),
+        current_pin: text("currentPin"),
+        new_pin: text("newPin"),
+        current_puk: text("currentPuk"),
+        new_puk: text("newPuk"),
+        new_management_key: text("newManagementKey"),
+        new_management_key_algorithm,
+        ..Default::default()
+    };
+    if let Some(years) = params.get("chuidValidYears") {
+        match years.as_u64().and_then(|v| u32::try_from(v).ok()) {
+            Some(years) if years > 0 => init_profile.chuid_valid_years = years,
+            _ => return Response::invalid_params(id, "Invalid chuidValidYears parameter"),
+        }
+    }
+
+    match piv::initialize(device_manager, device_id, &init_profile) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "chuid": result.chuid,
+                "cccCardIdentifier": result.ccc_card_identifier,
+                "pinChanged": result.pin_changed,
+                "pukChanged": result.puk_changed,
+                "managementKeyChanged": result.management_key_changed,
+                "managementKeyAlgorithm": result.management_key_algorithm,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "PIV_INITIALIZE_FAILED", "Failed to initialize PIV", &e),
+    }
+}
+
+/// Handle openpgpGetStatus command
+fn handle_openpgp_get_status(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling openpgpGetStatus command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    match openpgp::get_status(device_manager, device_id) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "status": result.status,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "OPENPGP_GET_STATUS_FAILED",
+            "Failed to read OpenPGP status",
+            &e,
+        ),
+    }
+}
+
+/// Build the success response shared by the OpenPGP PIN commands
+fn openpgp_pin_response(id: u32, message: &str, result: openpgp::OpenPgpPinResult) -> Response {
+    Response::success(
+        id,
+        serde_json::json!({
+            "success": true,
+            "message": message,
+            "pwStatus": result.pw_status,
+            "activityLog": result.activity_log
+        }),
+    )
+}
+
+/// Handle openpgpVerify command
+fn handle_openpgp_verify(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling openpgpVerify command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let pin = match params.get("pin").and_then(|v| v.as_str()) {
+        Some(pin) => pin,
+        None => {
+            return Response::invalid_params(id, "Missing pin parameter");
+        }
+    };
+
+    let password = match params.get("pinType").and_then(|v| v.as_str()) {
+        Some(name) => match openpgp::PasswordType::from_name(name) {
+            Some(password) => password,
+            None => {
+                return Response::invalid_params(id, &format!("Unknown PIN type: {}", name));
+            }
+        },
+        None => openpgp::PasswordType::User,
+    };
+
+    match openpgp::verify(device_manager, device_id, password, pin) {
+        Ok(result) => openpgp_pin_response(id, "PIN verified successfully", result),
+        Err(e) => Response::failure(id, "OPENPGP_VERIFY_FAILED", "Failed to verify PIN", &e),
+    }
+}
+
+/// Handle openpgpChangePin command
+fn handle_openpgp_change_pin(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling openpgpChangePin command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let current_pin = match params.get("currentPin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing currentPin parameter");
+        }
+    };
+
+    let new_pin = match params.get("newPin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing newPin parameter");
+        }
+    };
+
+    match openpgp::change_pin(device_manager, device_id, current_pin, new_pin) {
+        Ok(result) => openpgp_pin_response(id, "PIN changed successfully", result),
+        Err(e) => Response::failure(id, "OPENPGP_CHANGE_PIN_FAILED", "Failed to change PIN", &e),
+    }
+}
+
+/// Handle openpgpChangeAdminPin command
+fn handle_openpgp_change_admin_pin(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling openpgpChangeAdminPin command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let current_admin_pin = match params.get("currentAdminPin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing currentAdminPin parameter");
+        }
+    };
+
+    let new_admin_pin = match params.get("newAdminPin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing newAdminPin parameter");
+        }
+    };
+
+    match openpgp::change_admin_pin(device_manager, device_id, current_admin_pin, new_admin_pin) {
+        Ok(result) => openpgp_pin_response(id, "Admin PIN changed successfully", result),
+        Err(e) => Response::failure(
+            id,
+            "OPENPGP_CHANGE_ADMIN_PIN_FAILED",
+            "Failed to change admin PIN",
+            &e,
+        ),
+    }
+}
+
+/// Handle openpgpSetResetCode command
+fn handle_openpgp_set_reset_code(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling openpgpSetResetCode command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let admin_pin = match params.get("adminPin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing adminPin parameter");
+        }
+    };
+
+    let reset_code = match params.get("resetCode").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing resetCode parameter");
+        }
+    };
+
+    match openpgp::set_reset_code(device_manager, device_id, admin_pin, reset_code) {
+        Ok(result) => openpgp_pin_response(id, "Reset code updated successfully", result),
+        Err(e) => Response::failure(
+            id,
+            "OPENPGP_SET_RESET_CODE_FAILED",
+            "Failed to set reset code",
+            &e,
+        ),
+    }
+}
+
+/// Handle openpgpUnblockPin command
+fn handle_openpgp_unblock_pin(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling openpgpUnblockPin command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let new_pin = match params.get("newPin").and_then(|v| v.as_str()) {
+        Some(pin) => pin,
+        None => {
+            return Response::invalid_params(id, "Missing newPin parameter");
+        }
+    };
+
+    // Either the reset code or the admin PIN authorises the unblock
+    let authorization = match (
+        params.get("resetCode").and_then(|v| v.as_str()),
+        params.get("adminPin").and_then(|v| v.as_str()),
+    ) {
+        (Some(code), None) => openpgp::UnblockAuthorization::ResetCode(code),
+        (None, Some(admin_pin)) => openpgp::UnblockAuthorization::AdminPin(admin_pin),
+        _ => {
+            return Response::invalid_params(id, "Provide exactly one of resetCode or adminPin");
+        }
+    };
+
+    match openpgp::unblock_pin(device_manager, device_id, authorization, new_pin) {
+        Ok(result) => openpgp_pin_response(id, "PIN unblocked successfully", result),
+        Err(e) => Response::failure(
+            id,
+            "OPENPGP_UNBLOCK_PIN_FAILED",
+            "Failed to unblock PIN",
+            &e,
+        ),
+    }
+}
+
+/// Parse the required OpenPGP key slot parameter
+fn parse_openpgp_slot(params: &serde_json::Value) -> Result<openpgp::OpenPgpKeySlot, String> {
+    match params.get("slot").and_then(|v| v.as_str()) {
+        Some(name) => openpgp::OpenPgpKeySlot::from_name(name).ok_or_else(|| {
+            format!(
+                "Invalid slot: {} (expected signature, decryption or authentication)",
+                name
+            )
+        }),
+        None => Err("Missing slot parameter".to_string()),
+    }
+}
+
+/// Parse the optional OpenPGP key algorithm parameter
+fn parse_openpgp_algorithm(
+    params: &serde_json::Value,
+) -> Result<Option<openpgp::KeyAlgorithm>, String> {
+    match params.get("algorithm").and_then(|v| v.as_str()) {
+        Some(name) => openpgp::KeyAlgorithm::from_name(name)
+            .map(Some)
+            .ok_or_else(|| format!("Unsupported algorithm: {}", name)),
+        None => Ok(None),
+    }
+}
+
+/// Parse the optional key creation time (seconds since the Unix epoch)
+fn parse_creation_time(params: &serde_json::Value) -> Result<Option<u32>, String> {
+    match params.get("creationTime") {
+        Some(value) => value
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .map(Some)
+            .ok_or_else(|| "creationTime must be a Unix timestamp".to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Parse the optional userId, and the pin needed to self-certify it
+fn parse_self_certification(
+    params: &serde_json::Value,
+) -> Result<Option<openpgp::SelfCertification<'_>>, String> {
+    let user_id = match params.get("userId") {
+        Some(value) => value
+            .as_str()
+            .ok_or_else(|| "userId must be a string".to_string())?,
+        None => return Ok(None),
+    };
+    let pin = params
+        .get("pin")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| "Missing pin parameter (needed to self-certify userId)".to_string())?;
+    Ok(Some(openpgp::SelfCertification { user_id, pin }))
+}
+
+/// Build the success response shared by OpenPGP key generation and import
+fn openpgp_key_response(id: u32, message: &str, result: openpgp::OpenPgpKeyResult) -> Response {
+    Response::success(
+        id,
+        serde_json::json!({
+            "success": true,
+            "message": message,
+            "slot": result.slot,
+            "algorithm": result.algorithm,
+            "fingerprint": result.fingerprint,
+            "generationTime": result.generation_time,
+            "publicKeyPacket": result.public_key_packet,
+            "userId": result.user_id,
+            "publicKey": result.public_key_armored,
+            "activityLog": result.activity_log
+        }),
+    )
+}
+
+/// Handle openpgpSetAlgorithm command
+fn handle_openpgp_set_algorithm(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling openpgpSetAlgorithm command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match parse_openpgp_slot(params) {
+        Ok(slot) => slot,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let algorithm = match parse_openpgp_algorithm(params) {
+        Ok(Some(algorithm)) => algorithm,
+        Ok(None) => {
+            return Response::invalid_params(id, "Missing algorithm parameter");
+        }
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let admin_pin = match params.get("adminPin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing adminPin parameter");
+        }
+    };
+
+    match openpgp::set_algorithm(device_manager, device_id, slot, algorithm, admin_pin) {
+        Ok(activity_log) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "Key algorithm updated successfully",
+                "slot": slot,
+                "algorithm": algorithm,
+                "activityLog": activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "OPENPGP_SET_ALGORITHM_FAILED",
+            "Failed to set key algorithm",
+            &e,
+        ),
+    }
+}
+
+/// Handle openpgpGenerateKey command
+fn handle_openpgp_generate_key(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling openpgpGenerateKey command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match parse_openpgp_slot(params) {
+        Ok(slot) => slot,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let algorithm = match parse_openpgp_algorithm(params) {
+        Ok(algorithm) => algorithm,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let admin_pin = match params.get("adminPin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing adminPin parameter");
+        }
+    };
+
+    let creation_time = match parse_creation_time(params) {
+        Ok(time) => time,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let certification = match parse_self_certification(params) {
+        Ok(certification) => certification,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    match openpgp::generate_key(
+        device_manager,
+        device_id,
+        slot,
+        algorithm,
+        admin_pin,
+        creation_time,
+        certification.as_ref(),
+    ) {
+        Ok(result) => openpgp_key_response(id, "Key generated successfully", result),
+        Err(e) => Response::failure(
+            id,
+            "OPENPGP_GENERATE_KEY_FAILED",
+            "Failed to generate key",
+            &e,
+        ),
+    }
+}
+
+/// Handle openpgpImportKey command
+fn handle_openpgp_import_key(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling openpgpImportKey command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match parse_openpgp_slot(params) {
+        Ok(slot) => slot,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let private_key = match params.get("privateKey").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing privateKey parameter");
+        }
+    };
+
+    let admin_pin = match params.get("adminPin").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing adminPin parameter");
+        }
+    };
+
+    let creation_time = match parse_creation_time(params) {
+        Ok(time) => time,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    let certification = match parse_self_certification(params) {
+        Ok(certification) => certification,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    match openpgp::import_key(
+        device_manager,
+        device_id,
+        slot,
+        private_key,
+        admin_pin,
+        creation_time,
+        certification.as_ref(),
+    ) {
+        Ok(result) => openpgp_key_response(id, "Key imported successfully", result),
+        Err(e) => Response::failure(id, "OPENPGP_IMPORT_KEY_FAILED", "Failed to import key", &e),
+    }
+}
+
+/// Handle openpgpReset command
+fn handle_openpgp_reset(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling openpgpReset command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let confirmed = params
+        .get("confirm")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let mut on_step = |step: &apdu::ProgressStep| send_progress(id, step);
+
+    match openpgp::reset(device_manager, device_id, confirmed, &mut on_step) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "status": result.status,
+                "defaultPin": result.default_pin,
+                "defaultAdminPin": result.default_admin_pin,
+                "warnings": result.warnings,
+                "steps": result.steps,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "OPENPGP_RESET_FAILED", "Failed to reset OpenPGP", &e),
+    }
+}
+
+/// Read the optional OATH access code parameter
+fn oath_access_code(params: &serde_json::Value) -> Option<&str> {
+    params.get("accessCode").and_then(|v| v.as_str())
+}
+
+/// Parse the optional Unix timestamp used for TOTP codes
+fn parse_oath_timestamp(params: &serde_json::Value) -> Result<Option<u64>, String> {
+    match params.get("timestamp") {
+        Some(value) => value
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| "timestamp must be a Unix timestamp".to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Parse the credential fields of oathPut
+fn parse_oath_credential(params: &serde_json::Value) -> Result<oath::OathCredentialData, String> {
+    let account = match params.get("account").and_then(|v| v.as_str()) {
+        Some(value) => value.to_string(),
+        None => return Err("Missing account parameter".to_string()),
+    };
+    let secret = match params.get("secret").and_then(|v| v.as_str()) {
+        Some(value) => oath::decode_base32(value).map_err(|e| e.to_string())?,
+        None => return Err("Missing secret parameter".to_string()),
+    };
+    let oath_type = match params.get("type").and_then(|v| v.as_str()) {
+        Some(name) => oath::OathType::from_name(name)
+            .ok_or_else(|| format!("Invalid type: {} (expected totp or hotp)", name))?,
+        None => oath::OathType::Totp,
+    };
+    let algorithm = match params.get("algorithm").and_then(|v| v.as_str()) {
+        Some(name) => oath::OathAlgorithm::from_name(name)
+            .ok_or_else(|| format!("Unsupported algorithm: {}", name))?,
+        None => oath::OathAlgorithm::Sha1,
+    };
+    let number = |name: &str, default: u64| -> Result<u64, String> {
+        match params.get(name) {
+            Some(value) => value
+                .as_u64()
+                .ok_or_else(|| format!("{} must be a positive integer", name)),
+            None => Ok(default),
+        }
+    };
+    let digits = u8::try_from(number("digits", u64::from(oath::DEFAULT_DIGITS))?)
+        .map_err(|_| "digits is out of range".to_string())?;
+    let period = u32::try_from(number("period", u64::from(oath::DEFAULT_PERIOD))?)
+        .map_err(|_| "period is out of range".to_string())?;
+    let counter =
+        u32::try_from(number("counter", 0)?).map_err(|_| "counter is out of range".to_string())?;
+
+    let credential = oath::OathCredentialData {
+        issuer: params
+            .get("issuer")
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+            .map(String::from),
+        account,
+        secret,
+        oath_type,
+        algorithm,
+        digits,
+        period,
+        counter,
+        touch: params
+            .get("touch")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+    };
+    credential.validate().map_err(|e| e.to_string())?;
+    Ok(credential)
+}
+
+/// Build the success response shared by the OATH code commands
+fn oath_codes_response(id: u32, result: oath::OathCalculateResult) -> Response {
+    Response::success(
+        id,
+        serde_json::json!({
+            "success": true,
+            "timestamp": result.timestamp,
+            "codes": result.codes,
+            "activityLog": result.activity_log
+        }),
+    )
+}
+
+/// Handle oathList command
+fn handle_oath_list(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling oathList command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    match oath::list(device_manager, device_id, oath_access_code(params)) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "status": result.status,
+                "credentials": result.credentials,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "OATH_LIST_FAILED",
+            "Failed to list OATH credentials",
+            &e,
+        ),
+    }
+}
+
+/// Handle oathCalculate command
+fn handle_oath_calculate(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling oathCalculate command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let name = match params.get("name").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing name parameter");
+        }
+    };
+
+    let timestamp = match parse_oath_timestamp(params) {
+        Ok(timestamp) => timestamp,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    match oath::calculate(
+        device_manager,
+        device_id,
+        name,
+        timestamp,
+        oath_access_code(params),
+    ) {
+        Ok(result) => oath_codes_response(id, result),
+        Err(e) => Response::failure(
+            id,
+            "OATH_CALCULATE_FAILED",
+            "Failed to calculate OATH code",
+            &e,
+        ),
+    }
+}
+
+/// Handle oathCalculateAll command
+fn handle_oath_calculate_all(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling oathCalculateAll command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let timestamp = match parse_oath_timestamp(params) {
+        Ok(timestamp) => timestamp,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    match oath::calculate_all(
+        device_manager,
+        device_id,
+        timestamp,
+        oath_access_code(params),
+    ) {
+        Ok(result) => oath_codes_response(id, result),
+        Err(e) => Response::failure(
+            id,
+            "OATH_CALCULATE_FAILED",
+            "Failed to calculate OATH codes",
+            &e,
+        ),
+    }
+}
+
+/// Handle oathPut command
+fn handle_oath_put(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling oathPut command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let credential = match parse_oath_credential(params) {
+        Ok(credential) => credential,
+        Err(message) => {
+            return Response::invalid_params(id, &message);
+        }
+    };
+
+    match oath::put(
+        device_manager,
+        device_id,
+        &credential,
+        oath_access_code(params),
+    ) {
+        Ok((credential, activity_log)) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "Credential stored successfully",
+                "credential": credential,
+                "activityLog": activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "OATH_PUT_FAILED", "Failed to store OATH credential", &e),
+    }
+}
+
+/// Handle oathDelete command
+fn handle_oath_delete(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling oathDelete command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let name = match params.get("name").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing name parameter");
+        }
+    };
+
+    match oath::delete(device_manager, device_id, name, oath_access_code(params)) {
+        Ok(activity_log) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "Credential deleted successfully",
+                "activityLog": activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "OATH_DELETE_FAILED",
+            "Failed to delete OATH credential",
+            &e,
+        ),
+    }
+}
+
+/// Handle oathRename command
+fn handle_oath_rename(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling oathRename command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let name = match params.get("name").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing name parameter");
+        }
+    };
+
+    let account = match params.get("account").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing account parameter");
+        }
+    };
+    let issuer = params.get("issuer").and_then(|v| v.as_str());
+
+    match oath::rename(
+        device_manager,
+        device_id,
+        name,
+        issuer,
+        account,
+        oath_access_code(params),
+    ) {
+        Ok((credential, activity_log)) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "Credential renamed successfully",
+                "credential": credential,
+                "activityLog": activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "OATH_RENAME_FAILED",
+            "Failed to rename OATH credential",
+            &e,
+        ),
+    }
+}
+
+/// Handle oathSetAccessCode command
+///
+/// Omitting `newAccessCode` removes the access code.
+fn handle_oath_set_access_code(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling oathSetAccessCode command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let new_access_code = params.get("newAccessCode").and_then(|v| v.as_str());
+
+    match oath::set_access_code(
+        device_manager,
+        device_id,
+        oath_access_code(params),
+        new_access_code,
+    ) {
+        Ok((status, activity_log)) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": if new_access_code.is_some() {
+                    "Access code set successfully"
+                } else {
+                    "Access code removed successfully"
+                },
+                "status": status,
+                "activityLog": activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "OATH_SET_ACCESS_CODE_FAILED",
+            "Failed to set OATH access code",
+            &e,
+        ),
+    }
+}
+
+/// Handle oathValidate command
+fn handle_oath_validate(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling oathValidate command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let access_code = match oath_access_code(params) {
+        Some(value) => value,
+        None => {
+            return Response::invalid_params(id, "Missing accessCode parameter");
+        }
+    };
+
+    match oath::validate(device_manager, device_id, access_code) {
+        Ok((status, activity_log)) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "Access code accepted",
+                "status": status,
+                "activityLog": activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "OATH_VALIDATE_FAILED",
+            "Failed to validate OATH access code",
+            &e,
+        ),
+    }
+}
+
+/// Handle oathImportUri command
+///
+/// Accepts a single `uri` or a list of `uris`.
+fn handle_oath_import_uri(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling oathImportUri command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let uris = match (
+        params.get("uri").and_then(|v| v.as_str()),
+        parse_string_list(params, "uris"),
+    ) {
+        (_, Err(message)) => {
+            return Response::invalid_params(id, &message);
+        }
+        (Some(uri), Ok(None)) => vec![uri.to_string()],
+        (None, Ok(Some(uris))) if !uris.is_empty() => uris,
+        (Some(_), Ok(Some(_))) => {
+            return Response::invalid_params(id, "Pass either uri or uris, not both");
+        }
+        (None, _) => {
+            return Response::invalid_params(id, "Missing uri parameter");
+        }
+    };
+
+    let touch = params
+        .get("touch")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    match oath::import_uris(
+        device_manager,
+        device_id,
+        &uris,
+        touch,
+        oath_access_code(params),
+    ) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": format!("Imported {} credentials", result.imported.len()),
+                "imported": result.imported,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(
+            id,
+            "OATH_IMPORT_FAILED",
+            "Failed to import OATH credentials",
+            &e,
+        ),
+    }
+}
+
+/// Handle oathExportInventory command
+fn handle_oath_export_inventory(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling oathExportInventory command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let format = params
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("json");
+    if format != "json" && format != "csv" {
+        return Response::invalid_params(id, "format must be json or csv");
+    }
+
+    let result = match oath::inventory(device_manager, device_id, oath_access_code(params)) {
+        Ok(result) => result,
+        Err(e) => {
+            return Response::failure(
+                id,
+                "OATH_EXPORT_FAILED",
+                "Failed to export OATH inventory",
+                &e,
+            );
+        }
+    };
+
+    let content = if format == "csv" {
+        oath::inventory_csv(&result.entries)
+    } else {
+        match serde_json::to_string_pretty(&result.entries) {
+            Ok(json) => json,
+            Err(e) => {
+                return Response::failure(
+                    id,
+                    "OATH_EXPORT_FAILED",
+                    "Failed to export OATH inventory",
+                    &e.into(),
+                );
+            }
+        }
+    };
+
+    Response::success(
+        id,
+        serde_json::json!({
+            "success": true,
+            "format": format,
+            "content": content,
+            "entries": result.entries,
+            "activityLog": result.activity_log
+        }),
+    )
+}
+
+/// Handle ndefRead command
+fn handle_ndef_read(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling ndefRead command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    match ndef::read(device_manager, device_id) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "capabilities": result.capabilities,
+                "records": result.records,
+                "message": result.message,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "NDEF_READ_FAILED", "Failed to read NDEF message", &e),
+    }
+}
+
+/// Handle ndefWrite command
+fn handle_ndef_write(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling ndefWrite command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let records: Vec<ndef::NdefRecord> = match params.get("records") {
+        Some(values) => match serde_json::from_value(values.clone()) {
+            Ok(records) => records,
+            Err(e) => {
+                return Response::invalid_params(id, &format!("Invalid records: {}", e));
+            }
+        },
+        None => {
+            return Response::invalid_params(id, "Missing records parameter");
+        }
+    };
+
+    match ndef::write(device_manager, device_id, &records) {
+        Ok(result) => Response::success(
+            id,
+            serde_json::json!({
+                "success": true,
+                "message": "NDEF message written successfully",
+                "capabilities": result.capabilities,
+                "bytesWritten": result.bytes_written,
+                "activityLog": result.activity_log
+            }),
+        ),
+        Err(e) => Response::failure(id, "NDEF_WRITE_FAILED", "Failed to write NDEF message", &e),
+    }
+}
+
+/// Parse the slot parameter of OTP commands
+fn parse_otp_slot(params: &serde_json::Value) -> Result<otp::OtpSlot, String> {
+    match params.get("slot").and_then(|v| v.as_u64()) {
+        Some(number) => otp::OtpSlot::from_number(number)
+            .ok_or_else(|| format!("Invalid slot: {} (expected 1 or 2)", number)),
+        None => Err("Missing slot parameter".to_string()),
+    }
+}
+
+/// Parse the optional accessCode and newAccessCode parameters
+fn parse_otp_access_codes(params: &serde_json::Value) -> Result<otp::OtpAccessCodes, String> {
+    let code = |name: &str| match params.get(name).and_then(|v| v.as_str()) {
+        Some(value) => otp::parse_access_code(value)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", name, e)),
+        None => Ok(None),
+    };
+    Ok(otp::OtpAccessCodes {
+        current: code("accessCode")?,
+        new: code("newAccessCode")?,
+    })
+}
+
+/// Parse the slot configuration of otpProgramSlot
+fn parse_otp_slot_config(params: &serde_json::Value) -> Result<otp::OtpSlotConfig, String> {
+    let flag = |name: &str, default: bool| {
+        params
+            .get(name)
+            .and_then(|v| v.as_bool())
+            .unwrap_or(default)
+    };
+    let string = |name: &str| match params.get(name).and_then(|v| v.as_str()) {
+        Some(value) => Ok(value),
+        None => Err(format!("Missing {} parameter", name)),
+    };
+
+    match params.get("type").and_then(|v| v.as_str()) {
+        Some("static") => Ok(otp::OtpSlotConfig::StaticPassword {
+            password: Zeroizing::new(string("password")?.to_string()),
+            append_enter: flag("appendEnter", true),
+        }),
+        Some("hotp") => {
+            let number = |name: &str, default: u64| match params.get(name) {
+                Some(value) => value
+                    .as_u64()
+                    .ok_or_else(|| format!("{} must be a positive integer", name)),
+                None => Ok(default),
+            };
+            Ok(otp::OtpSlotConfig::Hotp {
+                secret: oath::decode_base32(string("secret")?).map_err(|e| e.to_string())?,
+                digits: u8::try_from(number("digits", 6)?)
+                    .map_err(|_| "digits is out of range".to_string())?,
+                counter: u32::try_from(number("counter", 0)?)
+                    .map_err(|_| "counter is out of range".to_string())?,
+                append_enter: flag("appendEnter", true),
+            })
+        }
+        Some("challengeResponse") => Ok(otp::OtpSlotConfig::ChallengeResponse {
+            key: Zeroizing::new(
+                hex::decode(string("key")?).map_err(|_| "key must be hex".to_string())?,
+            ),
+            require_touch: flag("requireTouch", false),
+        }),
+        Some(other) => Err(format!(
+            "Invalid type: {} (expected static, hotp or challengeResponse)",
+            other
+        )),
+        None => Err("Missing type parameter".to_string()),
+    }
+}
+
+/// Build the response shared by OTP commands
+fn otp_status_response(id: u32, result: otp::OtpStatusResult, message: &str) -> Response {
+    Response::success(
+        id,
+        serde_json::json!({
+            "success": true,
+            "message": message,
+            "status": result.status,
+            "activityLog": result.activity_log
+        }),
+    )
+}
+
+/// Handle otpGetStatus command
+fn handle_otp_get_status(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling otpGetStatus command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    match otp::get_status(device_manager, device_id) {
+        Ok(result) => otp_status_response(id, result, "OTP status read successfully"),
+        Err(e) => Response::failure(id, "OTP_STATUS_FAILED", "Failed to read OTP status", &e),
+    }
+}
+
+/// Handle otpProgramSlot command
+fn handle_otp_program_slot(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling otpProgramSlot command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match parse_otp_slot(params) {
+        Ok(slot) => slot,
+        Err(message) => return Response::invalid_params(id, &message),
+    };
+    let config = match parse_otp_slot_config(params) {
+        Ok(config) => config,
+        Err(message) => return Response::invalid_params(id, &message),
+    };
+    let access_codes = match parse_otp_access_codes(params) {
+        Ok(codes) => codes,
+        Err(message) => return Response::invalid_params(id, &message),
+    };
+
+    match otp::program_slot(device_manager, device_id, slot, &config, &access_codes) {
+        Ok(result) => otp_status_response(id, result, "OTP slot programmed successfully"),
+        Err(e) => Response::failure(id, "OTP_PROGRAM_FAILED", "Failed to program OTP slot", &e),
+    }
+}
+
+/// Handle otpDeleteSlot command
+fn handle_otp_delete_slot(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling otpDeleteSlot command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let slot = match parse_otp_slot(params) {
+        Ok(slot) => slot,
+        Err(message) => return Response::invalid_params(id, &message),
+    };
+    let access_codes = match parse_otp_access_codes(params) {
+        Ok(codes) => codes,
+        Err(message) => return Response::invalid_params(id, &message),
+    };
+
+    match otp::delete_slot(
+        device_manager,
+        device_id,
+        slot,
+        access_codes.current.as_ref(),
+    ) {
+        Ok(result) => otp_status_response(id, result, "OTP slot deleted successfully"),
+        Err(e) => Response::failure(id, "OTP_DELETE_FAILED", "Failed to delete OTP slot", &e),
+    }
+}
+
+/// Handle otpSwapSlots command
+fn handle_otp_swap_slots(
+    id: u32,
+    params: &serde_json::Value,
+    device_manager: &device::DeviceManager,
+) -> Response {
+    log::debug!("Handling otpSwapSlots command");
+
+    let device_id = match params.get("deviceId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => {
+            return Response::invalid_params(id, "Missing deviceId parameter");
+        }
+    };
+
+    let access_codes = match parse_otp_access_codes(params) {
+        Ok(codes) => codes,
+        Err(message) => return Response::invalid_params(id, &message),
+    };
+
+    match otp::swap_slots(device_manager, device_id, access_codes.current.as_ref()) {
+        Ok(result) => otp_status_response(id, result, "OTP slots swapped successfully"),
+        Err(e) => Response::failure(id, "OTP_SWAP_FAILED", "Failed to swap OTP slots", &e),
+    }
+}
+
+/// Process a single request
+fn process_request(mut request: Request, device_manager: &device::DeviceManager) -> Response {
+    log::info!(
+        "Processing command: {} (id: {})",
+        request.command,
+        request.id
+    );
+
+    match request.command.as_str() {
+        "ping" => handle_ping(request.id),
+        "getVersion" => handle_get_version(request.id),
+        "listDevices" => handle_list_devices(request.id),
+        "openDevice" => handle_open_device(request.id, &request.params, device_manager),
+        "closeDevice" => handle_close_device(request.id, &request.params, device_manager),
+        "sendHid" => handle_send_hid(request.id, &request.params, device_manager),
+        "receiveHid" => handle_receive_hid(request.id, &request.params, device_manager),
+        "transmitApdu" => handle_transmit_apdu(request.id, &request.params, device_manager),
+        "detectProtocols" => handle_detect_protocols(request.id, &request.params, device_manager),
+        "fido2GetInfo" => handle_fido2_get_info(request.id, &request.params, device_manager),
+        "fido2GetPinRetries" => {
+            handle_fido2_get_pin_retries(request.id, &request.params, device_manager)
+        }
+        "fido2SetPin" => handle_fido2_set_pin(request.id, &request.params, device_manager),
+        "fido2ChangePin" => handle_fido2_change_pin(request.id, &request.params, device_manager),
+        "fido2ListCredentials" => {
+            handle_fido2_list_credentials(request.id, &request.params, device_manager)
+        }
+        "fido2DeleteCredential" => {
+            handle_fido2_delete_credential(request.id, &request.params, device_manager)
+        }
+        "fido2ResetDevice" => {
+            handle_fido2_reset_device(request.id, &request.params, device_manager)
+        }
+        "pivGetData" => handle_piv_get_data(request.id, &request.params, device_manager),
+        "pivSelect" => handle_piv_select(request.id, &request.params, device_manager),
+        "pivGetPinRetries" => {
+            handle_piv_get_pin_retries(request.id, &request.params, device_manager)
+        }
+        "pivVerifyPin" => handle_piv_verify_pin(request.id, &request.params, device_manager),
+        "pivChangePin" => handle_piv_change_pin(request.id, &request.params, device_manager),
+        "pivChangePuk" => handle_piv_change_puk(request.id, &request.params, device_manager),
+        "pivUnblockPin" => handle_piv_unblock_pin(request.id, &request.params, device_manager),
+        "pivAuthenticate" => handle_piv_authenticate(request.id, &request.params, device_manager),
+        "pivChangeManagementKey" => {
+            handle_piv_change_management_key(request.id, &request.params, device_manager)
+        }
+        "pivGenerateKey" => handle_piv_generate_key(request.id, &request.params, device_manager),
+        "pivImportCertificate" => {
+            handle_piv_import_certificate(request.id, &request.params, device_manager)
+        }
+        "pivExportCertificate" => {
+            handle_piv_export_certificate(request.id, &request.params, device_manager)
+        }
+        "pivDeleteCertificate" => {
+            handle_piv_delete_certificate(request.id, &request.params, device_manager)
+        }
+        "pivSign" => handle_piv_sign(request.id, &request.params, device_manager),
+        "pivDecrypt" => handle_piv_decrypt(request.id, &request.params, device_manager),
+        "pivEcdh" => handle_piv_ecdh(request.id, &request.params, device_manager),
+        "pivAttestSlot" => handle_piv_attest_slot(request.id, &request.params, device_manager),
+        "pivGenerateCsr" => handle_piv_generate_csr(request.id, &request.params, device_manager),
+        "pivSelfSignCertificate" => {
+            handle_piv_self_sign_certificate(request.id, &request.params, device_manager)
+        }
+        "pivImportKey" => handle_piv_import_key(request.id, &mut request.params, device_manager),
+        "pivReset" => handle_piv_reset(request.id, &request.params, device_manager),
+        "pivInitialize" => handle_piv_initialize(request.id, &request.params, device_manager),
+        "openpgpGetStatus" => {
+            handle_openpgp_get_status(request.id, &request.params, device_manager)
+        }
+        "openpgpVerify" => handle_openpgp_verify(request.id, &request.params, device_manager),
+        "openpgpChangePin" => {
+            handle_openpgp_change_pin(request.id, &request.params, device_manager)
+        }
+        "openpgpChangeAdminPin" => {
+            handle_openpgp_change_admin_pin(request.id, &request.params, device_manager)
+        }
+        "openpgpSetResetCode" => {
+            handle_openpgp_set_reset_code(request.id, &request.params, device_manager)
+        }
+        "openpgpUnblockPin" => {
+            handle_openpgp_unblock_pin(request.id, &request.params, device_manager)
+        }
+        "openpgpSetAlgorithm" => {
+            handle_openpgp_set_algorithm(request.id, &request.params, device_manager)
+        }
+        "openpgpGenerateKey" => {
+            handle_openpgp_generate_key(request.id, &request.params, device_manager)
+        }
+        "openpgpImportKey" => {
+            handle_openpgp_import_key(request.id, &request.params, device_manager)
+        }
+        "openpgpReset" => handle_openpgp_reset(request.id, &request.params, device_manager),
+        "oathList" => handle_oath_list(request.id, &request.params, device_manager),
+        "oathCalculate" => handle_oath_calculate(request.id, &request.params, device_manager),
+        "oathCalculateAll" => {
+            handle_oath_calculate_all(request.id, &request.params, device_manager)
+        }
+        "oathPut" => handle_oath_put(request.id, &request.params, device_manager),
+        "oathDelete" => handle_oath_delete(request.id, &request.params, device_manager),
+        "oathRename" => handle_oath_rename(request.id, &request.params, device_manager),
+        "oathSetAccessCode" => {
+            handle_oath_set_access_code(request.id, &request.params, device_manager)
+        }
+        "oathValidate" => handle_oath_validate(request.id, &request.params, device_manager),
+        "oathImportUri" => handle_oath_import_uri(request.id, &request.params, device_manager),
+        "oathExportInventory" => {
+            handle_oath_export_inventory(request.id, &request.params, device_manager)
+        }
+        "ndefRead" => handle_ndef_read(request.id, &request.params, device_manager),
+        "ndefWrite" => handle_ndef_write(request.id, &request.params, device_manager),
+        "otpGetStatus" => handle_otp_get_status(request.id, &request.params, device_manager),
+        "otpProgramSlot" => handle_otp_program_slot(request.id, &request.params, device_manager),
+        "otpDeleteSlot" => handle_otp_delete_slot(request.id, &request.params, device_manager),
+        "otpSwapSlots" => handle_otp_swap_slots(request.id, &request.params, device_manager),
+        _ => Response::error_with(
+            request.id,
+            "UNKNOWN_COMMAND",
+            ErrorCategory::InvalidParams,
+            false,
+            &format!("Unknown command: {}", request.command),
+            None,
+        ),
+    }
+}
+
+fn main() -> io::Result<()> {
+    // Initialize logger
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .target(env_logger::Target::Stderr)
+        .init();
+
+    log::info!("Feitian SK Manager Native Host starting...");
+    log::info!("Version: {}", env!("CARGO_PKG_VERSION"));
+
+    // Initialize device manager
+    let device_manager = match device::DeviceManager::new() {
+        Ok(manager) => {
+            log::info!("Device manager initialized successfully");
+            manager
+        }
+        Err(e) => {
+            log::error!("Failed to initialize device manager: {}", e);
+            log::error!("The native host will still run, but device operations may fail");
+            // Continue anyway - some commands like ping and getVersion will still work
+            device::DeviceManager::new().unwrap_or_else(|_| {
+                panic!("Critical: Could not initialize device manager");
+            })
+        }
+    };
+
+    // Main message loop
+    loop {
+        // Read message length
+        let length = match read_message_length() {
+            Ok(len) => len,
+            Err(e) => {
+                if e.kind() == io::ErrorKind::UnexpectedEof {
+                    log::info!("Connection closed by client");
+                    break;
+                }
+                log::error!("Failed to read message length: {}", e);
+                continue;
+            }
+        };
+
+        // Validate message length
+        if length == 0 || length > 1024 * 1024 {
+            log::error!("Invalid message length: {}", length);
+            continue;
+        }
+
+        // Read message content
+        // The request may carry PINs and private keys
+        let message = match read_message(length) {
+            Ok(msg) => Zeroizing::new(msg),
+            Err(e) => {
+                log::error!("Failed to read message: {}", e);
+                continue;
+            }
+        };
+
+        // Parse request
+        let request: Request = match serde_json::from_str(&message) {
+            Ok(req) => req,
+            Err(e) => {
+                log::error!("Failed to parse request: {}", e);
+                // Send error response with id 0 if we can't parse the request
+                let error_response = Response::error_with(
+                    0,
+                    "INVALID_JSON",
+                    ErrorCategory::InvalidParams,
+                    false,
+                    &e.to_string(),
+                    None,
+                );
+                if let Ok(json) = serde_json::to_string(&error_response) {
+                    let _ = write_message(&json);
+                }
+                continue;
+            }
+        };
+
+        // Params and results may hold secrets, so only the envelope is logged
+        log::debug!("Received request {}: {}", request.id, request.command);
+
+        // Process request with device manager
+        let response = process_request(request, &device_manager);
+
+        // Send response
+        match serde_json::to_string(&response) {
+            Ok(json) => {
+                log::debug!("Sending response {}: {}", response.id, response.status);
+                if let Err(e) = write_message(&json) {
+                    log::error!("Failed to send response: {}", e);
+                    break;
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to serialize response: {}", e);
+            }
+        }
+    }
+
+    log::info!("Native host shutting down");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_command() {
+        let response = handle_ping(1);
+        assert_eq!(response.status, "ok");
+        assert!(response.result.is_some());
+        assert!(response.error.is_none());
+    }
+
+    #[test]
+    fn test_get_version_command() {
+        let response = handle_get_version(2);
+        assert_eq!(response.status, "ok");
+        assert!(response.result.is_some());
+
+        if let Some(result) = response.result {
+            assert!(result.get("version").is_some());
+            assert!(result.get("name").is_some());
+        }
+    }
 
     #[test]
     fn test_unknown_command() {
@@ -912,6 +3706,33 @@ mod tests {
         if let Some(error) = response.error {
             assert_eq!(error.code, "TEST_ERROR");
             assert_eq!(error.message, "Test error message");
+            assert_eq!(error.category, ErrorCategory::Internal);
         }
     }
+
+    #[test]
+    fn test_invalid_params_response() {
+        let response = Response::invalid_params(1, "Missing deviceId parameter");
+        let error = response.error.unwrap();
+        assert_eq!(error.code, "INVALID_PARAMS");
+        assert_eq!(error.category, ErrorCategory::InvalidParams);
+        assert!(!error.retryable);
+    }
+
+    #[test]
+    fn test_failure_uses_taxonomy_code() {
+        let err: anyhow::Error = Error::from_status_word(0x63, 0xC1).into();
+        let response = Response::failure(1, "PIV_GET_DATA_FAILED", "Failed to get PIV data", &err);
+        let error = response.error.unwrap();
+        assert_eq!(error.code, "PIN_INVALID");
+        assert_eq!(error.category, ErrorCategory::User);
+        assert!(error.retryable);
+        assert_eq!(error.details, Some(serde_json::json!({ "retries": 1 })));
+
+        let err = anyhow::anyhow!("something odd");
+        let response = Response::failure(1, "PIV_GET_DATA_FAILED", "Failed to get PIV data", &err);
+        let error = response.error.unwrap();
+        assert_eq!(error.code, "PIV_GET_DATA_FAILED");
+        assert_eq!(error.message, "Failed to get PIV data: something odd");
+    }
 }
@@ -0,0 +1,955 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::apdu::{self, ApduLog, Continuation, Redact};
+use crate::device::DeviceManager;
+use crate::error::Error;
+
+// NDEF Tag Application AID (Type 4 Tag)
+const NDEF_AID: [u8; 7] = [0xD2, 0x76, 0x00, 0x00, 0x85, 0x01, 0x01];
+const CC_FILE_ID: [u8; 2] = [0xE1, 0x03];
+
+// Instructions
+const INS_SELECT: u8 = 0xA4;
+const INS_READ_BINARY: u8 = 0xB0;
+const INS_UPDATE_BINARY: u8 = 0xD6;
+
+// SELECT by file identifier, first or only occurrence, no response data
+const SELECT_BY_FILE_ID: u8 = 0x00;
+const SELECT_NO_RESPONSE: u8 = 0x0C;
+
+// Capability container layout
+const CC_MIN_LEN: usize = 15;
+const TLV_NDEF_FILE_CONTROL: u8 = 0x04;
+const NDEF_FILE_CONTROL_LEN: u8 = 0x06;
+const ACCESS_GRANTED: u8 = 0x00;
+
+// NLEN prefix of the NDEF file
+const NLEN_LEN: usize = 2;
+
+// Short APDUs carry at most 255 bytes of data per command
+const MAX_SHORT_APDU_DATA: usize = 0xFF;
+
+// Record header flags
+const FLAG_MB: u8 = 0x80;
+const FLAG_ME: u8 = 0x40;
+const FLAG_CF: u8 = 0x20;
+const FLAG_SR: u8 = 0x10;
+const FLAG_IL: u8 = 0x08;
+const TNF_MASK: u8 = 0x07;
+
+// Type name formats
+const TNF_EMPTY: u8 = 0x00;
+const TNF_WELL_KNOWN: u8 = 0x01;
+const TNF_MIME: u8 = 0x02;
+
+// Well-known record types
+const RTD_URI: &[u8] = b"U";
+const RTD_TEXT: &[u8] = b"T";
+const RTD_SMART_POSTER: &[u8] = b"Sp";
+const RTD_ACTION: &[u8] = b"act";
+
+// Text record status byte
+const TEXT_UTF16: u8 = 0x80;
+const TEXT_LANGUAGE_MASK: u8 = 0x3F;
+
+/// URI identifier codes (NFC Forum URI RTD, table 3), indexed by code
+const URI_PREFIXES: [&str; 36] = [
+    "",
+    "http://www.",
+    "https://www.",
+    "http://",
+    "https://",
+    "tel:",
+    "mailto:",
+    "ftp://anonymous:anonymous@",
+    "ftp://ftp.",
+    "ftps://",
+    "sftp://",
+    "smb://",
+    "nfs://",
+    "ftp://",
+    "dav://",
+    "news:",
+    "telnet://",
+    "imap:",
+    "rtsp://",
+    "urn:",
+    "pop:",
+    "sip:",
+    "sips:",
+    "tftp:",
+    "btspp://",
+    "btl2cap://",
+    "btgoep://",
+    "tcpobex://",
+    "irdaobex://",
+    "file://",
+    "urn:epc:id:",
+    "urn:epc:tag:",
+    "urn:epc:pat:",
+    "urn:epc:raw:",
+    "urn:epc:",
+    "urn:nfc:",
+];
+
+/// Smart Poster recommended action
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmartPosterAction {
+    Do,
+    Save,
+    Edit,
+}
+
+impl SmartPosterAction {
+    fn code(self) -> u8 {
+        match self {
+            SmartPosterAction::Do => 0x00,
+            SmartPosterAction::Save => 0x01,
+            SmartPosterAction::Edit => 0x02,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0x00 => Some(SmartPosterAction::Do),
+            0x01 => Some(SmartPosterAction::Save),
+            0x02 => Some(SmartPosterAction::Edit),
+            _ => None,
+        }
+    }
+}
+
+/// Text with its IANA language code
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NdefText {
+    pub text: String,
+    #[serde(default = "default_language")]
+    pub language: String,
+}
+
+fn default_language() -> String {
+    "en".to_string()
+}
+
+/// A decoded NDEF record
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum NdefRecord {
+    Uri {
+        uri: String,
+    },
+    Text {
+        text: String,
+        #[serde(default = "default_language")]
+        language: String,
+    },
+    SmartPoster {
+        uri: String,
+        #[serde(default)]
+        titles: Vec<NdefText>,
+        #[serde(default)]
+        action: Option<SmartPosterAction>,
+    },
+    Mime {
+        mime_type: String,
+        /// Hex-encoded payload
+        data: String,
+    },
+    /// Any other record, kept so it can be shown and written back
+    Unknown {
+        tnf: u8,
+        /// Hex-encoded record type
+        record_type: String,
+        /// Hex-encoded payload
+        payload: String,
+    },
+}
+
+/// Capability container (E103)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NdefCapabilities {
+    pub mapping_version: String,
+    /// Maximum data read with one READ BINARY
+    pub max_le: u16,
+    /// Maximum data written with one UPDATE BINARY
+    pub max_lc: u16,
+    pub file_id: String,
+    /// NDEF file size, including the two-byte length
+    pub max_size: u16,
+    pub read_access: u8,
+    pub write_access: u8,
+}
+
+impl NdefCapabilities {
+    fn file_id_bytes(&self) -> Result<[u8; 2]> {
+        let bytes = hex::decode(&self.file_id)?;
+        bytes
+            .try_into()
+            .map_err(|_| anyhow!("NDEF file ID must be two bytes"))
+    }
+
+    /// Largest chunk for READ BINARY (`read`) or UPDATE BINARY
+    fn chunk_len(&self, read: bool) -> usize {
+        let limit = if read { self.max_le } else { self.max_lc };
+        usize::from(limit).clamp(1, MAX_SHORT_APDU_DATA)
+    }
+}
+
+/// NDEF message read from the tag
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NdefReadResult {
+    pub capabilities: NdefCapabilities,
+    pub records: Vec<NdefRecord>,
+    /// Hex-encoded raw NDEF message
+    pub message: String,
+    pub activity_log: Vec<ApduLog>,
+}
+
+/// Outcome of writing an NDEF message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NdefWriteResult {
+    pub capabilities: NdefCapabilities,
+    pub bytes_written: usize,
+    pub activity_log: Vec<ApduLog>,
+}
+
+/// Parse the capability container
+fn parse_capabilities(data: &[u8]) -> Result<NdefCapabilities> {
+    if data.len() < CC_MIN_LEN {
+        return Err(Error::MalformedResponse(format!(
+            "Capability container is {} bytes, expected at least {}",
+            data.len(),
+            CC_MIN_LEN
+        ))
+        .into());
+    }
+    if data[7] != TLV_NDEF_FILE_CONTROL || data[8] != NDEF_FILE_CONTROL_LEN {
+        return Err(Error::MalformedResponse(
+            "Capability container has no NDEF File Control TLV".to_string(),
+        )
+        .into());
+    }
+
+    let u16_at = |offset: usize| u16::from_be_bytes([data[offset], data[offset + 1]]);
+    Ok(NdefCapabilities {
+        mapping_version: format!("{}.{}", data[2] >> 4, data[2] & 0x0F),
+        max_le: u16_at(3),
+        max_lc: u16_at(5),
+        file_id: hex::encode_upper(&data[9..11]),
+        max_size: u16_at(11),
+        read_access: data[13],
+        write_access: data[14],
+    })
+}
+
+/// Split a URI into its identifier code and the remainder
+fn compress_uri(uri: &str) -> (u8, &str) {
+    URI_PREFIXES
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, prefix)| uri.starts_with(*prefix))
+        .max_by_key(|(_, prefix)| prefix.len())
+        .map(|(code, prefix)| (code as u8, &uri[prefix.len()..]))
+        .unwrap_or((0, uri))
+}
+
+fn decode_uri(payload: &[u8]) -> Result<String> {
+    let (&code, rest) = payload
+        .split_first()
+        .ok_or_else(|| Error::MalformedResponse("Empty URI record".to_string()))?;
+    let prefix = URI_PREFIXES.get(usize::from(code)).copied().unwrap_or("");
+    Ok(format!("{}{}", prefix, String::from_utf8_lossy(rest)))
+}
+
+fn encode_uri(uri: &str) -> Vec<u8> {
+    let (code, rest) = compress_uri(uri);
+    let mut payload = vec![code];
+    payload.extend_from_slice(rest.as_bytes());
+    payload
+}
+
+fn decode_text(payload: &[u8]) -> Result<NdefText> {
+    let (&status, rest) = payload
+        .split_first()
+        .ok_or_else(|| Error::MalformedResponse("Empty Text record".to_string()))?;
+    let language_len = usize::from(status & TEXT_LANGUAGE_MASK);
+    if rest.len() < language_len {
+        return Err(Error::MalformedResponse("Text record language overflows".to_string()).into());
+    }
+    let (language, text) = rest.split_at(language_len);
+
+    let text = if status & TEXT_UTF16 != 0 {
+        // Big-endian unless a byte order mark says otherwise
+        let (little_endian, text) = match text {
+            [0xFF, 0xFE, rest @ ..] => (true, rest),
+            [0xFE, 0xFF, rest @ ..] => (false, rest),
+            _ => (false, text),
+        };
+        let units: Vec<u16> = text
+            .chunks_exact(2)
+            .map(|pair| {
+                if little_endian {
+                    u16::from_le_bytes([pair[0], pair[1]])
+                } else {
+                    u16::from_be_bytes([pair[0], pair[1]])
+                }
+            })
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(text).into_owned()
+    };
+
+    Ok(NdefText {
+        text,
+        language: String::from_utf8_lossy(language).into_owned(),
+    })
+}
+
+fn encode_text(text: &str, language: &str) -> Result<Vec<u8>> {
+    if language.len() > usize::from(TEXT_LANGUAGE_MASK) {
+        return Err(Error::InvalidParams(format!("Language code too long: {}", language)).into());
+    }
+    let mut payload = vec![language.len() as u8];
+    payload.extend_from_slice(language.as_bytes());
+    payload.extend_from_slice(text.as_bytes());
+    Ok(payload)
+}
+
+/// A record before interpretation of its type
+struct RawRecord {
+    tnf: u8,
+    record_type: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+/// Split an NDEF message into raw records
+fn parse_raw_records(message: &[u8]) -> Result<Vec<RawRecord>> {
+    let malformed = |message: &str| Error::MalformedResponse(message.to_string());
+    let mut records = Vec::new();
+    let mut pos = 0;
+
+    while pos < message.len() {
+        let header = message[pos];
+        if header & FLAG_CF != 0 {
+            return Err(malformed("Chunked NDEF records are not supported").into());
+        }
+        let mut take = |len: usize| -> Result<&[u8]> {
+            let field = message
+                .get(pos..pos + len)
+                .ok_or_else(|| malformed("NDEF record is truncated"))?;
+            pos += len;
+            Ok(field)
+        };
+        take(1)?;
+
+        let type_len = usize::from(take(1)?[0]);
+        let payload_len = if header & FLAG_SR != 0 {
+            usize::from(take(1)?[0])
+        } else {
+            let bytes = take(4)?;
+            u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+        };
+        let id_len = if header & FLAG_IL != 0 {
+            usize::from(take(1)?[0])
+        } else {
+            0
+        };
+        let record_type = take(type_len)?.to_vec();
+        take(id_len)?;
+        let payload = take(payload_len)?.to_vec();
+
+        records.push(RawRecord {
+            tnf: header & TNF_MASK,
+            record_type,
+            payload,
+        });
+        if header & FLAG_ME != 0 {
+            break;
+        }
+    }
+    Ok(records)
+}
+
+fn decode_smart_poster(payload: &[u8]) -> Result<NdefRecord> {
+    let mut uri = None;
+    let mut titles = Vec::new();
+    let mut action = None;
+    for record in parse_raw_records(payload)? {
+        if record.tnf != TNF_WELL_KNOWN {
+            continue;
+        }
+        match record.record_type.as_slice() {
+            RTD_URI => uri = Some(decode_uri(&record.payload)?),
+            RTD_TEXT => titles.push(decode_text(&record.payload)?),
+            RTD_ACTION => {
+                action = record
+                    .payload
+                    .first()
+                    .copied()
+                    .and_then(SmartPosterAction::from_code)
+            }
+            _ => {}
+        }
+    }
+    Ok(NdefRecord::SmartPoster {
+        uri: uri.ok_or_else(|| {
+            Error::MalformedResponse("Smart Poster has no URI record".to_string())
+        })?,
+        titles,
+        action,
+    })
+}
+
+fn decode_record(record: RawRecord) -> Result<NdefRecord> {
+    match (record.tnf, record.record_type.as_slice()) {
+        (TNF_WELL_KNOWN, RTD_URI) => Ok(NdefRecord::Uri {
+            uri: decode_uri(&record.payload)?,
+        }),
+        (TNF_WELL_KNOWN, RTD_TEXT) => {
+            let text = decode_text(&record.payload)?;
+            Ok(NdefRecord::Text {
+                text: text.text,
+                language: text.language,
+            })
+        }
+        (TNF_WELL_KNOWN, RTD_SMART_POSTER) => decode_smart_poster(&record.payload),
+        (TNF_MIME, mime_type) => Ok(NdefRecord::Mime {
+            mime_type: String::from_utf8_lossy(mime_type).into_owned(),
+            data: hex::encode(&record.payload),
+        }),
+        _ => Ok(NdefRecord::Unknown {
+            tnf: record.tnf,
+            record_type: hex::encode(&record.record_type),
+            payload: hex::encode(&record.payload),
+        }),
+    }
+}
+
+/// Decode every record of an NDEF message
+pub fn decode_message(message: &[u8]) -> Result<Vec<NdefRecord>> {
+    parse_raw_records(message)?
+        .into_iter()
+        .filter(|record| record.tnf != TNF_EMPTY)
+        .map(decode_record)
+        .collect()
+}
+
+/// Encode raw records as a message, setting MB/ME and short-record flags
+fn encode_raw_records(records: &[RawRecord]) -> Vec<u8> {
+    let mut message = Vec::new();
+    for (i, record) in records.iter().enumerate() {
+        let mut header = record.tnf;
+        if i == 0 {
+            header |= FLAG_MB;
+        }
+        if i == records.len() - 1 {
+            header |= FLAG_ME;
+        }
+        let short = record.payload.len() <= usize::from(u8::MAX);
+        if short {
+            header |= FLAG_SR;
+        }
+
+        message.push(header);
+        message.push(record.record_type.len() as u8);
+        if short {
+            message.push(record.payload.len() as u8);
+        } else {
+            message.extend((record.payload.len() as u32).to_be_bytes());
+        }
+        message.extend_from_slice(&record.record_type);
+        message.extend_from_slice(&record.payload);
+    }
+    message
+}
+
+fn well_known(record_type: &[u8], payload: Vec<u8>) -> RawRecord {
+    RawRecord {
+        tnf: TNF_WELL_KNOWN,
+        record_type: record_type.to_vec(),
+        payload,
+    }
+}
+
+fn encode_record(record: &NdefRecord) -> Result<RawRecord> {
+    let invalid = |message: String| -> anyhow::Error { Error::InvalidParams(message).into() };
+    match record {
+        NdefRecord::Uri { uri } => Ok(well_known(RTD_URI, encode_uri(uri))),
+        NdefRecord::Text { text, language } => {
+            Ok(well_known(RTD_TEXT, encode_text(text, language)?))
+        }
+        NdefRecord::SmartPoster {
+            uri,
+            titles,
+            action,
+        } => {
+            let mut nested = vec![well_known(RTD_URI, encode_uri(uri))];
+            for title in titles {
+                nested.push(well_known(
+                    RTD_TEXT,
+                    encode_text(&title.text, &title.language)?,
+                ));
+            }
+            if let Some(action) = action {
+                nested.push(well_known(RTD_ACTION, vec![action.code()]));
+            }
+            Ok(well_known(RTD_SMART_POSTER, encode_raw_records(&nested)))
+        }
+        NdefRecord::Mime { mime_type, data } => {
+            if mime_type.is_empty() || mime_type.len() > usize::from(u8::MAX) {
+                return Err(invalid(format!("Invalid MIME type: {}", mime_type)));
+            }
+            Ok(RawRecord {
+                tnf: TNF_MIME,
+                record_type: mime_type.as_bytes().to_vec(),
+                payload: hex::decode(data)
+                    .map_err(|e| invalid(format!("MIME data must be hex: {}", e)))?,
+            })
+        }
+        NdefRecord::Unknown {
+            tnf,
+            record_type,
+            payload,
+        } => {
+            let record_type = hex::decode(record_type)
+                .map_err(|e| invalid(format!("Record type must be hex: {}", e)))?;
+            if record_type.len() > usize::from(u8::MAX) {
+                return Err(invalid(format!(
+                    "Record type is {} bytes, at most {} are allowed",
+                    record_type.len(),
+                    u8::MAX
+                )));
+            }
+            Ok(RawRecord {
+                tnf: tnf & TNF_MASK,
+                record_type,
+                payload: hex::decode(payload)
+                    .map_err(|e| invalid(format!("Record payload must be hex: {}", e)))?,
+            })
+        }
+    }
+}
+
+/// Encode records as an NDEF message
+///
+/// No records encodes the empty message (a single empty record), which
+/// clears the tag.
+pub fn encode_message(records: &[NdefRecord]) -> Result<Vec<u8>> {
+    if records.is_empty() {
+        return Ok(encode_raw_records(&[RawRecord {
+            tnf: TNF_EMPTY,
+            record_type: Vec::new(),
+            payload: Vec::new(),
+        }]));
+    }
+    let raw = records
+        .iter()
+        .map(encode_record)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(encode_raw_records(&raw))
+}
+
+fn build_select_file_apdu(file_id: [u8; 2]) -> Vec<u8> {
+    vec![
+        0x00,
+        INS_SELECT,
+        SELECT_BY_FILE_ID,
+        SELECT_NO_RESPONSE,
+        0x02,
+        file_id[0],
+        file_id[1],
+    ]
+}
+
+fn build_read_binary_apdu(offset: usize, len: usize) -> Vec<u8> {
+    let [p1, p2] = (offset as u16).to_be_bytes();
+    vec![0x00, INS_READ_BINARY, p1, p2, len as u8]
+}
+
+fn build_update_binary_apdu(offset: usize, data: &[u8]) -> Vec<u8> {
+    let [p1, p2] = (offset as u16).to_be_bytes();
+    let mut apdu = vec![0x00, INS_UPDATE_BINARY, p1, p2, data.len() as u8];
+    apdu.extend_from_slice(data);
+    apdu
+}
+
+fn transmit(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    apdu: &[u8],
+    command_name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    apdu::transmit(
+        device_manager,
+        device_id,
+        apdu,
+        Redact::Nothing,
+        command_name,
+        activity_log,
+    )
+}
+
+/// Select a file, failing when it does not exist
+fn select_file(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    file_id: [u8; 2],
+    name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<()> {
+    select(
+        device_manager,
+        device_id,
+        &build_select_file_apdu(file_id),
+        name,
+        activity_log,
+    )
+}
+
+/// SELECT an application or file, reporting 6A 82 as not supported
+fn select(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    apdu: &[u8],
+    name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<()> {
+    let command_name = format!("SELECT {}", name);
+    let response = apdu::exchange(
+        device_manager,
+        device_id,
+        apdu,
+        Continuation::GetResponse,
+        Redact::Nothing,
+        &command_name,
+        activity_log,
+    )?;
+    if response.sw1 == 0x6A && response.sw2 == 0x82 {
+        return Err(Error::NotSupported(format!("{} not found", name)).into());
+    }
+    response.into_data(&command_name)?;
+    Ok(())
+}
+
+/// Read `len` bytes from the selected file in chunks of at most `chunk`
+fn read_binary(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    offset: usize,
+    len: usize,
+    chunk: usize,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(len);
+    while data.len() < len {
+        let position = offset + data.len();
+        let want = chunk.min(len - data.len());
+        let response = transmit(
+            device_manager,
+            device_id,
+            &build_read_binary_apdu(position, want),
+            &format!("READ BINARY (offset {})", position),
+            activity_log,
+        )?;
+        if response.is_empty() {
+            return Err(
+                Error::MalformedResponse("READ BINARY returned no data".to_string()).into(),
+            );
+        }
+        data.extend_from_slice(&response[..response.len().min(want)]);
+    }
+    Ok(data)
+}
+
+/// Select the NDEF application and read the capability container
+fn read_capabilities(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<NdefCapabilities> {
+    select(
+        device_manager,
+        device_id,
+        &apdu::build_select_apdu(&NDEF_AID),
+        "NDEF Application",
+        activity_log,
+    )?;
+    select_file(
+        device_manager,
+        device_id,
+        CC_FILE_ID,
+        "Capability Container",
+        activity_log,
+    )?;
+    let header = read_binary(
+        device_manager,
+        device_id,
+        0,
+        CC_MIN_LEN,
+        CC_MIN_LEN,
+        activity_log,
+    )?;
+    parse_capabilities(&header)
+}
+
+/// Read and decode the NDEF message
+pub fn read(device_manager: &DeviceManager, device_id: &str) -> Result<NdefReadResult> {
+    log::debug!("Reading NDEF message...");
+
+    let mut activity_log = Vec::new();
+    let capabilities = read_capabilities(device_manager, device_id, &mut activity_log)?;
+    if capabilities.read_access != ACCESS_GRANTED {
+        return Err(Error::OperationDenied(format!(
+            "NDEF file is not readable (access 0x{:02X})",
+            capabilities.read_access
+        ))
+        .into());
+    }
+
+    select_file(
+        device_manager,
+        device_id,
+        capabilities.file_id_bytes()?,
+        "NDEF File",
+        &mut activity_log,
+    )?;
+    let chunk = capabilities.chunk_len(true);
+    let nlen = read_binary(
+        device_manager,
+        device_id,
+        0,
+        NLEN_LEN,
+        chunk,
+        &mut activity_log,
+    )?;
+    let nlen = usize::from(u16::from_be_bytes([nlen[0], nlen[1]]));
+    if nlen + NLEN_LEN > usize::from(capabilities.max_size) {
+        return Err(Error::MalformedResponse(format!(
+            "NDEF length {} exceeds file size {}",
+            nlen, capabilities.max_size
+        ))
+        .into());
+    }
+
+    let message = read_binary(
+        device_manager,
+        device_id,
+        NLEN_LEN,
+        nlen,
+        chunk,
+        &mut activity_log,
+    )?;
+    let records = decode_message(&message)?;
+
+    log::info!("Read {} NDEF records", records.len());
+    Ok(NdefReadResult {
+        capabilities,
+        records,
+        message: hex::encode(&message),
+        activity_log,
+    })
+}
+
+/// Encode and write an NDEF message
+///
+/// NLEN is zeroed first and written last, so an interrupted write leaves an
+/// empty tag rather than a truncated message.
+pub fn write(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    records: &[NdefRecord],
+) -> Result<NdefWriteResult> {
+    let message = encode_message(records)?;
+    log::debug!("Writing {}-byte NDEF message...", message.len());
+
+    let mut activity_log = Vec::new();
+    let capabilities = read_capabilities(device_manager, device_id, &mut activity_log)?;
+    if capabilities.write_access != ACCESS_GRANTED {
+        return Err(Error::OperationDenied(format!(
+            "NDEF file is read-only (access 0x{:02X})",
+            capabilities.write_access
+        ))
+        .into());
+    }
+    let capacity = usize::from(capabilities.max_size).saturating_sub(NLEN_LEN);
+    if message.len() > capacity {
+        return Err(Error::InvalidParams(format!(
+            "NDEF message is {} bytes but the tag holds at most {}",
+            message.len(),
+            capacity
+        ))
+        .into());
+    }
+
+    select_file(
+        device_manager,
+        device_id,
+        capabilities.file_id_bytes()?,
+        "NDEF File",
+        &mut activity_log,
+    )?;
+    transmit(
+        device_manager,
+        device_id,
+        &build_update_binary_apdu(0, &[0x00, 0x00]),
+        "UPDATE BINARY (clear NLEN)",
+        &mut activity_log,
+    )?;
+
+    let chunk = capabilities.chunk_len(false);
+    for (i, part) in message.chunks(chunk).enumerate() {
+        let offset = NLEN_LEN + i * chunk;
+        transmit(
+            device_manager,
+            device_id,
+            &build_update_binary_apdu(offset, part),
+            &format!("UPDATE BINARY (offset {})", offset),
+            &mut activity_log,
+        )?;
+    }
+
+    transmit(
+        device_manager,
+        device_id,
+        &build_update_binary_apdu(0, &(message.len() as u16).to_be_bytes()),
+        "UPDATE BINARY (NLEN)",
+        &mut activity_log,
+    )?;
+
+    log::info!("Wrote {}-byte NDEF message", message.len());
+    Ok(NdefWriteResult {
+        capabilities,
+        bytes_written: message.len(),
+        activity_log,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_capabilities() {
+        let cc = hex::decode("000F2000FF00FF0406E10400FF00FF").unwrap();
+        let capabilities = parse_capabilities(&cc).unwrap();
+        assert_eq!(capabilities.mapping_version, "2.0");
+        assert_eq!((capabilities.max_le, capabilities.max_lc), (0xFF, 0xFF));
+        assert_eq!(capabilities.file_id, "E104");
+        assert_eq!(capabilities.max_size, 0xFF);
+        assert_eq!(capabilities.write_access, 0xFF);
+        assert_eq!(capabilities.chunk_len(true), 0xFF);
+
+        assert!(parse_capabilities(&cc[..10]).is_err());
+        let mut bad = cc.clone();
+        bad[7] = 0x05;
+        assert!(parse_capabilities(&bad).is_err());
+    }
+
+    #[test]
+    fn test_uri_record_round_trip() {
+        let message = encode_message(&[NdefRecord::Uri {
+            uri: "https://www.example.com/start".to_string(),
+        }])
+        .unwrap();
+        assert_eq!(
+            message,
+            [&[0xD1, 0x01, 0x12, b'U', 0x02][..], b"example.com/start"].concat()
+        );
+        assert_eq!(
+            decode_message(&message).unwrap(),
+            vec![NdefRecord::Uri {
+                uri: "https://www.example.com/start".to_string()
+            }]
+        );
+        assert_eq!(compress_uri("urn:epc:id:x"), (0x1E, "x"));
+        assert_eq!(compress_uri("custom:x"), (0x00, "custom:x"));
+    }
+
+    #[test]
+    fn test_text_and_mime_records() {
+        let records = vec![
+            NdefRecord::Text {
+                text: "Hello".to_string(),
+                language: "en".to_string(),
+            },
+            NdefRecord::Mime {
+                mime_type: "text/vcard".to_string(),
+                data: hex::encode("BEGIN:VCARD"),
+            },
+        ];
+        let message = encode_message(&records).unwrap();
+        assert_eq!(&message[..5], &[0x91, 0x01, 0x08, b'T', 0x02]);
+        assert_eq!(decode_message(&message).unwrap(), records);
+
+        // UTF-16 with a little-endian byte order mark
+        let text = decode_text(&[0x82, b'd', b'e', 0xFF, 0xFE, b'H', 0x00, b'i', 0x00]).unwrap();
+        assert_eq!((text.text.as_str(), text.language.as_str()), ("Hi", "de"));
+    }
+
+    #[test]
+    fn test_smart_poster_and_long_records() {
+        let poster = NdefRecord::SmartPoster {
+            uri: "https://example.com".to_string(),
+            titles: vec![NdefText {
+                text: "Welcome".to_string(),
+                language: "en".to_string(),
+            }],
+            action: Some(SmartPosterAction::Do),
+        };
+        let message = encode_message(std::slice::from_ref(&poster)).unwrap();
+        assert_eq!(&message[..4], &[0xD1, 0x02, message.len() as u8 - 5, b'S']);
+        assert_eq!(decode_message(&message).unwrap(), vec![poster]);
+
+        let long = NdefRecord::Mime {
+            mime_type: "application/octet-stream".to_string(),
+            data: "00".repeat(300),
+        };
+        let message = encode_message(std::slice::from_ref(&long)).unwrap();
+        assert_eq!(message[0], 0xC2);
+        assert_eq!(&message[2..6], &300u32.to_be_bytes());
+        assert_eq!(decode_message(&message).unwrap(), vec![long]);
+
+        assert!(decode_message(&[0xD1, 0x01, 0x05, b'U']).is_err());
+        let oversized = NdefRecord::Unknown {
+            tnf: 0x04,
+            record_type: "61".repeat(256),
+            payload: String::new(),
+        };
+        let err = encode_message(&[oversized]).unwrap_err();
+        assert!(matches!(
+            crate::error::classify(&err),
+            Some(Error::InvalidParams(_))
+        ));
+        assert_eq!(
+            decode_message(&encode_message(&[]).unwrap()).unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_record_json() {
+        let record: NdefRecord =
+            serde_json::from_str(r#"{"type":"smartPoster","uri":"https://example.com"}"#).unwrap();
+        assert_eq!(
+            record,
+            NdefRecord::SmartPoster {
+                uri: "https://example.com".to_string(),
+                titles: vec![],
+                action: None,
+            }
+        );
+        let text: NdefRecord = serde_json::from_str(r#"{"type":"text","text":"hi"}"#).unwrap();
+        assert_eq!(
+            text,
+            NdefRecord::Text {
+                text: "hi".to_string(),
+                language: "en".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,1646 @@
+use anyhow::{anyhow, Context, Result};
+use base64ct::{Base64UrlUnpadded, Encoding};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+use crate::apdu::{self, ApduLog, Continuation, Redact};
+use crate::device::DeviceManager;
+use crate::error::Error;
+use crate::tlv;
+
+// YKOATH application AID
+const OATH_AID: [u8; 7] = [0xA0, 0x00, 0x00, 0x05, 0x27, 0x21, 0x01];
+
+// Instructions
+const INS_PUT: u8 = 0x01;
+const INS_DELETE: u8 = 0x02;
+const INS_SET_CODE: u8 = 0x03;
+const INS_RENAME: u8 = 0x05;
+const INS_LIST: u8 = 0xA1;
+const INS_CALCULATE: u8 = 0xA2;
+const INS_VALIDATE: u8 = 0xA3;
+const INS_CALCULATE_ALL: u8 = 0xA4;
+const INS_SEND_REMAINING: u8 = 0xA5;
+
+// CALCULATE / CALCULATE ALL P2: return truncated codes
+const TRUNCATED_RESPONSE: u8 = 0x01;
+
+// Tags
+const TAG_NAME: u8 = 0x71;
+const TAG_NAME_LIST: u8 = 0x72;
+const TAG_KEY: u8 = 0x73;
+const TAG_CHALLENGE: u8 = 0x74;
+const TAG_RESPONSE: u8 = 0x75;
+const TAG_TRUNCATED: u8 = 0x76;
+const TAG_HOTP: u8 = 0x77;
+const TAG_PROPERTY: u8 = 0x78;
+const TAG_VERSION: u8 = 0x79;
+const TAG_IMF: u8 = 0x7A;
+const TAG_ALGORITHM: u8 = 0x7B;
+const TAG_TOUCH: u8 = 0x7C;
+
+// Credential type (high nibble) and hash algorithm (low nibble)
+const TYPE_HOTP: u8 = 0x10;
+const TYPE_TOTP: u8 = 0x20;
+const TYPE_MASK: u8 = 0xF0;
+const ALGORITHM_SHA1: u8 = 0x01;
+const ALGORITHM_SHA256: u8 = 0x02;
+const ALGORITHM_SHA512: u8 = 0x03;
+const ALGORITHM_MASK: u8 = 0x0F;
+
+const PROPERTY_REQUIRE_TOUCH: u8 = 0x02;
+
+// Access code key derivation (PBKDF2-HMAC-SHA1 over the device salt)
+const ACCESS_KEY_ITERATIONS: u32 = 1000;
+const ACCESS_KEY_LEN: usize = 16;
+const CHALLENGE_LEN: usize = 8;
+
+// Credential limits
+const MAX_NAME_LEN: usize = 64;
+const MIN_SECRET_LEN: usize = 14;
+const MIN_DIGITS: u8 = 6;
+const MAX_DIGITS: u8 = 8;
+pub const DEFAULT_DIGITS: u8 = 6;
+pub const DEFAULT_PERIOD: u32 = 30;
+
+// Device ID: unpadded URL-safe base64 of the first 16 bytes of SHA-256(salt)
+const DEVICE_ID_LEN: usize = 16;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+const OTPAUTH_SCHEME: &str = "otpauth://";
+
+const INVENTORY_CSV_HEADER: &str = "issuer,account,type,algorithm,digits,period,touch";
+
+/// Credential type
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OathType {
+    Hotp,
+    Totp,
+}
+
+impl OathType {
+    /// Parse the name used in command parameters and otpauth URIs
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "hotp" => Some(OathType::Hotp),
+            "totp" => Some(OathType::Totp),
+            _ => None,
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            OathType::Hotp => TYPE_HOTP,
+            OathType::Totp => TYPE_TOTP,
+        }
+    }
+}
+
+/// HMAC hash algorithm of a credential
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum OathAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl OathAlgorithm {
+    /// Parse the name used in command parameters and otpauth URIs
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().replace('-', "").as_str() {
+            "SHA1" => Some(OathAlgorithm::Sha1),
+            "SHA256" => Some(OathAlgorithm::Sha256),
+            "SHA512" => Some(OathAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            OathAlgorithm::Sha1 => ALGORITHM_SHA1,
+            OathAlgorithm::Sha256 => ALGORITHM_SHA256,
+            OathAlgorithm::Sha512 => ALGORITHM_SHA512,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        match code & ALGORITHM_MASK {
+            ALGORITHM_SHA1 => Some(OathAlgorithm::Sha1),
+            ALGORITHM_SHA256 => Some(OathAlgorithm::Sha256),
+            ALGORITHM_SHA512 => Some(OathAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    fn block_size(self) -> usize {
+        match self {
+            OathAlgorithm::Sha1 | OathAlgorithm::Sha256 => 64,
+            OathAlgorithm::Sha512 => 128,
+        }
+    }
+
+    fn hmac(self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        fn mac<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+            let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+            mac.update(message);
+            mac.finalize().into_bytes().to_vec()
+        }
+        match self {
+            OathAlgorithm::Sha1 => mac::<Hmac<Sha1>>(key, message),
+            OathAlgorithm::Sha256 => mac::<Hmac<Sha256>>(key, message),
+            OathAlgorithm::Sha512 => mac::<Hmac<Sha512>>(key, message),
+        }
+    }
+
+    /// Keys longer than the hash block are hashed first, as HMAC would
+    fn shorten_key(self, secret: &[u8]) -> Zeroizing<Vec<u8>> {
+        if secret.len() <= self.block_size() {
+            return Zeroizing::new(secret.to_vec());
+        }
+        Zeroizing::new(match self {
+            OathAlgorithm::Sha1 => sha1::Sha1::digest(secret).to_vec(),
+            OathAlgorithm::Sha256 => Sha256::digest(secret).to_vec(),
+            OathAlgorithm::Sha512 => Sha512::digest(secret).to_vec(),
+        })
+    }
+}
+
+/// A credential stored in the OATH application
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OathCredential {
+    /// Name as stored on the key, including any period prefix
+    pub name: String,
+    pub issuer: Option<String>,
+    pub account: String,
+    pub oath_type: OathType,
+    /// Absent when the card did not report it (CALCULATE ALL)
+    pub algorithm: Option<OathAlgorithm>,
+    /// TOTP time step in seconds
+    pub period: Option<u32>,
+}
+
+/// A calculated code, or the reason none was returned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OathCode {
+    pub credential: OathCredential,
+    pub code: Option<String>,
+    pub digits: Option<u8>,
+    /// Unix time the TOTP code becomes valid
+    pub valid_from: Option<u64>,
+    /// Unix time the TOTP code expires
+    pub valid_to: Option<u64>,
+    /// The credential needs a touch, so it was left out of CALCULATE ALL
+    pub touch_required: bool,
+}
+
+/// OATH application status from SELECT
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OathStatus {
+    pub version: String,
+    pub device_id: String,
+    pub access_code_set: bool,
+}
+
+/// Credential list with activity logs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OathListResult {
+    pub status: OathStatus,
+    pub credentials: Vec<OathCredential>,
+    pub activity_log: Vec<ApduLog>,
+}
+
+/// Calculated codes with activity logs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OathCalculateResult {
+    pub timestamp: u64,
+    pub codes: Vec<OathCode>,
+    pub activity_log: Vec<ApduLog>,
+}
+
+/// Outcome of importing otpauth URIs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OathImportResult {
+    pub imported: Vec<OathCredential>,
+    pub activity_log: Vec<ApduLog>,
+}
+
+/// Non-secret description of a stored credential
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OathInventoryEntry {
+    pub issuer: Option<String>,
+    pub account: String,
+    pub oath_type: OathType,
+    pub algorithm: Option<OathAlgorithm>,
+    pub digits: Option<u8>,
+    pub period: Option<u32>,
+    /// Unknown for HOTP, which CALCULATE ALL does not evaluate
+    pub touch: Option<bool>,
+}
+
+/// Credential inventory with activity logs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OathInventoryResult {
+    pub entries: Vec<OathInventoryEntry>,
+    pub activity_log: Vec<ApduLog>,
+}
+
+/// A credential to store with PUT
+#[derive(Debug, Clone)]
+pub struct OathCredentialData {
+    pub issuer: Option<String>,
+    pub account: String,
+    /// Shared secret, already decoded
+    pub secret: Zeroizing<Vec<u8>>,
+    pub oath_type: OathType,
+    pub algorithm: OathAlgorithm,
+    pub digits: u8,
+    pub period: u32,
+    pub counter: u32,
+    pub touch: bool,
+}
+
+impl OathCredentialData {
+    /// Name stored on the key: `[period/][issuer:]account`
+    pub fn name(&self) -> String {
+        format_name(
+            self.oath_type,
+            self.period,
+            self.issuer.as_deref(),
+            &self.account,
+        )
+    }
+
+    /// Check the fields the OATH application would reject
+    pub fn validate(&self) -> Result<()> {
+        if self.account.is_empty() {
+            return Err(Error::InvalidParams("Account name must not be empty".to_string()).into());
+        }
+        if self.name().len() > MAX_NAME_LEN {
+            return Err(Error::InvalidParams(format!(
+                "Credential name exceeds {} bytes",
+                MAX_NAME_LEN
+            ))
+            .into());
+        }
+        if self.secret.is_empty() {
+            return Err(Error::InvalidParams("Secret must not be empty".to_string()).into());
+        }
+        if !(MIN_DIGITS..=MAX_DIGITS).contains(&self.digits) {
+            return Err(Error::InvalidParams(format!(
+                "Digits must be between {} and {}",
+                MIN_DIGITS, MAX_DIGITS
+            ))
+            .into());
+        }
+        if self.oath_type == OathType::Totp && self.period == 0 {
+            return Err(Error::InvalidParams("Period must be positive".to_string()).into());
+        }
+        Ok(())
+    }
+}
+
+/// Decode an RFC 4648 base32 secret
+///
+/// Whitespace, padding and lowercase letters are accepted, as authenticator
+/// setup pages commonly show them.
+pub fn decode_base32(input: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let mut out = Zeroizing::new(Vec::with_capacity(input.len() * 5 / 8));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&b| b == c.to_ascii_uppercase() as u8)
+            .ok_or_else(|| Error::InvalidParams(format!("Invalid base32 character: {}", c)))?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Ok(out)
+}
+
+/// Build the stored name: TOTP periods other than 30 seconds become a prefix
+pub fn format_name(
+    oath_type: OathType,
+    period: u32,
+    issuer: Option<&str>,
+    account: &str,
+) -> String {
+    let mut name = String::new();
+    if oath_type == OathType::Totp && period != DEFAULT_PERIOD {
+        name.push_str(&format!("{}/", period));
+    }
+    if let Some(issuer) = issuer.filter(|i| !i.is_empty()) {
+        name.push_str(issuer);
+        name.push(':');
+    }
+    name.push_str(account);
+    name
+}
+
+/// Split a stored name into period, issuer and account
+fn parse_name(name: &str, oath_type: OathType) -> (Option<u32>, Option<String>, String) {
+    let mut rest = name;
+    let mut period = None;
+    if oath_type == OathType::Totp {
+        period = Some(DEFAULT_PERIOD);
+        if let Some((prefix, remainder)) = rest.split_once('/') {
+            if let Ok(value) = prefix.parse::<u32>() {
+                period = Some(value);
+                rest = remainder;
+            }
+        }
+    }
+    match rest.split_once(':') {
+        Some((issuer, account)) if !issuer.is_empty() => {
+            (period, Some(issuer.to_string()), account.to_string())
+        }
+        _ => (period, None, rest.to_string()),
+    }
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|d| d as u8)
+}
+
+/// Percent-decode a URI component; `plus_as_space` applies to query values
+fn percent_decode(input: &str, plus_as_space: bool) -> Result<String> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let value = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|pair| Some(hex_value(pair[0])? << 4 | hex_value(pair[1])?))
+                    .ok_or_else(|| {
+                        Error::InvalidParams(format!("Invalid percent-encoding in {}", input))
+                    })?;
+                out.push(value);
+                i += 3;
+            }
+            b'+' if plus_as_space => {
+                out.push(b' ');
+                i += 1;
+            }
+            byte => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(out)
+        .map_err(|_| Error::InvalidParams(format!("{} is not valid UTF-8", input)).into())
+}
+
+/// Parse an `otpauth://` URI as produced by authenticator QR codes
+///
+/// The label is `[issuer:]account`; an `issuer` parameter must agree with the
+/// label prefix when both are present. Defaults follow the Key URI format:
+/// SHA1, 6 digits, 30 second period and counter 0. Unknown parameters such as
+/// `image` are ignored.
+pub fn parse_otpauth_uri(uri: &str) -> Result<OathCredentialData> {
+    let invalid = |message: String| -> anyhow::Error { Error::InvalidParams(message).into() };
+
+    let uri = uri.trim();
+    let rest = uri
+        .get(..OTPAUTH_SCHEME.len())
+        .filter(|scheme| scheme.eq_ignore_ascii_case(OTPAUTH_SCHEME))
+        .map(|_| &uri[OTPAUTH_SCHEME.len()..])
+        .ok_or_else(|| invalid("URI must start with otpauth://".to_string()))?;
+    let (kind, rest) = rest
+        .split_once('/')
+        .ok_or_else(|| invalid("URI has no label".to_string()))?;
+    let oath_type = OathType::from_name(kind)
+        .ok_or_else(|| invalid(format!("Unsupported OTP type: {}", kind)))?;
+    let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+    let label = percent_decode(label, false)?;
+    let (label_issuer, account) = match label.split_once(':') {
+        Some((issuer, account)) => (Some(issuer.trim().to_string()), account.trim().to_string()),
+        None => (None, label.trim().to_string()),
+    };
+
+    let mut params: Vec<(String, String)> = Vec::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let key = percent_decode(key, true)?.to_ascii_lowercase();
+        if params.iter().any(|(k, _)| *k == key) {
+            return Err(invalid(format!("Duplicate {} parameter", key)));
+        }
+        params.push((key, percent_decode(value, true)?));
+    }
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.trim())
+    };
+    let number = |name: &str, default: u32| -> Result<u32> {
+        match param(name) {
+            Some(value) => value
+                .parse::<u32>()
+                .map_err(|_| invalid(format!("{} must be a non-negative integer", name))),
+            None => Ok(default),
+        }
+    };
+
+    let issuer = match (label_issuer.filter(|i| !i.is_empty()), param("issuer")) {
+        (Some(label), Some(param)) if !param.is_empty() && label != param => {
+            return Err(invalid(format!(
+                "Issuer parameter {} does not match label issuer {}",
+                param, label
+            )));
+        }
+        (Some(label), _) => Some(label),
+        (None, Some(param)) if !param.is_empty() => Some(param.to_string()),
+        _ => None,
+    };
+
+    let secret = decode_base32(
+        param("secret").ok_or_else(|| invalid("URI has no secret parameter".to_string()))?,
+    )?;
+    let algorithm = match param("algorithm") {
+        Some(name) => OathAlgorithm::from_name(name)
+            .ok_or_else(|| invalid(format!("Unsupported algorithm: {}", name)))?,
+        None => OathAlgorithm::Sha1,
+    };
+    let digits = u8::try_from(number("digits", u32::from(DEFAULT_DIGITS))?)
+        .map_err(|_| invalid("digits is out of range".to_string()))?;
+
+    let credential = OathCredentialData {
+        issuer,
+        account,
+        secret,
+        oath_type,
+        algorithm,
+        digits,
+        period: number("period", DEFAULT_PERIOD)?,
+        counter: number("counter", 0)?,
+        touch: false,
+    };
+    credential.validate()?;
+    Ok(credential)
+}
+
+fn credential(
+    name: String,
+    oath_type: OathType,
+    algorithm: Option<OathAlgorithm>,
+) -> OathCredential {
+    let (period, issuer, account) = parse_name(&name, oath_type);
+    OathCredential {
+        name,
+        issuer,
+        account,
+        oath_type,
+        algorithm,
+        period,
+    }
+}
+
+/// Format a truncated (31-bit) response as a code of `digits` digits
+fn format_code(value: u32, digits: u8) -> String {
+    let value = value & 0x7FFF_FFFF;
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(u32::from(digits)),
+        width = usize::from(digits)
+    )
+}
+
+/// Parse a truncated response: digit count followed by four bytes
+fn parse_truncated(value: &[u8]) -> Result<String> {
+    match value {
+        [digits, a, b, c, d] => Ok(format_code(u32::from_be_bytes([*a, *b, *c, *d]), *digits)),
+        _ => Err(Error::MalformedResponse("Truncated response must be 5 bytes".to_string()).into()),
+    }
+}
+
+/// TOTP moving factor for a Unix time
+fn time_challenge(timestamp: u64, period: u32) -> [u8; CHALLENGE_LEN] {
+    (timestamp / u64::from(period)).to_be_bytes()
+}
+
+fn totp_window(timestamp: u64, period: u32) -> (u64, u64) {
+    let start = timestamp - timestamp % u64::from(period);
+    (start, start + u64::from(period))
+}
+
+/// Append one simple TLV (all OATH lengths fit in one or three bytes)
+fn push_tlv(data: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    data.push(tag);
+    data.extend(tlv::encode_length(value.len()));
+    data.extend_from_slice(value);
+}
+
+fn build_apdu(ins: u8, p1: u8, p2: u8, data: &[u8]) -> Vec<u8> {
+    let mut apdu = vec![0x00, ins, p1, p2, data.len() as u8];
+    apdu.extend_from_slice(data);
+    apdu
+}
+
+/// Build the PUT data field for a credential
+fn build_put_data(credential: &OathCredentialData) -> Result<Zeroizing<Vec<u8>>> {
+    credential.validate()?;
+    let mut secret = credential.algorithm.shorten_key(&credential.secret);
+    if secret.len() < MIN_SECRET_LEN {
+        secret.resize(MIN_SECRET_LEN, 0);
+    }
+
+    let mut key = Zeroizing::new(vec![
+        credential.oath_type.code() | credential.algorithm.code(),
+        credential.digits,
+    ]);
+    key.extend_from_slice(&secret);
+
+    let mut data = Zeroizing::new(Vec::new());
+    push_tlv(&mut data, TAG_NAME, credential.name().as_bytes());
+    push_tlv(&mut data, TAG_KEY, &key);
+    if credential.touch {
+        data.extend([TAG_PROPERTY, PROPERTY_REQUIRE_TOUCH]);
+    }
+    if credential.oath_type == OathType::Hotp && credential.counter > 0 {
+        push_tlv(&mut data, TAG_IMF, &credential.counter.to_be_bytes());
+    }
+    Ok(data)
+}
+
+/// Parse the LIST response into credentials
+fn parse_list(data: &[u8]) -> Result<Vec<OathCredential>> {
+    let mut credentials = Vec::new();
+    for entry in tlv::parse(data)? {
+        if entry.tag != [TAG_NAME_LIST] {
+            continue;
+        }
+        let (&kind, name) = entry
+            .value
+            .split_first()
+            .ok_or_else(|| Error::MalformedResponse("Empty credential entry".to_string()))?;
+        let oath_type = match kind & TYPE_MASK {
+            TYPE_HOTP => OathType::Hotp,
+            TYPE_TOTP => OathType::Totp,
+            other => {
+                log::warn!("Skipping credential of unknown type 0x{:02X}", other);
+                continue;
+            }
+        };
+        credentials.push(credential(
+            String::from_utf8_lossy(name).into_owned(),
+            oath_type,
+            OathAlgorithm::from_code(kind),
+        ));
+    }
+    Ok(credentials)
+}
+
+/// Parse the CALCULATE ALL response: each name is followed by its result
+fn parse_calculate_all(data: &[u8], timestamp: u64) -> Result<Vec<OathCode>> {
+    let entries = tlv::parse(data)?;
+    let mut codes = Vec::new();
+    for pair in entries.chunks(2) {
+        let [name, result] = pair else {
+            return Err(Error::MalformedResponse("Credential without a result".to_string()).into());
+        };
+        if name.tag != [TAG_NAME] {
+            return Err(Error::MalformedResponse(format!(
+                "Expected credential name, found tag {}",
+                hex::encode(&name.tag)
+            ))
+            .into());
+        }
+        let name = String::from_utf8_lossy(&name.value).into_owned();
+        let oath_type = if result.tag == [TAG_HOTP] {
+            OathType::Hotp
+        } else {
+            OathType::Totp
+        };
+        let credential = credential(name, oath_type, None);
+
+        let mut code = OathCode {
+            credential,
+            code: None,
+            // Every result starts with the credential's digit count
+            digits: result.value.first().copied(),
+            valid_from: None,
+            valid_to: None,
+            touch_required: result.tag == [TAG_TOUCH],
+        };
+        if result.tag == [TAG_TRUNCATED] {
+            code.code = Some(parse_truncated(&result.value)?);
+            let (from, to) = totp_window(timestamp, DEFAULT_PERIOD);
+            code.valid_from = Some(from);
+            code.valid_to = Some(to);
+        }
+        codes.push(code);
+    }
+    Ok(codes)
+}
+
+/// Parsed SELECT response
+struct SelectResponse {
+    status: OathStatus,
+    /// Device salt for access key derivation
+    salt: Vec<u8>,
+    /// Pending challenge, present when an access code is set
+    challenge: Option<Vec<u8>>,
+    algorithm: OathAlgorithm,
+}
+
+/// Parse the SELECT response
+fn parse_select(data: &[u8]) -> Result<SelectResponse> {
+    let entries = tlv::parse(data)?;
+    let version = tlv::find(&entries, &[TAG_VERSION])
+        .map(|v| {
+            v.value
+                .iter()
+                .map(|b| b.to_string())
+                .collect::<Vec<_>>()
+                .join(".")
+        })
+        .unwrap_or_default();
+    let salt = tlv::find(&entries, &[TAG_NAME])
+        .map(|v| v.value.clone())
+        .ok_or_else(|| Error::MalformedResponse("OATH SELECT response has no salt".to_string()))?;
+    let challenge = tlv::find(&entries, &[TAG_CHALLENGE]).map(|v| v.value.clone());
+    let algorithm = tlv::find(&entries, &[TAG_ALGORITHM])
+        .and_then(|v| v.value.first().copied())
+        .and_then(OathAlgorithm::from_code)
+        .unwrap_or(OathAlgorithm::Sha1);
+
+    let status = OathStatus {
+        version,
+        device_id: Base64UrlUnpadded::encode_string(&Sha256::digest(&salt)[..DEVICE_ID_LEN]),
+        access_code_set: challenge.is_some(),
+    };
+    Ok(SelectResponse {
+        status,
+        salt,
+        challenge,
+        algorithm,
+    })
+}
+
+/// Derive the access key from an access code and the device salt
+fn derive_access_key(access_code: &str, salt: &[u8]) -> Zeroizing<[u8; ACCESS_KEY_LEN]> {
+    let mut key = Zeroizing::new([0u8; ACCESS_KEY_LEN]);
+    pbkdf2::pbkdf2_hmac::<Sha1>(
+        access_code.as_bytes(),
+        salt,
+        ACCESS_KEY_ITERATIONS,
+        key.as_mut(),
+    );
+    key
+}
+
+fn random_challenge() -> [u8; CHALLENGE_LEN] {
+    let mut challenge = [0u8; CHALLENGE_LEN];
+    for byte in challenge.iter_mut() {
+        *byte = rand::random::<u8>();
+    }
+    challenge
+}
+
+fn transmit(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    apdu: &[u8],
+    command_name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    transmit_redacted(
+        device_manager,
+        device_id,
+        apdu,
+        Redact::Nothing,
+        command_name,
+        activity_log,
+    )
+}
+
+/// Transmit a command whose data field must not appear in the log
+fn transmit_secret(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    apdu: &[u8],
+    command_name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    transmit_redacted(
+        device_manager,
+        device_id,
+        apdu,
+        Redact::Command,
+        command_name,
+        activity_log,
+    )
+}
+
+fn transmit_redacted(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    apdu: &[u8],
+    redact: Redact,
+    command_name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    apdu::transmit_with_continuation(
+        device_manager,
+        device_id,
+        apdu,
+        Continuation::Instruction {
+            ins: INS_SEND_REMAINING,
+            name: "SEND REMAINING",
+        },
+        redact,
+        command_name,
+        activity_log,
+    )
+}
+
+/// Answer the card's challenge and check its answer to ours
+fn validate_access_code(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    key: &[u8],
+    challenge: &[u8],
+    algorithm: OathAlgorithm,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<()> {
+    let our_challenge = random_challenge();
+    let mut data = Vec::new();
+    push_tlv(&mut data, TAG_RESPONSE, &algorithm.hmac(key, challenge));
+    push_tlv(&mut data, TAG_CHALLENGE, &our_challenge);
+
+    let response = transmit_secret(
+        device_manager,
+        device_id,
+        &build_apdu(INS_VALIDATE, 0x00, 0x00, &data),
+        "VALIDATE",
+        activity_log,
+    )
+    .map_err(|e| match crate::error::classify(&e) {
+        Some(Error::StatusWord {
+            sw1: 0x69,
+            sw2: 0x84,
+        })
+        | Some(Error::StatusWord {
+            sw1: 0x6A,
+            sw2: 0x80,
+        }) => anyhow::Error::new(Error::PinInvalid { retries: None }).context("Wrong access code"),
+        _ => e,
+    })?;
+
+    let card_response = tlv::find_path(&response, &[&[TAG_RESPONSE]])?
+        .ok_or_else(|| Error::MalformedResponse("VALIDATE returned no response".to_string()))?;
+    if card_response != algorithm.hmac(key, &our_challenge) {
+        return Err(Error::MalformedResponse(
+            "Card failed to prove knowledge of the access code".to_string(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Select the OATH application and unlock it if an access code is set
+fn open_session(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    access_code: Option<&str>,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<SelectResponse> {
+    let response = transmit(
+        device_manager,
+        device_id,
+        &apdu::build_select_apdu(&OATH_AID),
+        "SELECT OATH Application",
+        activity_log,
+    )?;
+    let session = parse_select(&response)?;
+
+    if let Some(challenge) = &session.challenge {
+        let access_code = access_code.ok_or_else(|| {
+            anyhow::Error::new(Error::PinRequired).context("OATH access code required")
+        })?;
+        let key = derive_access_key(access_code, &session.salt);
+        validate_access_code(
+            device_manager,
+            device_id,
+            key.as_ref(),
+            challenge,
+            session.algorithm,
+            activity_log,
+        )?;
+    }
+    Ok(session)
+}
+
+fn list_credentials(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<OathCredential>> {
+    let response = transmit(
+        device_manager,
+        device_id,
+        &[0x00, INS_LIST, 0x00, 0x00],
+        "LIST",
+        activity_log,
+    )?;
+    parse_list(&response)
+}
+
+/// Calculate one credential's code
+fn calculate_credential(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    credential: &OathCredential,
+    timestamp: u64,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<OathCode> {
+    let mut data = Vec::new();
+    push_tlv(&mut data, TAG_NAME, credential.name.as_bytes());
+    let window = match (credential.oath_type, credential.period) {
+        (OathType::Totp, Some(period)) if period > 0 => {
+            push_tlv(&mut data, TAG_CHALLENGE, &time_challenge(timestamp, period));
+            Some(totp_window(timestamp, period))
+        }
+        _ => {
+            push_tlv(&mut data, TAG_CHALLENGE, &[]);
+            None
+        }
+    };
+
+    let response = transmit(
+        device_manager,
+        device_id,
+        &build_apdu(INS_CALCULATE, 0x00, TRUNCATED_RESPONSE, &data),
+        &format!("CALCULATE ({})", credential.name),
+        activity_log,
+    )?;
+    let value = tlv::find_path(&response, &[&[TAG_TRUNCATED]])?
+        .ok_or_else(|| Error::MalformedResponse("CALCULATE returned no code".to_string()))?;
+
+    Ok(OathCode {
+        credential: credential.clone(),
+        code: Some(parse_truncated(&value)?),
+        digits: value.first().copied(),
+        valid_from: window.map(|(from, _)| from),
+        valid_to: window.map(|(_, to)| to),
+        touch_required: false,
+    })
+}
+
+/// Send CALCULATE ALL; HOTP counters are not advanced
+fn calculate_all_codes(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    timestamp: u64,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<OathCode>> {
+    let mut data = Vec::new();
+    push_tlv(
+        &mut data,
+        TAG_CHALLENGE,
+        &time_challenge(timestamp, DEFAULT_PERIOD),
+    );
+    let response = transmit(
+        device_manager,
+        device_id,
+        &build_apdu(INS_CALCULATE_ALL, 0x00, TRUNCATED_RESPONSE, &data),
+        "CALCULATE ALL",
+        activity_log,
+    )?;
+    parse_calculate_all(&response, timestamp)
+}
+
+fn find_credential(credentials: Vec<OathCredential>, name: &str) -> Result<OathCredential> {
+    credentials
+        .into_iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| Error::InvalidParams(format!("No OATH credential named {}", name)).into())
+}
+
+/// Unix time to calculate codes for, defaulting to now
+fn code_timestamp(timestamp: Option<u64>) -> Result<u64> {
+    match timestamp {
+        Some(timestamp) => Ok(timestamp),
+        None => Ok(SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| anyhow!("System clock is before 1970"))?
+            .as_secs()),
+    }
+}
+
+/// List the stored credentials
+pub fn list(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    access_code: Option<&str>,
+) -> Result<OathListResult> {
+    log::debug!("Listing OATH credentials...");
+
+    let mut activity_log = Vec::new();
+    let status = open_session(device_manager, device_id, access_code, &mut activity_log)?.status;
+    let credentials = list_credentials(device_manager, device_id, &mut activity_log)?;
+
+    log::info!("Found {} OATH credentials", credentials.len());
+    Ok(OathListResult {
+        status,
+        credentials,
+        activity_log,
+    })
+}
+
+/// Calculate the code of a single credential
+///
+/// HOTP credentials advance their counter; touch credentials wait for a touch.
+pub fn calculate(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    name: &str,
+    timestamp: Option<u64>,
+    access_code: Option<&str>,
+) -> Result<OathCalculateResult> {
+    let timestamp = code_timestamp(timestamp)?;
+    log::debug!("Calculating OATH code for {}...", name);
+
+    let mut activity_log = Vec::new();
+    open_session(device_manager, device_id, access_code, &mut activity_log)?;
+    let credential = find_credential(
+        list_credentials(device_manager, device_id, &mut activity_log)?,
+        name,
+    )?;
+    let code = calculate_credential(
+        device_manager,
+        device_id,
+        &credential,
+        timestamp,
+        &mut activity_log,
+    )?;
+
+    Ok(OathCalculateResult {
+        timestamp,
+        codes: vec![code],
+        activity_log,
+    })
+}
+
+/// Calculate the codes of all TOTP credentials
+///
+/// CALCULATE ALL uses a 30 second step, so credentials with another period
+/// are recalculated individually. HOTP and touch credentials are listed
+/// without a code.
+pub fn calculate_all(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    timestamp: Option<u64>,
+    access_code: Option<&str>,
+) -> Result<OathCalculateResult> {
+    let timestamp = code_timestamp(timestamp)?;
+    log::debug!("Calculating all OATH codes...");
+
+    let mut activity_log = Vec::new();
+    open_session(device_manager, device_id, access_code, &mut activity_log)?;
+
+    let mut codes = calculate_all_codes(device_manager, device_id, timestamp, &mut activity_log)?;
+    for code in codes.iter_mut() {
+        let period = code.credential.period.unwrap_or(DEFAULT_PERIOD);
+        if code.code.is_some() && period != DEFAULT_PERIOD {
+            *code = calculate_credential(
+                device_manager,
+                device_id,
+                &code.credential,
+                timestamp,
+                &mut activity_log,
+            )?;
+        }
+    }
+
+    log::info!("Calculated {} OATH codes", codes.len());
+    Ok(OathCalculateResult {
+        timestamp,
+        codes,
+        activity_log,
+    })
+}
+
+/// Store a credential, replacing any with the same name
+pub fn put(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    credential: &OathCredentialData,
+    access_code: Option<&str>,
+) -> Result<(OathCredential, Vec<ApduLog>)> {
+    let data = build_put_data(credential)?;
+    let name = credential.name();
+    log::debug!("Storing OATH credential {}...", name);
+
+    let mut activity_log = Vec::new();
+    open_session(device_manager, device_id, access_code, &mut activity_log)?;
+    transmit_secret(
+        device_manager,
+        device_id,
+        &build_apdu(INS_PUT, 0x00, 0x00, &data),
+        &format!("PUT ({})", name),
+        &mut activity_log,
+    )?;
+
+    log::info!("OATH credential {} stored", name);
+    let stored = self::credential(name, credential.oath_type, Some(credential.algorithm));
+    Ok((stored, activity_log))
+}
+
+/// Delete a credential
+pub fn delete(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    name: &str,
+    access_code: Option<&str>,
+) -> Result<Vec<ApduLog>> {
+    log::debug!("Deleting OATH credential {}...", name);
+
+    let mut activity_log = Vec::new();
+    open_session(device_manager, device_id, access_code, &mut activity_log)?;
+    let mut data = Vec::new();
+    push_tlv(&mut data, TAG_NAME, name.as_bytes());
+    transmit(
+        device_manager,
+        device_id,
+        &build_apdu(INS_DELETE, 0x00, 0x00, &data),
+        &format!("DELETE ({})", name),
+        &mut activity_log,
+    )?;
+
+    log::info!("OATH credential {} deleted", name);
+    Ok(activity_log)
+}
+
+/// Rename a credential, keeping its period prefix
+pub fn rename(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    name: &str,
+    new_issuer: Option<&str>,
+    new_account: &str,
+    access_code: Option<&str>,
+) -> Result<(OathCredential, Vec<ApduLog>)> {
+    log::debug!("Renaming OATH credential {}...", name);
+
+    let mut activity_log = Vec::new();
+    open_session(device_manager, device_id, access_code, &mut activity_log)?;
+    let current = find_credential(
+        list_credentials(device_manager, device_id, &mut activity_log)?,
+        name,
+    )?;
+    let new_name = format_name(
+        current.oath_type,
+        current.period.unwrap_or(DEFAULT_PERIOD),
+        new_issuer,
+        new_account,
+    );
+    if new_account.is_empty() || new_name.len() > MAX_NAME_LEN {
+        return Err(
+            Error::InvalidParams(format!("New name must be 1 to {} bytes", MAX_NAME_LEN)).into(),
+        );
+    }
+
+    let mut data = Vec::new();
+    push_tlv(&mut data, TAG_NAME, name.as_bytes());
+    push_tlv(&mut data, TAG_NAME, new_name.as_bytes());
+    transmit(
+        device_manager,
+        device_id,
+        &build_apdu(INS_RENAME, 0x00, 0x00, &data),
+        &format!("RENAME ({})", name),
+        &mut activity_log,
+    )?;
+
+    log::info!("OATH credential {} renamed to {}", name, new_name);
+    let renamed = self::credential(new_name, current.oath_type, current.algorithm);
+    Ok((renamed, activity_log))
+}
+
+/// Set, change or (with `new_access_code` of `None`) remove the access code
+pub fn set_access_code(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    access_code: Option<&str>,
+    new_access_code: Option<&str>,
+) -> Result<(OathStatus, Vec<ApduLog>)> {
+    if new_access_code == Some("") {
+        return Err(Error::InvalidParams("Access code must not be empty".to_string()).into());
+    }
+
+    let mut activity_log = Vec::new();
+    let session = open_session(device_manager, device_id, access_code, &mut activity_log)?;
+
+    let data = match new_access_code {
+        Some(code) => {
+            let key = derive_access_key(code, &session.salt);
+            let challenge = random_challenge();
+
+            let mut key_data = Zeroizing::new(vec![TYPE_TOTP | ALGORITHM_SHA1]);
+            key_data.extend_from_slice(key.as_ref());
+            let mut data = Zeroizing::new(Vec::new());
+            push_tlv(&mut data, TAG_KEY, &key_data);
+            push_tlv(&mut data, TAG_CHALLENGE, &challenge);
+            push_tlv(
+                &mut data,
+                TAG_RESPONSE,
+                &OathAlgorithm::Sha1.hmac(key.as_ref(), &challenge),
+            );
+            data
+        }
+        None => Zeroizing::new(vec![TAG_KEY, 0x00]),
+    };
+
+    transmit_secret(
+        device_manager,
+        device_id,
+        &build_apdu(INS_SET_CODE, 0x00, 0x00, &data),
+        "SET CODE",
+        &mut activity_log,
+    )?;
+
+    let mut status = session.status;
+    status.access_code_set = new_access_code.is_some();
+    log::info!(
+        "OATH access code {}",
+        if status.access_code_set {
+            "set"
+        } else {
+            "removed"
+        }
+    );
+    Ok((status, activity_log))
+}
+
+/// Check an access code, returning the application status
+pub fn validate(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    access_code: &str,
+) -> Result<(OathStatus, Vec<ApduLog>)> {
+    let mut activity_log = Vec::new();
+    let session = open_session(
+        device_manager,
+        device_id,
+        Some(access_code),
+        &mut activity_log,
+    )?;
+    Ok((session.status, activity_log))
+}
+
+/// Quote a CSV field when it contains a separator, quote or line break
+///
+/// Values a spreadsheet would read as a formula are prefixed with `'`.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// Render an inventory as CSV with a header row
+pub fn inventory_csv(entries: &[OathInventoryEntry]) -> String {
+    let mut csv = String::from(INVENTORY_CSV_HEADER);
+    csv.push('\n');
+    for entry in entries {
+        let fields = [
+            csv_field(entry.issuer.as_deref().unwrap_or_default()),
+            csv_field(&entry.account),
+            format!("{:?}", entry.oath_type).to_lowercase(),
+            entry
+                .algorithm
+                .map(|a| format!("{:?}", a).to_uppercase())
+                .unwrap_or_default(),
+            entry.digits.map(|d| d.to_string()).unwrap_or_default(),
+            entry.period.map(|p| p.to_string()).unwrap_or_default(),
+            entry.touch.map(|t| t.to_string()).unwrap_or_default(),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Combine LIST (type, algorithm) with CALCULATE ALL (digits, touch)
+fn build_inventory(
+    credentials: Vec<OathCredential>,
+    codes: &[OathCode],
+) -> Vec<OathInventoryEntry> {
+    credentials
+        .into_iter()
+        .map(|credential| {
+            let code = codes.iter().find(|c| c.credential.name == credential.name);
+            OathInventoryEntry {
+                issuer: credential.issuer,
+                account: credential.account,
+                oath_type: credential.oath_type,
+                algorithm: credential.algorithm,
+                digits: code.and_then(|c| c.digits),
+                period: credential.period,
+                touch: code.and_then(|c| {
+                    if c.touch_required {
+                        Some(true)
+                    } else if c.credential.oath_type == OathType::Hotp {
+                        None
+                    } else {
+                        Some(false)
+                    }
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Reject names that repeat within an import or already exist on the key
+///
+/// PUT silently overwrites a credential with the same name.
+fn check_import_names(
+    credentials: &[OathCredentialData],
+    existing: &[OathCredential],
+) -> Result<()> {
+    let mut names: Vec<String> = Vec::with_capacity(credentials.len());
+    for (index, credential) in credentials.iter().enumerate() {
+        let name = credential.name();
+        if names.contains(&name) {
+            return Err(Error::InvalidParams(format!(
+                "URI {}: {} appears more than once",
+                index + 1,
+                name
+            ))
+            .into());
+        }
+        if existing.iter().any(|c| c.name == name) {
+            return Err(Error::InvalidParams(format!(
+                "URI {}: {} already exists on the key",
+                index + 1,
+                name
+            ))
+            .into());
+        }
+        names.push(name);
+    }
+    Ok(())
+}
+
+/// Provision credentials from otpauth URIs
+///
+/// Every URI is validated, and checked against the others and the
+/// credentials already on the key, before anything is written. A PUT that
+/// fails part-way still leaves the credentials written before it on the key;
+/// the error names the failed credential and how many were imported.
+pub fn import_uris(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    uris: &[String],
+    touch: bool,
+    access_code: Option<&str>,
+) -> Result<OathImportResult> {
+    let mut credentials = Vec::with_capacity(uris.len());
+    for (index, uri) in uris.iter().enumerate() {
+        let mut credential = parse_otpauth_uri(uri)
+            .map_err(|e| Error::InvalidParams(format!("URI {}: {}", index + 1, e)))?;
+        credential.touch = touch;
+        credentials.push(credential);
+    }
+    log::debug!("Importing {} OATH credentials...", credentials.len());
+
+    let mut activity_log = Vec::new();
+    open_session(device_manager, device_id, access_code, &mut activity_log)?;
+    let existing = list_credentials(device_manager, device_id, &mut activity_log)?;
+    check_import_names(&credentials, &existing)?;
+
+    let mut imported = Vec::with_capacity(credentials.len());
+    for credential in &credentials {
+        let name = credential.name();
+        transmit_secret(
+            device_manager,
+            device_id,
+            &build_apdu(INS_PUT, 0x00, 0x00, &build_put_data(credential)?),
+            &format!("PUT ({})", name),
+            &mut activity_log,
+        )
+        .with_context(|| {
+            format!(
+                "Failed to import {}; {} of {} credentials were imported",
+                name,
+                imported.len(),
+                credentials.len()
+            )
+        })?;
+        imported.push(self::credential(
+            name,
+            credential.oath_type,
+            Some(credential.algorithm),
+        ));
+    }
+
+    log::info!("Imported {} OATH credentials", imported.len());
+    Ok(OathImportResult {
+        imported,
+        activity_log,
+    })
+}
+
+/// Describe the stored credentials without their secrets or codes
+pub fn inventory(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    access_code: Option<&str>,
+) -> Result<OathInventoryResult> {
+    log::debug!("Reading OATH inventory...");
+
+    let mut activity_log = Vec::new();
+    open_session(device_manager, device_id, access_code, &mut activity_log)?;
+    let credentials = list_credentials(device_manager, device_id, &mut activity_log)?;
+    let codes = calculate_all_codes(
+        device_manager,
+        device_id,
+        code_timestamp(None)?,
+        &mut activity_log,
+    )?;
+    let entries = build_inventory(credentials, &codes);
+
+    // The responses carry current codes, which do not belong in an export log
+    for entry in activity_log.iter_mut() {
+        if entry.command.starts_with("CALCULATE ALL") {
+            entry.response_hex = "[redacted]".to_string();
+        }
+    }
+
+    log::info!("OATH inventory has {} entries", entries.len());
+    Ok(OathInventoryResult {
+        entries,
+        activity_log,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_base32() {
+        assert_eq!(
+            decode_base32("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ")
+                .unwrap()
+                .as_slice(),
+            b"12345678901234567890"
+        );
+        assert_eq!(
+            decode_base32("mzxw6 ytb oi======").unwrap().as_slice(),
+            b"foobar"
+        );
+        assert!(decode_base32("MZXW1").is_err());
+    }
+
+    #[test]
+    fn test_name_format_and_parse() {
+        assert_eq!(
+            format_name(OathType::Totp, 30, Some("Example"), "alice"),
+            "Example:alice"
+        );
+        assert_eq!(
+            format_name(OathType::Totp, 60, Some("Example"), "alice"),
+            "60/Example:alice"
+        );
+        assert_eq!(format_name(OathType::Hotp, 60, None, "alice"), "alice");
+
+        assert_eq!(
+            parse_name("60/Example:alice@example.com", OathType::Totp),
+            (
+                Some(60),
+                Some("Example".to_string()),
+                "alice@example.com".to_string()
+            )
+        );
+        assert_eq!(
+            parse_name("a/b", OathType::Totp),
+            (Some(30), None, "a/b".to_string())
+        );
+        assert_eq!(
+            parse_name("5/x", OathType::Hotp),
+            (None, None, "5/x".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_code() {
+        // RFC 4226 appendix D, count 0 (dynamic truncation 0x4c93cf18)
+        assert_eq!(
+            parse_truncated(&[6, 0x4C, 0x93, 0xCF, 0x18]).unwrap(),
+            "755224"
+        );
+        assert_eq!(format_code(0xCC93_CF18, 8), "84755224");
+        assert_eq!(format_code(5, 6), "000005");
+        assert!(parse_truncated(&[6, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_build_put_data() {
+        let credential = OathCredentialData {
+            issuer: Some("Ex".to_string()),
+            account: "a".to_string(),
+            secret: decode_base32("MZXW6YTBOI").unwrap(),
+            oath_type: OathType::Hotp,
+            algorithm: OathAlgorithm::Sha256,
+            digits: 8,
+            period: DEFAULT_PERIOD,
+            counter: 2,
+            touch: true,
+        };
+        assert_eq!(
+            hex::encode(build_put_data(&credential).unwrap().as_slice()),
+            "710445783a61\
+             7310120866 6f6f626172 0000000000000000"
+                .replace(' ', "")
+                + "7802"
+                + "7a0400000002"
+        );
+
+        let mut invalid = credential.clone();
+        invalid.digits = 9;
+        assert!(build_put_data(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_parse_list_and_calculate_all() {
+        let mut list = vec![TAG_NAME_LIST, 9, TYPE_TOTP | ALGORITHM_SHA1];
+        list.extend_from_slice(b"Ex:alice");
+        list.extend([TAG_NAME_LIST, 5, TYPE_HOTP | ALGORITHM_SHA256]);
+        list.extend_from_slice(b"bob1");
+        let credentials = parse_list(&list).unwrap();
+        assert_eq!(credentials[0].issuer.as_deref(), Some("Ex"));
+        assert_eq!(credentials[0].period, Some(30));
+        assert_eq!(credentials[1].oath_type, OathType::Hotp);
+        assert_eq!(credentials[1].algorithm, Some(OathAlgorithm::Sha256));
+
+        let mut all = vec![
+            TAG_NAME,
+            1,
+            b'a',
+            TAG_TRUNCATED,
+            5,
+            6,
+            0x4C,
+            0x93,
+            0xCF,
+            0x18,
+        ];
+        all.extend([TAG_NAME, 1, b'b', TAG_HOTP, 1, 6]);
+        all.extend([TAG_NAME, 1, b'c', TAG_TOUCH, 1, 6]);
+        let codes = parse_calculate_all(&all, 95).unwrap();
+        assert_eq!(codes[0].code.as_deref(), Some("755224"));
+        assert_eq!(
+            (codes[0].valid_from, codes[0].valid_to),
+            (Some(90), Some(120))
+        );
+        assert_eq!(codes[1].credential.oath_type, OathType::Hotp);
+        assert!(codes[2].touch_required && codes[2].code.is_none());
+    }
+
+    #[test]
+    fn test_parse_select_and_access_key() {
+        let mut select = vec![TAG_VERSION, 3, 5, 4, 3];
+        select.extend([TAG_NAME, 8, 1, 2, 3, 4, 5, 6, 7, 8]);
+        select.extend([TAG_CHALLENGE, 8, 0, 0, 0, 0, 0, 0, 0, 1]);
+        select.extend([TAG_ALGORITHM, 1, ALGORITHM_SHA256]);
+        let session = parse_select(&select).unwrap();
+        assert_eq!(session.status.version, "5.4.3");
+        assert!(session.status.access_code_set);
+        assert_eq!(session.status.device_id.len(), 22);
+        assert_eq!(session.salt, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(session.algorithm, OathAlgorithm::Sha256);
+
+        // RFC 6070 PBKDF2-HMAC-SHA1 vector, truncated to 16 bytes
+        let mut key = [0u8; ACCESS_KEY_LEN];
+        pbkdf2::pbkdf2_hmac::<Sha1>(b"password", b"salt", 2, &mut key);
+        assert_eq!(hex::encode(key), "ea6c014dc72d6f8ccd1ed92ace1d41f0");
+        assert_eq!(derive_access_key("password", b"salt").len(), ACCESS_KEY_LEN);
+    }
+
+    #[test]
+    fn test_parse_otpauth_uri() {
+        let credential = parse_otpauth_uri(
+            "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ\
+             &issuer=ACME+Co&algorithm=sha256&digits=8&period=60&image=x",
+        )
+        .unwrap();
+        assert_eq!(credential.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(credential.account, "john.doe@email.com");
+        assert_eq!(credential.oath_type, OathType::Totp);
+        assert_eq!(credential.algorithm, OathAlgorithm::Sha256);
+        assert_eq!((credential.digits, credential.period), (8, 60));
+        assert_eq!(credential.secret.len(), 20);
+        assert_eq!(credential.name(), "60/ACME Co:john.doe@email.com");
+
+        let credential =
+            parse_otpauth_uri("OTPAUTH://hotp/alice?secret=mzxw6ytboi&issuer=Ex&counter=7")
+                .unwrap();
+        assert_eq!(credential.issuer.as_deref(), Some("Ex"));
+        assert_eq!(
+            (credential.oath_type, credential.counter),
+            (OathType::Hotp, 7)
+        );
+        assert_eq!(credential.name(), "Ex:alice");
+    }
+
+    #[test]
+    fn test_parse_otpauth_uri_rejects_invalid() {
+        for uri in [
+            "https://totp/a?secret=MZXW6YTBOI",
+            "otpauth://motp/a?secret=MZXW6YTBOI",
+            "otpauth://totp/a",
+            "otpauth://totp/a?secret=MZXW1",
+            "otpauth://totp/a?secret=MZXW6YTBOI&digits=9",
+            "otpauth://totp/a?secret=MZXW6YTBOI&period=0",
+            "otpauth://totp/a?secret=MZXW6YTBOI&algorithm=MD5",
+            "otpauth://totp/A:a?secret=MZXW6YTBOI&issuer=B",
+            "otpauth://totp/a?secret=MZXW6YTBOI&secret=MZXW6YTBOI",
+            "otpauth://totp/a%2?secret=MZXW6YTBOI",
+        ] {
+            assert!(parse_otpauth_uri(uri).is_err(), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn test_check_import_names() {
+        let uri = |account: &str| {
+            parse_otpauth_uri(&format!(
+                "otpauth://totp/Example:{}?secret=JBSWY3DPEHPK3PXP",
+                account
+            ))
+            .unwrap()
+        };
+        let existing = vec![credential("Example:bob".to_string(), OathType::Totp, None)];
+        assert!(check_import_names(&[uri("alice"), uri("carol")], &existing).is_ok());
+
+        let error = check_import_names(&[uri("alice"), uri("alice")], &[]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "URI 2: Example:alice appears more than once"
+        );
+        let error = check_import_names(&[uri("alice"), uri("bob")], &existing).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("URI 2: Example:bob already exists"));
+    }
+
+    #[test]
+    fn test_inventory_csv() {
+        let credentials = vec![
+            credential(
+                "Ex, Inc:a".to_string(),
+                OathType::Totp,
+                Some(OathAlgorithm::Sha1),
+            ),
+            credential("b".to_string(), OathType::Hotp, Some(OathAlgorithm::Sha256)),
+        ];
+        let mut all = vec![TAG_NAME, 9];
+        all.extend_from_slice(b"Ex, Inc:a");
+        all.extend([TAG_TOUCH, 1, 8, TAG_NAME, 1, b'b', TAG_HOTP, 1, 6]);
+        let codes = parse_calculate_all(&all, 0).unwrap();
+
+        let entries = build_inventory(credentials, &codes);
+        assert_eq!(entries[0].touch, Some(true));
+        assert_eq!(entries[1].digits, Some(6));
+        assert_eq!(entries[1].touch, None);
+        assert_eq!(
+            inventory_csv(&entries),
+            "issuer,account,type,algorithm,digits,period,touch\n\
+             \"Ex, Inc\",a,totp,SHA1,8,30,true\n\
+             ,b,hotp,SHA256,6,,\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_field_escapes_formulas() {
+        assert_eq!(csv_field("alice"), "alice");
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("a=b"), "a=b");
+    }
+}
@@ -0,0 +1,2858 @@
+use anyhow::{anyhow, Result};
+use base64ct::{Base64, Encoding};
+use p256::elliptic_curve::sec1::{FromEncodedPoint, ModulusSize, ToEncodedPoint};
+use p256::elliptic_curve::{AffinePoint, CurveArithmetic, FieldBytesSize, SecretKey};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::{Sha256, Sha384};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+use crate::apdu::{self, ApduLog, ProgressReporter, ProgressStep, Redact};
+use crate::device::DeviceManager;
+use crate::error::Error;
+use crate::piv;
+use crate::tlv::{self, Tlv};
+
+// OpenPGP application AID prefix (RID + application)
+const OPENPGP_AID: [u8; 6] = [0xD2, 0x76, 0x00, 0x01, 0x24, 0x01];
+const AID_LEN: usize = 16;
+
+// Instructions
+const INS_GET_DATA: u8 = 0xCA;
+const INS_PUT_DATA: u8 = 0xDA;
+const INS_VERIFY: u8 = 0x20;
+const INS_CHANGE_REFERENCE_DATA: u8 = 0x24;
+const INS_RESET_RETRY_COUNTER: u8 = 0x2C;
+const INS_GENERATE_ASYMMETRIC: u8 = 0x47;
+const INS_PUT_DATA_ODD: u8 = 0xDB;
+const INS_TERMINATE_DF: u8 = 0xE6;
+const INS_ACTIVATE_FILE: u8 = 0x44;
+const INS_PSO: u8 = 0x2A;
+
+// PSO:COMPUTE DIGITAL SIGNATURE P1-P2
+const PSO_COMPUTE_DIGITAL_SIGNATURE: [u8; 2] = [0x9E, 0x9A];
+
+// GENERATE ASYMMETRIC KEY PAIR P1
+const GENERATE_KEY_PAIR: u8 = 0x80;
+
+// Password references (P2)
+const PW1_SIGN: u8 = 0x81;
+const PW1_OTHER: u8 = 0x82;
+const PW3: u8 = 0x83;
+
+// RESET RETRY COUNTER P1: authorised by the reset code or by a verified PW3
+const RESET_WITH_RESET_CODE: u8 = 0x00;
+const RESET_WITH_PW3: u8 = 0x02;
+
+// Minimum lengths from the specification; maximums come from the PW status
+const PW1_MIN_LEN: usize = 6;
+const PW3_MIN_LEN: usize = 8;
+const RESET_CODE_MIN_LEN: usize = 8;
+
+// Factory default passwords
+const DEFAULT_PW1: &str = "123456";
+const DEFAULT_PW3: &str = "12345678";
+
+// Data objects readable with GET DATA (OpenPGP card 3.4, section 4.4)
+const DO_APPLICATION_RELATED_DATA: u16 = 0x006E;
+const DO_CARDHOLDER_RELATED_DATA: u16 = 0x0065;
+const DO_URL: u16 = 0x5F50;
+const DO_LOGIN_DATA: u16 = 0x005E;
+const DO_SECURITY_SUPPORT_TEMPLATE: u16 = 0x007A;
+const DO_PW_STATUS: u16 = 0x00C4;
+const DO_RESET_CODE: u16 = 0x00D3;
+// First of the per-slot objects, in signature/decryption/authentication order
+const DO_ALGORITHM_ATTRIBUTES: u16 = 0x00C1;
+const DO_FINGERPRINT: u16 = 0x00C7;
+const DO_GENERATION_TIME: u16 = 0x00CE;
+
+// Application related data (6E) elements
+const TAG_AID: [u8; 1] = [0x4F];
+const TAG_DISCRETIONARY: [u8; 1] = [0x73];
+const TAG_ALGORITHM_ATTRIBUTES: [u8; 3] = [0xC1, 0xC2, 0xC3];
+const TAG_PW_STATUS: [u8; 1] = [0xC4];
+const TAG_FINGERPRINTS: [u8; 1] = [0xC5];
+const TAG_GENERATION_TIMES: [u8; 1] = [0xCD];
+const TAG_UIF: [u8; 3] = [0xD6, 0xD7, 0xD8];
+
+// Cardholder related data (65) elements
+const TAG_NAME: [u8; 1] = [0x5B];
+const TAG_LANGUAGE: [u8; 2] = [0x5F, 0x2D];
+const TAG_SEX: [u8; 2] = [0x5F, 0x35];
+
+// Security support template (7A) element
+const TAG_SIGNATURE_COUNTER: [u8; 1] = [0x93];
+
+// Control reference templates selecting a key slot
+const CRT_SIGNATURE: u8 = 0xB6;
+const CRT_DECRYPTION: u8 = 0xB8;
+const CRT_AUTHENTICATION: u8 = 0xA4;
+
+// Public key template (7F49) elements
+const TAG_PUBLIC_KEY: [u8; 2] = [0x7F, 0x49];
+const TAG_RSA_MODULUS: [u8; 1] = [0x81];
+const TAG_RSA_EXPONENT: [u8; 1] = [0x82];
+const TAG_EC_POINT: [u8; 1] = [0x86];
+
+// Extended header list (4D) for key import
+const TAG_EXTENDED_HEADER_LIST: [u8; 1] = [0x4D];
+const TAG_PRIVATE_KEY_TEMPLATE: [u8; 2] = [0x7F, 0x48];
+const TAG_PRIVATE_KEY_DATA: [u8; 2] = [0x5F, 0x48];
+const TAG_IMPORT_RSA_EXPONENT: u8 = 0x91;
+const TAG_IMPORT_RSA_PRIME_P: u8 = 0x92;
+const TAG_IMPORT_RSA_PRIME_Q: u8 = 0x93;
+const TAG_IMPORT_EC_PRIVATE: u8 = 0x92;
+
+// RSA public exponent size written when a slot is switched to RSA
+const DEFAULT_RSA_EXPONENT_BITS: u16 = 32;
+const RSA_IMPORT_STANDARD: u8 = 0x00;
+
+// Version 4 public key packets (RFC 4880 sections 5.5.2 and 12.2)
+const PACKET_TAG_PUBLIC_KEY: u8 = 0xC6;
+const PACKET_TAG_USER_ID: u8 = 0xCD;
+const PACKET_TAG_SIGNATURE: u8 = 0xC2;
+const KEY_PACKET_VERSION: u8 = 0x04;
+const FINGERPRINT_PREFIX: u8 = 0x99;
+const USER_ID_HASH_PREFIX: u8 = 0xB4;
+
+// Version 4 self-signatures (RFC 4880 section 5.2.3)
+const SIGNATURE_VERSION: u8 = 0x04;
+const SIGNATURE_POSITIVE_CERTIFICATION: u8 = 0x13;
+const SUBPACKET_CREATION_TIME: u8 = 2;
+const SUBPACKET_ISSUER: u8 = 16;
+const SUBPACKET_KEY_FLAGS: u8 = 27;
+const SUBPACKET_ISSUER_FINGERPRINT: u8 = 33;
+const KEY_FLAGS_CERTIFY_SIGN: u8 = 0x03;
+const KEY_ID_LEN: usize = 8;
+
+// DigestInfo prefixes the card pads into a PKCS #1 v1.5 RSA signature
+const DIGEST_INFO_SHA256: [u8; 19] = [
+    0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+const DIGEST_INFO_SHA384: [u8; 19] = [
+    0x30, 0x41, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05,
+    0x00, 0x04, 0x30,
+];
+// Curve25519 points are MPIs of the native encoding behind a 40 prefix
+const NATIVE_POINT_PREFIX: u8 = 0x40;
+
+// ECDH KDF parameters (RFC 6637 section 9)
+const KDF_PARAMS_VERSION: u8 = 0x01;
+const HASH_SHA256: u8 = 0x08;
+const HASH_SHA384: u8 = 0x09;
+const CIPHER_AES128: u8 = 0x07;
+const CIPHER_AES256: u8 = 0x09;
+
+// ASCII armor (RFC 4880 section 6)
+const CRC24_INIT: u32 = 0x00B7_04CE;
+const CRC24_POLY: u32 = 0x0186_4CFB;
+const ARMOR_LINE_LEN: usize = 64;
+
+const FINGERPRINT_LEN: usize = 20;
+const TIMESTAMP_LEN: usize = 4;
+const PW_STATUS_LEN: usize = 7;
+
+// Algorithm IDs (RFC 4880 / RFC 6637 public key algorithms)
+const ALGORITHM_RSA: u8 = 0x01;
+const ALGORITHM_ECDH: u8 = 0x12;
+const ALGORITHM_ECDSA: u8 = 0x13;
+const ALGORITHM_EDDSA: u8 = 0x16;
+
+// Import format byte that may follow an EC curve OID
+const EC_IMPORT_WITH_PUBLIC_KEY: u8 = 0xFF;
+
+/// Curves defined for OpenPGP cards, by OID
+const CURVES: [(&str, &[u8]); 8] = [
+    (
+        "nistp256",
+        &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07],
+    ),
+    ("nistp384", &[0x2B, 0x81, 0x04, 0x00, 0x22]),
+    ("nistp521", &[0x2B, 0x81, 0x04, 0x00, 0x23]),
+    (
+        "brainpoolP256r1",
+        &[0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x07],
+    ),
+    (
+        "brainpoolP384r1",
+        &[0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x0B],
+    ),
+    (
+        "brainpoolP512r1",
+        &[0x2B, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x0D],
+    ),
+    (
+        "ed25519",
+        &[0x2B, 0x06, 0x01, 0x04, 0x01, 0xDA, 0x47, 0x0F, 0x01],
+    ),
+    (
+        "cv25519",
+        &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x97, 0x55, 0x01, 0x05, 0x01],
+    ),
+];
+
+/// Registered card manufacturers (GnuPG's list, abridged)
+const MANUFACTURERS: [(u16, &str); 12] = [
+    (0x0001, "PPC Card Systems"),
+    (0x0002, "Prism Payment Technologies"),
+    (0x0003, "OpenFortress Digital signatures"),
+    (0x0005, "ZeitControl cardsystems"),
+    (0x0006, "Yubico"),
+    (0x000B, "Feitian Technologies"),
+    (0x000F, "Nitrokey"),
+    (0x0042, "GnuPG e.V."),
+    (0x2342, "warpzone e.V."),
+    (0xF1D0, "CanoKeys"),
+    (0xF517, "FSIJ"),
+    (0xFFFF, "Test card"),
+];
+
+/// Key slot on an OpenPGP card
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OpenPgpKeySlot {
+    Signature,
+    Decryption,
+    Authentication,
+}
+
+impl OpenPgpKeySlot {
+    const ALL: [OpenPgpKeySlot; 3] = [
+        OpenPgpKeySlot::Signature,
+        OpenPgpKeySlot::Decryption,
+        OpenPgpKeySlot::Authentication,
+    ];
+
+    /// Parse the name used in command parameters
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "sig" | "signature" => Some(OpenPgpKeySlot::Signature),
+            "dec" | "enc" | "decryption" => Some(OpenPgpKeySlot::Decryption),
+            "aut" | "auth" | "authentication" => Some(OpenPgpKeySlot::Authentication),
+            _ => None,
+        }
+    }
+
+    fn control_reference(self) -> u8 {
+        match self {
+            OpenPgpKeySlot::Signature => CRT_SIGNATURE,
+            OpenPgpKeySlot::Decryption => CRT_DECRYPTION,
+            OpenPgpKeySlot::Authentication => CRT_AUTHENTICATION,
+        }
+    }
+
+    /// The slot's data object in a run of per-slot objects starting at `first`
+    fn data_object(self, first: u16) -> u16 {
+        first + self.index() as u16
+    }
+
+    /// Position in the fingerprint, timestamp and attribute objects
+    fn index(self) -> usize {
+        match self {
+            OpenPgpKeySlot::Signature => 0,
+            OpenPgpKeySlot::Decryption => 1,
+            OpenPgpKeySlot::Authentication => 2,
+        }
+    }
+}
+
+/// Algorithm attributes of a key slot (C1-C3)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OpenPgpAlgorithm {
+    Rsa {
+        modulus_bits: u16,
+        exponent_bits: u16,
+        import_format: u8,
+    },
+    Ecdh {
+        curve: String,
+        with_public_key: bool,
+    },
+    Ecdsa {
+        curve: String,
+        with_public_key: bool,
+    },
+    Eddsa {
+        curve: String,
+        with_public_key: bool,
+    },
+    Unknown {
+        raw: String,
+    },
+}
+
+/// Key algorithm that can be generated on or imported into a slot
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyAlgorithm {
+    Rsa2048,
+    Rsa3072,
+    Rsa4096,
+    NistP256,
+    NistP384,
+    Ed25519,
+    Cv25519,
+}
+
+impl KeyAlgorithm {
+    /// Parse the name used in command parameters
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "rsa2048" => Some(KeyAlgorithm::Rsa2048),
+            "rsa3072" => Some(KeyAlgorithm::Rsa3072),
+            "rsa4096" => Some(KeyAlgorithm::Rsa4096),
+            "nistp256" | "p256" => Some(KeyAlgorithm::NistP256),
+            "nistp384" | "p384" => Some(KeyAlgorithm::NistP384),
+            "ed25519" => Some(KeyAlgorithm::Ed25519),
+            "cv25519" | "x25519" => Some(KeyAlgorithm::Cv25519),
+            _ => None,
+        }
+    }
+
+    /// The algorithm described by a slot's current attributes, if supported
+    fn from_attributes(attributes: &OpenPgpAlgorithm) -> Option<Self> {
+        match attributes {
+            OpenPgpAlgorithm::Rsa { modulus_bits, .. } => match modulus_bits {
+                2048 => Some(KeyAlgorithm::Rsa2048),
+                3072 => Some(KeyAlgorithm::Rsa3072),
+                4096 => Some(KeyAlgorithm::Rsa4096),
+                _ => None,
+            },
+            OpenPgpAlgorithm::Ecdh { curve, .. }
+            | OpenPgpAlgorithm::Ecdsa { curve, .. }
+            | OpenPgpAlgorithm::Eddsa { curve, .. } => match curve.as_str() {
+                "nistp256" => Some(KeyAlgorithm::NistP256),
+                "nistp384" => Some(KeyAlgorithm::NistP384),
+                "ed25519" => Some(KeyAlgorithm::Ed25519),
+                "cv25519" => Some(KeyAlgorithm::Cv25519),
+                _ => None,
+            },
+            OpenPgpAlgorithm::Unknown { .. } => None,
+        }
+    }
+
+    fn rsa_bits(self) -> Option<u16> {
+        match self {
+            KeyAlgorithm::Rsa2048 => Some(2048),
+            KeyAlgorithm::Rsa3072 => Some(3072),
+            KeyAlgorithm::Rsa4096 => Some(4096),
+            _ => None,
+        }
+    }
+
+    fn curve_oid(self) -> Option<&'static [u8]> {
+        let name = match self {
+            KeyAlgorithm::NistP256 => "nistp256",
+            KeyAlgorithm::NistP384 => "nistp384",
+            KeyAlgorithm::Ed25519 => "ed25519",
+            KeyAlgorithm::Cv25519 => "cv25519",
+            _ => return None,
+        };
+        CURVES
+            .iter()
+            .find(|(curve, _)| *curve == name)
+            .map(|(_, oid)| *oid)
+    }
+
+    /// Public key algorithm ID, which depends on the slot for NIST curves
+    fn algorithm_id(self, slot: OpenPgpKeySlot) -> u8 {
+        match self {
+            KeyAlgorithm::Rsa2048 | KeyAlgorithm::Rsa3072 | KeyAlgorithm::Rsa4096 => ALGORITHM_RSA,
+            KeyAlgorithm::NistP256 | KeyAlgorithm::NistP384
+                if slot == OpenPgpKeySlot::Decryption =>
+            {
+                ALGORITHM_ECDH
+            }
+            KeyAlgorithm::NistP256 | KeyAlgorithm::NistP384 => ALGORITHM_ECDSA,
+            KeyAlgorithm::Ed25519 => ALGORITHM_EDDSA,
+            KeyAlgorithm::Cv25519 => ALGORITHM_ECDH,
+        }
+    }
+
+    /// Curve25519 keys are signing-only (Ed25519) or decryption-only (Cv25519)
+    fn check_slot(self, slot: OpenPgpKeySlot) -> Result<()> {
+        let allowed = match self {
+            KeyAlgorithm::Ed25519 => slot != OpenPgpKeySlot::Decryption,
+            KeyAlgorithm::Cv25519 => slot == OpenPgpKeySlot::Decryption,
+            _ => true,
+        };
+        if !allowed {
+            return Err(Error::InvalidParams(format!(
+                "{:?} keys cannot be used in the {:?} slot",
+                self, slot
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Algorithm attributes for a slot, keeping the RSA exponent size of `current`
+    fn attributes(self, slot: OpenPgpKeySlot, current: Option<&OpenPgpAlgorithm>) -> Vec<u8> {
+        if let Some(bits) = self.rsa_bits() {
+            let exponent_bits = match current {
+                Some(OpenPgpAlgorithm::Rsa { exponent_bits, .. }) => *exponent_bits,
+                _ => DEFAULT_RSA_EXPONENT_BITS,
+            };
+            let mut attributes = vec![ALGORITHM_RSA];
+            attributes.extend(bits.to_be_bytes());
+            attributes.extend(exponent_bits.to_be_bytes());
+            attributes.push(RSA_IMPORT_STANDARD);
+            return attributes;
+        }
+        let mut attributes = vec![self.algorithm_id(slot)];
+        attributes.extend_from_slice(self.curve_oid().unwrap_or_default());
+        attributes
+    }
+
+    /// ECDH KDF hash and key wrap cipher (RFC 6637 section 12)
+    fn kdf_parameters(self) -> [u8; 4] {
+        let (hash, cipher) = match self {
+            KeyAlgorithm::NistP384 => (HASH_SHA384, CIPHER_AES256),
+            _ => (HASH_SHA256, CIPHER_AES128),
+        };
+        [0x03, KDF_PARAMS_VERSION, hash, cipher]
+    }
+
+    /// Hash algorithm for self-signatures
+    fn signature_hash(self) -> u8 {
+        match self {
+            KeyAlgorithm::NistP384 => HASH_SHA384,
+            _ => HASH_SHA256,
+        }
+    }
+}
+
+/// Public key returned by the card or derived from an imported key
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PublicKeyMaterial {
+    Rsa {
+        modulus: Vec<u8>,
+        exponent: Vec<u8>,
+    },
+    /// Uncompressed SEC1 point, or the native 32 bytes for Curve25519
+    Ec {
+        point: Vec<u8>,
+    },
+}
+
+/// Private key decoded for import
+struct ImportKey {
+    algorithm: KeyAlgorithm,
+    public_key: PublicKeyMaterial,
+    /// RSA prime length in bytes; primes are left-padded to it
+    rsa_prime_len: usize,
+    /// RSA: e, p, q; EC: the private scalar (Ed25519: the seed)
+    secrets: Vec<Zeroizing<Vec<u8>>>,
+}
+
+/// User interaction flag (touch) setting of a key slot
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TouchSetting {
+    Off,
+    On,
+    /// Enabled and cannot be disabled without a reset
+    Fixed,
+    Cached,
+    CachedFixed,
+}
+
+impl TouchSetting {
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0x00 => Some(TouchSetting::Off),
+            0x01 => Some(TouchSetting::On),
+            0x02 => Some(TouchSetting::Fixed),
+            0x03 => Some(TouchSetting::Cached),
+            0x04 => Some(TouchSetting::CachedFixed),
+            _ => None,
+        }
+    }
+}
+
+/// Key information for one slot
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OpenPgpKeyInfo {
+    pub slot: OpenPgpKeySlot,
+    /// Hex fingerprint, absent when the slot is empty
+    pub fingerprint: Option<String>,
+    /// RFC 3339 generation time, absent when not set
+    pub generation_time: Option<String>,
+    pub algorithm: Option<OpenPgpAlgorithm>,
+    pub touch: Option<TouchSetting>,
+}
+
+/// PW status bytes (C4)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OpenPgpPwStatus {
+    /// Whether PW1 stays valid for more than one signature
+    pub pw1_valid_for_multiple_signatures: bool,
+    pub pw1_max_length: u8,
+    pub reset_code_max_length: u8,
+    pub pw3_max_length: u8,
+    pub pw1_retries: u8,
+    pub reset_code_retries: u8,
+    pub pw3_retries: u8,
+}
+
+/// OpenPGP card status
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OpenPgpStatus {
+    pub aid: String,
+    /// Specification version, e.g. "3.4"
+    pub version: String,
+    pub manufacturer_id: String,
+    pub manufacturer: Option<String>,
+    pub serial_number: String,
+    pub cardholder_name: Option<String>,
+    pub languages: Vec<String>,
+    pub sex: Option<String>,
+    pub url: Option<String>,
+    pub login_data: Option<String>,
+    pub signature_counter: Option<u32>,
+    pub pw_status: Option<OpenPgpPwStatus>,
+    pub keys: Vec<OpenPgpKeyInfo>,
+}
+
+/// Which password a VERIFY targets
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PasswordType {
+    /// PW1 for PSO:COMPUTE DIGITAL SIGNATURE
+    Signing,
+    /// PW1 for decryption and authentication
+    User,
+    /// PW3 (admin PIN)
+    Admin,
+}
+
+impl PasswordType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "signing" | "sign" => Some(PasswordType::Signing),
+            "user" => Some(PasswordType::User),
+            "admin" => Some(PasswordType::Admin),
+            _ => None,
+        }
+    }
+
+    fn reference(self) -> u8 {
+        match self {
+            PasswordType::Signing => PW1_SIGN,
+            PasswordType::User => PW1_OTHER,
+            PasswordType::Admin => PW3,
+        }
+    }
+
+    /// The PW status retry counter spent by a wrong password
+    fn retry_counter(self) -> fn(&OpenPgpPwStatus) -> u8 {
+        match self {
+            PasswordType::Admin => |s| s.pw3_retries,
+            _ => |s| s.pw1_retries,
+        }
+    }
+}
+
+/// Result of a PIN operation with the PW status read afterwards
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenPgpPinResult {
+    pub pw_status: Option<OpenPgpPwStatus>,
+    pub activity_log: Vec<ApduLog>,
+}
+
+/// Key generated on or imported into a slot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenPgpKeyResult {
+    pub slot: OpenPgpKeySlot,
+    pub algorithm: KeyAlgorithm,
+    pub fingerprint: String,
+    pub generation_time: String,
+    /// Version 4 public key packet, hex encoded
+    pub public_key_packet: String,
+    /// The User ID the key was self-certified with, if any
+    pub user_id: Option<String>,
+    /// ASCII-armored key packet, followed by the User ID and its
+    /// self-signature when certified. A bare key packet is not importable
+    /// into GnuPG; it is only useful to match fingerprints.
+    pub public_key_armored: String,
+    pub activity_log: Vec<ApduLog>,
+}
+
+/// User ID to self-certify a new signature key with
+///
+/// The certification is signed on the card, so PW1 is needed as well.
+pub struct SelfCertification<'a> {
+    pub user_id: &'a str,
+    pub pin: &'a str,
+}
+
+/// State of the OpenPGP application after a reset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenPgpResetResult {
+    pub status: OpenPgpStatus,
+    pub default_pin: String,
+    pub default_admin_pin: String,
+    /// Signs that the card did not return to its defaults
+    pub warnings: Vec<String>,
+    pub steps: Vec<ProgressStep>,
+    pub activity_log: Vec<ApduLog>,
+}
+
+/// OpenPGP status with activity logs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenPgpStatusResult {
+    pub status: OpenPgpStatus,
+    pub activity_log: Vec<ApduLog>,
+}
+
+/// Build GET DATA APDU; OpenPGP addresses data objects through P1-P2
+fn build_get_data_apdu(tag: u16) -> Vec<u8> {
+    let [p1, p2] = tag.to_be_bytes();
+    vec![0x00, INS_GET_DATA, p1, p2, 0x00]
+}
+
+fn build_password_apdu(ins: u8, p1: u8, p2: u8, data: &[u8]) -> Vec<u8> {
+    let mut apdu = vec![0x00, ins, p1, p2, data.len() as u8];
+    apdu.extend_from_slice(data);
+    apdu
+}
+
+fn build_verify_apdu(password: PasswordType, pin: &str) -> Vec<u8> {
+    build_password_apdu(INS_VERIFY, 0x00, password.reference(), pin.as_bytes())
+}
+
+/// CHANGE REFERENCE DATA takes the old and new values concatenated
+fn build_change_reference_apdu(reference: u8, current: &str, new: &str) -> Vec<u8> {
+    let data = [current.as_bytes(), new.as_bytes()].concat();
+    build_password_apdu(INS_CHANGE_REFERENCE_DATA, 0x00, reference, &data)
+}
+
+/// RESET RETRY COUNTER for PW1, with the reset code or after PW3 verification
+fn build_reset_retry_counter_apdu(reset_code: Option<&str>, new_pin: &str) -> Vec<u8> {
+    match reset_code {
+        Some(code) => {
+            let data = [code.as_bytes(), new_pin.as_bytes()].concat();
+            build_password_apdu(
+                INS_RESET_RETRY_COUNTER,
+                RESET_WITH_RESET_CODE,
+                PW1_SIGN,
+                &data,
+            )
+        }
+        None => build_password_apdu(
+            INS_RESET_RETRY_COUNTER,
+            RESET_WITH_PW3,
+            PW1_SIGN,
+            new_pin.as_bytes(),
+        ),
+    }
+}
+
+/// PUT DATA to the reset code object; empty data removes the reset code
+fn build_set_reset_code_apdu(reset_code: &str) -> Vec<u8> {
+    let [p1, p2] = DO_RESET_CODE.to_be_bytes();
+    build_password_apdu(INS_PUT_DATA, p1, p2, reset_code.as_bytes())
+}
+
+/// Check a password against the specification minimum and an optional card maximum
+fn check_password_length(name: &str, value: &str, min: usize, max: Option<u8>) -> Result<()> {
+    let len = value.len();
+    let max = max.map_or(usize::from(u8::MAX), usize::from);
+    if len < min || len > max {
+        return Err(Error::PinPolicyViolation(format!(
+            "{} must be {} to {} characters",
+            name, min, max
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// SELECT the OpenPGP application
+fn select_application(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<()> {
+    transmit(
+        device_manager,
+        device_id,
+        &apdu::build_select_apdu(&OPENPGP_AID),
+        "SELECT OpenPGP Application",
+        activity_log,
+    )?;
+    Ok(())
+}
+
+/// Read a data object, returning its value without the outer tag
+fn get_data(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    tag: u16,
+    name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    let data = transmit(
+        device_manager,
+        device_id,
+        &build_get_data_apdu(tag),
+        &format!("GET DATA ({})", name),
+        activity_log,
+    )?;
+
+    // Constructed objects come back wrapped in their own tag
+    let outer = if tag > 0xFF {
+        tag.to_be_bytes().to_vec()
+    } else {
+        vec![tag as u8]
+    };
+    match tlv::parse(&data) {
+        Ok(tlvs) if tlvs.len() == 1 && tlvs[0].tag == outer => Ok(tlvs[0].value.clone()),
+        _ => Ok(data),
+    }
+}
+
+/// Read an optional data object, logging instead of failing
+fn get_optional_data(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    tag: u16,
+    name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Option<Vec<u8>> {
+    match get_data(device_manager, device_id, tag, name, activity_log) {
+        Ok(data) if !data.is_empty() => Some(data),
+        Ok(_) => None,
+        Err(e) => {
+            log::debug!("{} not available: {}", name, e);
+            None
+        }
+    }
+}
+
+fn manufacturer_name(id: u16) -> Option<String> {
+    MANUFACTURERS
+        .iter()
+        .find(|(known, _)| *known == id)
+        .map(|(_, name)| name.to_string())
+}
+
+fn curve_name(oid: &[u8]) -> String {
+    CURVES
+        .iter()
+        .find(|(_, known)| *known == oid)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| format!("oid:{}", hex::encode(oid)))
+}
+
+/// Parse algorithm attributes (C1-C3)
+fn parse_algorithm_attributes(value: &[u8]) -> OpenPgpAlgorithm {
+    let unknown = || OpenPgpAlgorithm::Unknown {
+        raw: hex::encode(value),
+    };
+    let Some((&id, rest)) = value.split_first() else {
+        return unknown();
+    };
+
+    if id == ALGORITHM_RSA {
+        return match rest {
+            [n1, n2, e1, e2, format @ ..] => OpenPgpAlgorithm::Rsa {
+                modulus_bits: u16::from_be_bytes([*n1, *n2]),
+                exponent_bits: u16::from_be_bytes([*e1, *e2]),
+                import_format: format.first().copied().unwrap_or(0),
+            },
+            _ => unknown(),
+        };
+    }
+
+    // EC attributes are the curve OID, optionally followed by the import format
+    let (oid, with_public_key) = match rest.split_last() {
+        Some((&EC_IMPORT_WITH_PUBLIC_KEY, oid)) => (oid, true),
+        _ => (rest, false),
+    };
+    if oid.is_empty() {
+        return unknown();
+    }
+    let curve = curve_name(oid);
+    match id {
+        ALGORITHM_ECDH => OpenPgpAlgorithm::Ecdh {
+            curve,
+            with_public_key,
+        },
+        ALGORITHM_ECDSA => OpenPgpAlgorithm::Ecdsa {
+            curve,
+            with_public_key,
+        },
+        ALGORITHM_EDDSA => OpenPgpAlgorithm::Eddsa {
+            curve,
+            with_public_key,
+        },
+        _ => unknown(),
+    }
+}
+
+fn parse_pw_status(value: &[u8]) -> Result<OpenPgpPwStatus> {
+    if value.len() < PW_STATUS_LEN {
+        return Err(Error::MalformedResponse(format!(
+            "PW status bytes must be {} bytes, got {}",
+            PW_STATUS_LEN,
+            value.len()
+        ))
+        .into());
+    }
+    Ok(OpenPgpPwStatus {
+        pw1_valid_for_multiple_signatures: value[0] != 0,
+        pw1_max_length: value[1],
+        reset_code_max_length: value[2],
+        pw3_max_length: value[3],
+        pw1_retries: value[4],
+        reset_code_retries: value[5],
+        pw3_retries: value[6],
+    })
+}
+
+/// Format a key generation timestamp, treating zero as unset
+fn format_timestamp(bytes: &[u8]) -> Option<String> {
+    let seconds = u32::from_be_bytes(bytes.try_into().ok()?);
+    if seconds == 0 {
+        return None;
+    }
+    x509_cert::der::DateTime::from_unix_duration(Duration::from_secs(u64::from(seconds)))
+        .ok()
+        .map(|time| time.to_string())
+}
+
+/// Format the cardholder name ("Surname<<Given<Names") for display
+fn format_name(value: &[u8]) -> Option<String> {
+    let raw = String::from_utf8_lossy(value);
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return None;
+    }
+    let name = match raw.split_once("<<") {
+        Some((surname, given)) => format!("{} {}", given, surname),
+        None => raw.to_string(),
+    };
+    Some(name.replace('<', " ").trim().to_string())
+}
+
+/// Decode the ISO 5218 sex code
+fn sex_name(value: &[u8]) -> Option<String> {
+    let name = match value.first()? {
+        b'1' => "male",
+        b'2' => "female",
+        b'9' => "notApplicable",
+        _ => "notKnown",
+    };
+    Some(name.to_string())
+}
+
+/// Slot-indexed fixed-size entries of a fingerprint or timestamp object
+fn slot_entry(value: Option<&Tlv>, slot: OpenPgpKeySlot, len: usize) -> Option<&[u8]> {
+    let start = slot.index() * len;
+    value.and_then(|tlv| tlv.value.get(start..start + len))
+}
+
+/// Parse Application Related Data (6E content) into a status with key details
+fn parse_application_data(data: &[u8]) -> Result<OpenPgpStatus> {
+    let elements = tlv::parse(data)?;
+    let aid = tlv::find(&elements, &TAG_AID)
+        .map(|tlv| tlv.value.clone())
+        .filter(|aid| aid.len() == AID_LEN && aid.starts_with(&OPENPGP_AID))
+        .ok_or_else(|| Error::MalformedResponse("OpenPGP AID (4F) missing".to_string()))?;
+
+    // Version 3 cards nest key data in 73, older cards list it directly
+    let discretionary = match tlv::find(&elements, &TAG_DISCRETIONARY) {
+        Some(tlv) => tlv.children()?,
+        None => elements.clone(),
+    };
+
+    let fingerprints = tlv::find(&discretionary, &TAG_FINGERPRINTS);
+    let timestamps = tlv::find(&discretionary, &TAG_GENERATION_TIMES);
+    let keys = OpenPgpKeySlot::ALL
+        .iter()
+        .map(|&slot| OpenPgpKeyInfo {
+            slot,
+            fingerprint: slot_entry(fingerprints, slot, FINGERPRINT_LEN)
+                .filter(|fp| fp.iter().any(|&b| b != 0))
+                .map(hex::encode),
+            generation_time: slot_entry(timestamps, slot, TIMESTAMP_LEN).and_then(format_timestamp),
+            algorithm: tlv::find(&discretionary, &[TAG_ALGORITHM_ATTRIBUTES[slot.index()]])
+                .map(|tlv| parse_algorithm_attributes(&tlv.value)),
+            touch: tlv::find(&discretionary, &[TAG_UIF[slot.index()]])
+                .and_then(|tlv| tlv.value.first().copied())
+                .and_then(TouchSetting::from_id),
+        })
+        .collect();
+
+    let pw_status = tlv::find(&discretionary, &TAG_PW_STATUS)
+        .map(|tlv| parse_pw_status(&tlv.value))
+        .transpose()?;
+
+    let manufacturer = u16::from_be_bytes([aid[8], aid[9]]);
+    Ok(OpenPgpStatus {
+        aid: hex::encode(&aid),
+        version: format!("{:x}.{:x}", aid[6], aid[7]),
+        manufacturer_id: format!("{:04x}", manufacturer),
+        manufacturer: manufacturer_name(manufacturer),
+        serial_number: hex::encode(&aid[10..14]),
+        cardholder_name: None,
+        languages: Vec::new(),
+        sex: None,
+        url: None,
+        login_data: None,
+        signature_counter: None,
+        pw_status,
+        keys,
+    })
+}
+
+/// Merge Cardholder Related Data (65 content) into the status
+fn apply_cardholder_data(status: &mut OpenPgpStatus, data: &[u8]) -> Result<()> {
+    let elements = tlv::parse(data)?;
+    status.cardholder_name =
+        tlv::find(&elements, &TAG_NAME).and_then(|tlv| format_name(&tlv.value));
+    status.languages = tlv::find(&elements, &TAG_LANGUAGE)
+        .map(|tlv| {
+            tlv.value
+                .chunks(2)
+                .map(|code| String::from_utf8_lossy(code).into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    status.sex = tlv::find(&elements, &TAG_SEX).and_then(|tlv| sex_name(&tlv.value));
+    Ok(())
+}
+
+/// Read the digital signature counter from the security support template
+fn parse_signature_counter(data: &[u8]) -> Result<Option<u32>> {
+    let counter = tlv::parse(data)?
+        .into_iter()
+        .find(|tlv| tlv.tag == TAG_SIGNATURE_COUNTER)
+        .map(|tlv| {
+            tlv.value
+                .iter()
+                .fold(0u32, |acc, &b| (acc << 8) | u32::from(b))
+        });
+    Ok(counter)
+}
+
+fn optional_text(value: Option<Vec<u8>>) -> Option<String> {
+    value
+        .map(|v| String::from_utf8_lossy(&v).trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// Read the card status: AID, cardholder data, PW status and key information
+pub fn get_status(device_manager: &DeviceManager, device_id: &str) -> Result<OpenPgpStatusResult> {
+    log::debug!("Reading OpenPGP card status...");
+
+    let mut activity_log = Vec::new();
+    select_application(device_manager, device_id, &mut activity_log)?;
+    let status = read_status(device_manager, device_id, &mut activity_log)?;
+
+    log::info!(
+        "OpenPGP card {} (version {}) status read",
+        status.serial_number,
+        status.version
+    );
+    Ok(OpenPgpStatusResult {
+        status,
+        activity_log,
+    })
+}
+
+/// Read the application, cardholder and security data of the selected application
+fn read_status(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<OpenPgpStatus> {
+    let application_data = get_data(
+        device_manager,
+        device_id,
+        DO_APPLICATION_RELATED_DATA,
+        "Application Related Data",
+        activity_log,
+    )?;
+    if application_data.is_empty() {
+        return Err(anyhow!("Card returned no application related data"));
+    }
+    let mut status = parse_application_data(&application_data)?;
+
+    if let Some(data) = get_optional_data(
+        device_manager,
+        device_id,
+        DO_CARDHOLDER_RELATED_DATA,
+        "Cardholder Related Data",
+        activity_log,
+    ) {
+        if let Err(e) = apply_cardholder_data(&mut status, &data) {
+            log::warn!("Failed to parse cardholder data: {}", e);
+        }
+    }
+
+    status.url = optional_text(get_optional_data(
+        device_manager,
+        device_id,
+        DO_URL,
+        "URL",
+        activity_log,
+    ));
+    status.login_data = optional_text(get_optional_data(
+        device_manager,
+        device_id,
+        DO_LOGIN_DATA,
+        "Login Data",
+        activity_log,
+    ));
+
+    if let Some(data) = get_optional_data(
+        device_manager,
+        device_id,
+        DO_SECURITY_SUPPORT_TEMPLATE,
+        "Security Support Template",
+        activity_log,
+    ) {
+        match parse_signature_counter(&data) {
+            Ok(counter) => status.signature_counter = counter,
+            Err(e) => log::warn!("Failed to parse signature counter: {}", e),
+        }
+    }
+
+    Ok(status)
+}
+
+/// Read the PW status bytes, which carry the retry counters
+fn read_pw_status(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Option<OpenPgpPwStatus> {
+    let data = get_optional_data(
+        device_manager,
+        device_id,
+        DO_PW_STATUS,
+        "PW Status Bytes",
+        activity_log,
+    )?;
+    match parse_pw_status(&data) {
+        Ok(status) => Some(status),
+        Err(e) => {
+            log::warn!("Failed to parse PW status: {}", e);
+            None
+        }
+    }
+}
+
+fn transmit(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    apdu: &[u8],
+    command_name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    apdu::transmit(
+        device_manager,
+        device_id,
+        apdu,
+        Redact::Nothing,
+        command_name,
+        activity_log,
+    )
+}
+
+/// Turn a rejected password into `PinInvalid` or `PinBlocked`
+///
+/// Cards answer a wrong password with 63Cx or just 6982, so `remaining` is
+/// asked for the count from the PW status bytes and the 63Cx count is only
+/// a fallback. Other errors are returned unchanged.
+fn wrong_password_error(
+    error: anyhow::Error,
+    remaining: impl FnOnce() -> Option<u8>,
+) -> anyhow::Error {
+    let reported = match crate::error::classify(&error) {
+        Some(Error::SecurityStatusNotSatisfied) => None,
+        Some(Error::PinInvalid { retries }) => *retries,
+        _ => return error,
+    };
+    match remaining().or(reported) {
+        Some(0) => Error::PinBlocked.into(),
+        retries => Error::PinInvalid { retries }.into(),
+    }
+}
+
+/// Send a command carrying a password, see `wrong_password_error`
+fn transmit_password(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    apdu: &[u8],
+    command_name: &str,
+    retries: fn(&OpenPgpPwStatus) -> u8,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    apdu::transmit(
+        device_manager,
+        device_id,
+        apdu,
+        Redact::Command,
+        command_name,
+        activity_log,
+    )
+    .map_err(|error| {
+        wrong_password_error(error, || {
+            read_pw_status(device_manager, device_id, activity_log).map(|s| retries(&s))
+        })
+    })
+}
+
+/// VERIFY a password, see `transmit_password`
+fn verify_password(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    password: PasswordType,
+    pin: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<()> {
+    transmit_password(
+        device_manager,
+        device_id,
+        &build_verify_apdu(password, pin),
+        &format!("VERIFY ({:?})", password),
+        password.retry_counter(),
+        activity_log,
+    )?;
+    Ok(())
+}
+
+/// Select the application and read the PW status for length limits
+fn prepare_password_change(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Option<OpenPgpPwStatus>> {
+    select_application(device_manager, device_id, activity_log)?;
+    Ok(read_pw_status(device_manager, device_id, activity_log))
+}
+
+/// Verify PW1 (signing or user) or PW3
+pub fn verify(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    password: PasswordType,
+    pin: &str,
+) -> Result<OpenPgpPinResult> {
+    log::debug!("Verifying OpenPGP {:?} PIN...", password);
+
+    let mut activity_log = Vec::new();
+    select_application(device_manager, device_id, &mut activity_log)?;
+    verify_password(device_manager, device_id, password, pin, &mut activity_log)?;
+
+    log::info!("OpenPGP {:?} PIN verified", password);
+    Ok(OpenPgpPinResult {
+        pw_status: read_pw_status(device_manager, device_id, &mut activity_log),
+        activity_log,
+    })
+}
+
+/// Change PW1 (user PIN)
+pub fn change_pin(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    current_pin: &str,
+    new_pin: &str,
+) -> Result<OpenPgpPinResult> {
+    log::debug!("Changing OpenPGP user PIN...");
+
+    let mut activity_log = Vec::new();
+    let status = prepare_password_change(device_manager, device_id, &mut activity_log)?;
+    check_password_length(
+        "PIN",
+        new_pin,
+        PW1_MIN_LEN,
+        status.as_ref().map(|s| s.pw1_max_length),
+    )?;
+    transmit_password(
+        device_manager,
+        device_id,
+        &build_change_reference_apdu(PW1_SIGN, current_pin, new_pin),
+        "CHANGE REFERENCE DATA (PW1)",
+        |s| s.pw1_retries,
+        &mut activity_log,
+    )?;
+
+    log::info!("OpenPGP user PIN changed");
+    Ok(OpenPgpPinResult {
+        pw_status: read_pw_status(device_manager, device_id, &mut activity_log),
+        activity_log,
+    })
+}
+
+/// Change PW3 (admin PIN)
+pub fn change_admin_pin(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    current_admin_pin: &str,
+    new_admin_pin: &str,
+) -> Result<OpenPgpPinResult> {
+    log::debug!("Changing OpenPGP admin PIN...");
+
+    let mut activity_log = Vec::new();
+    let status = prepare_password_change(device_manager, device_id, &mut activity_log)?;
+    check_password_length(
+        "Admin PIN",
+        new_admin_pin,
+        PW3_MIN_LEN,
+        status.as_ref().map(|s| s.pw3_max_length),
+    )?;
+    transmit_password(
+        device_manager,
+        device_id,
+        &build_change_reference_apdu(PW3, current_admin_pin, new_admin_pin),
+        "CHANGE REFERENCE DATA (PW3)",
+        |s| s.pw3_retries,
+        &mut activity_log,
+    )?;
+
+    log::info!("OpenPGP admin PIN changed");
+    Ok(OpenPgpPinResult {
+        pw_status: read_pw_status(device_manager, device_id, &mut activity_log),
+        activity_log,
+    })
+}
+
+/// Set the reset code under PW3; an empty code removes it
+pub fn set_reset_code(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    admin_pin: &str,
+    reset_code: &str,
+) -> Result<OpenPgpPinResult> {
+    log::debug!("Setting OpenPGP reset code...");
+
+    let mut activity_log = Vec::new();
+    let status = prepare_password_change(device_manager, device_id, &mut activity_log)?;
+    if !reset_code.is_empty() {
+        check_password_length(
+            "Reset code",
+            reset_code,
+            RESET_CODE_MIN_LEN,
+            status.as_ref().map(|s| s.reset_code_max_length),
+        )?;
+    }
+    verify_password(
+        device_manager,
+        device_id,
+        PasswordType::Admin,
+        admin_pin,
+        &mut activity_log,
+    )?;
+    apdu::transmit(
+        device_manager,
+        device_id,
+        &build_set_reset_code_apdu(reset_code),
+        Redact::Command,
+        "PUT DATA (Reset Code)",
+        &mut activity_log,
+    )?;
+
+    log::info!("OpenPGP reset code updated");
+    Ok(OpenPgpPinResult {
+        pw_status: read_pw_status(device_manager, device_id, &mut activity_log),
+        activity_log,
+    })
+}
+
+/// How an unblock of PW1 is authorised
+pub enum UnblockAuthorization<'a> {
+    ResetCode(&'a str),
+    AdminPin(&'a str),
+}
+
+/// Unblock PW1 and set a new user PIN
+pub fn unblock_pin(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    authorization: UnblockAuthorization,
+    new_pin: &str,
+) -> Result<OpenPgpPinResult> {
+    log::debug!("Unblocking OpenPGP user PIN...");
+
+    let mut activity_log = Vec::new();
+    let status = prepare_password_change(device_manager, device_id, &mut activity_log)?;
+    check_password_length(
+        "PIN",
+        new_pin,
+        PW1_MIN_LEN,
+        status.as_ref().map(|s| s.pw1_max_length),
+    )?;
+
+    // The reset code has its own retry counter; after PW3 it is PW3's
+    let (apdu, retries): (_, fn(&OpenPgpPwStatus) -> u8) = match authorization {
+        UnblockAuthorization::ResetCode(code) => {
+            (build_reset_retry_counter_apdu(Some(code), new_pin), |s| {
+                s.reset_code_retries
+            })
+        }
+        UnblockAuthorization::AdminPin(admin_pin) => {
+            verify_password(
+                device_manager,
+                device_id,
+                PasswordType::Admin,
+                admin_pin,
+                &mut activity_log,
+            )?;
+            (build_reset_retry_counter_apdu(None, new_pin), |s| {
+                s.pw3_retries
+            })
+        }
+    };
+    transmit_password(
+        device_manager,
+        device_id,
+        &apdu,
+        "RESET RETRY COUNTER (PW1)",
+        retries,
+        &mut activity_log,
+    )?;
+
+    log::info!("OpenPGP user PIN unblocked");
+    Ok(OpenPgpPinResult {
+        pw_status: read_pw_status(device_manager, device_id, &mut activity_log),
+        activity_log,
+    })
+}
+
+/// Encode a multiprecision integer: bit count then the big-endian value
+fn mpi(value: &[u8]) -> Vec<u8> {
+    let start = value.iter().position(|&b| b != 0).unwrap_or(value.len());
+    let value = &value[start..];
+    let bits = match value.first() {
+        Some(first) => (value.len() - 1) * 8 + (8 - first.leading_zeros() as usize),
+        None => 0,
+    };
+    let mut out = (bits as u16).to_be_bytes().to_vec();
+    out.extend_from_slice(value);
+    out
+}
+
+/// Encode a new-format packet length
+fn packet_length(len: usize) -> Vec<u8> {
+    match len {
+        0..=191 => vec![len as u8],
+        192..=8383 => {
+            let len = len - 192;
+            vec![(len >> 8) as u8 + 192, len as u8]
+        }
+        _ => {
+            let mut out = vec![0xFF];
+            out.extend((len as u32).to_be_bytes());
+            out
+        }
+    }
+}
+
+/// Body of a version 4 public key packet
+fn public_key_body(
+    algorithm: KeyAlgorithm,
+    slot: OpenPgpKeySlot,
+    public_key: &PublicKeyMaterial,
+    timestamp: u32,
+) -> Result<Vec<u8>> {
+    let algorithm_id = algorithm.algorithm_id(slot);
+    let mut body = vec![KEY_PACKET_VERSION];
+    body.extend(timestamp.to_be_bytes());
+    body.push(algorithm_id);
+
+    match public_key {
+        PublicKeyMaterial::Rsa { modulus, exponent } => {
+            body.extend(mpi(modulus));
+            body.extend(mpi(exponent));
+        }
+        PublicKeyMaterial::Ec { point } => {
+            let oid = algorithm
+                .curve_oid()
+                .ok_or_else(|| anyhow!("{:?} is not an EC algorithm", algorithm))?;
+            body.push(oid.len() as u8);
+            body.extend_from_slice(oid);
+            let point = match algorithm {
+                KeyAlgorithm::Ed25519 | KeyAlgorithm::Cv25519 => {
+                    [&[NATIVE_POINT_PREFIX][..], point].concat()
+                }
+                _ => point.clone(),
+            };
+            body.extend(mpi(&point));
+            if algorithm_id == ALGORITHM_ECDH {
+                body.extend(algorithm.kdf_parameters());
+            }
+        }
+    }
+    Ok(body)
+}
+
+/// Version 4 fingerprint: SHA-1 over the key packet body with a fixed header
+fn fingerprint(body: &[u8]) -> [u8; FINGERPRINT_LEN] {
+    let mut hasher = Sha1::new();
+    hasher.update([FINGERPRINT_PREFIX]);
+    hasher.update((body.len() as u16).to_be_bytes());
+    hasher.update(body);
+    hasher.finalize().into()
+}
+
+/// Wrap a body in a new-format packet header
+fn packet(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![tag];
+    packet.extend(packet_length(body.len()));
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn hash(algorithm: u8, data: &[u8]) -> Vec<u8> {
+    match algorithm {
+        HASH_SHA384 => Sha384::digest(data).to_vec(),
+        _ => Sha256::digest(data).to_vec(),
+    }
+}
+
+/// Signature fields covered by the hash: version through hashed subpackets
+fn certification_hashed_part(
+    algorithm_id: u8,
+    hash_algorithm: u8,
+    fingerprint: &[u8; FINGERPRINT_LEN],
+    created: u32,
+) -> Vec<u8> {
+    let mut subpackets = vec![1 + TIMESTAMP_LEN as u8, SUBPACKET_CREATION_TIME];
+    subpackets.extend(created.to_be_bytes());
+    subpackets.extend([2, SUBPACKET_KEY_FLAGS, KEY_FLAGS_CERTIFY_SIGN]);
+    subpackets.extend([
+        2 + FINGERPRINT_LEN as u8,
+        SUBPACKET_ISSUER_FINGERPRINT,
+        KEY_PACKET_VERSION,
+    ]);
+    subpackets.extend_from_slice(fingerprint);
+
+    let mut hashed = vec![
+        SIGNATURE_VERSION,
+        SIGNATURE_POSITIVE_CERTIFICATION,
+        algorithm_id,
+        hash_algorithm,
+    ];
+    hashed.extend((subpackets.len() as u16).to_be_bytes());
+    hashed.extend(subpackets);
+    hashed
+}
+
+/// Digest of a User ID certification (RFC 4880 section 5.2.4)
+fn certification_digest(
+    key_body: &[u8],
+    user_id: &str,
+    hashed_part: &[u8],
+    hash_algorithm: u8,
+) -> Vec<u8> {
+    let mut data = vec![FINGERPRINT_PREFIX];
+    data.extend((key_body.len() as u16).to_be_bytes());
+    data.extend_from_slice(key_body);
+    data.push(USER_ID_HASH_PREFIX);
+    data.extend((user_id.len() as u32).to_be_bytes());
+    data.extend_from_slice(user_id.as_bytes());
+    data.extend_from_slice(hashed_part);
+    data.extend([SIGNATURE_VERSION, 0xFF]);
+    data.extend((hashed_part.len() as u32).to_be_bytes());
+    hash(hash_algorithm, &data)
+}
+
+/// Input to PSO:COMPUTE DIGITAL SIGNATURE: a DigestInfo for RSA, else the digest
+fn signature_input(algorithm: KeyAlgorithm, hash_algorithm: u8, digest: &[u8]) -> Vec<u8> {
+    if algorithm.rsa_bits().is_none() {
+        return digest.to_vec();
+    }
+    let prefix = match hash_algorithm {
+        HASH_SHA384 => &DIGEST_INFO_SHA384,
+        _ => &DIGEST_INFO_SHA256,
+    };
+    [&prefix[..], digest].concat()
+}
+
+/// Signature packet body around the signature the card returned
+///
+/// For ECDSA and EdDSA the card returns r and s concatenated, which become
+/// two MPIs.
+fn signature_body(
+    algorithm: KeyAlgorithm,
+    hashed_part: &[u8],
+    fingerprint: &[u8; FINGERPRINT_LEN],
+    digest: &[u8],
+    signature: &[u8],
+) -> Result<Vec<u8>> {
+    let mut body = hashed_part.to_vec();
+    let mut unhashed = vec![1 + KEY_ID_LEN as u8, SUBPACKET_ISSUER];
+    unhashed.extend_from_slice(&fingerprint[FINGERPRINT_LEN - KEY_ID_LEN..]);
+    body.extend((unhashed.len() as u16).to_be_bytes());
+    body.extend(unhashed);
+    body.extend_from_slice(&digest[..2]);
+
+    if algorithm.rsa_bits().is_some() {
+        if signature.is_empty() {
+            return Err(Error::MalformedResponse("Empty RSA signature".to_string()).into());
+        }
+        body.extend(mpi(signature));
+    } else {
+        if signature.is_empty() || !signature.len().is_multiple_of(2) {
+            return Err(Error::MalformedResponse(format!(
+                "EC signature must be r and s of equal length, got {} bytes",
+                signature.len()
+            ))
+            .into());
+        }
+        let (r, s) = signature.split_at(signature.len() / 2);
+        body.extend(mpi(r));
+        body.extend(mpi(s));
+    }
+    Ok(body)
+}
+
+/// The key packet, then the User ID and its self-signature if certified
+fn key_packets(key_body: &[u8], certification: Option<(&str, &[u8])>) -> Vec<u8> {
+    let mut packets = packet(PACKET_TAG_PUBLIC_KEY, key_body);
+    if let Some((user_id, signature)) = certification {
+        packets.extend(packet(PACKET_TAG_USER_ID, user_id.as_bytes()));
+        packets.extend(packet(PACKET_TAG_SIGNATURE, signature));
+    }
+    packets
+}
+
+fn build_sign_apdu(input: &[u8]) -> Vec<u8> {
+    let [p1, p2] = PSO_COMPUTE_DIGITAL_SIGNATURE;
+    let mut apdu = build_password_apdu(INS_PSO, p1, p2, input);
+    apdu.push(0x00);
+    apdu
+}
+
+/// CRC-24 checksum used by ASCII armor
+fn crc24(data: &[u8]) -> u32 {
+    let mut crc = CRC24_INIT;
+    for &byte in data {
+        crc ^= u32::from(byte) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x0100_0000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc & 0x00FF_FFFF
+}
+
+/// ASCII-armor a sequence of public key packets
+fn armor_public_key(packet: &[u8]) -> String {
+    let encoded = Base64::encode_string(packet);
+    let mut armored = String::from("-----BEGIN PGP PUBLIC KEY BLOCK-----\n\n");
+    for line in encoded.as_bytes().chunks(ARMOR_LINE_LEN) {
+        armored.push_str(&String::from_utf8_lossy(line));
+        armored.push('\n');
+    }
+    armored.push('=');
+    armored.push_str(&Base64::encode_string(&crc24(packet).to_be_bytes()[1..]));
+    armored.push_str("\n-----END PGP PUBLIC KEY BLOCK-----\n");
+    armored
+}
+
+/// Parse the public key template (7F49) returned by GENERATE ASYMMETRIC KEY PAIR
+fn parse_public_key_template(
+    response: &[u8],
+    algorithm: KeyAlgorithm,
+) -> Result<PublicKeyMaterial> {
+    let template = tlv::find_path(response, &[&TAG_PUBLIC_KEY])?.ok_or_else(|| {
+        Error::MalformedResponse("Public key template (7F49) missing".to_string())
+    })?;
+    let elements = tlv::parse(&template)?;
+    let element = |tag: &[u8], name: &str| {
+        tlv::find(&elements, tag)
+            .map(|tlv| tlv.value.clone())
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| Error::MalformedResponse(format!("Public key {} missing", name)))
+    };
+
+    if algorithm.rsa_bits().is_some() {
+        Ok(PublicKeyMaterial::Rsa {
+            modulus: element(&TAG_RSA_MODULUS, "modulus")?,
+            exponent: element(&TAG_RSA_EXPONENT, "exponent")?,
+        })
+    } else {
+        Ok(PublicKeyMaterial::Ec {
+            point: element(&TAG_EC_POINT, "point")?,
+        })
+    }
+}
+
+/// Left-pad a secret to `len` bytes
+fn padded_secret(value: &[u8], len: usize) -> Result<Zeroizing<Vec<u8>>> {
+    if value.len() > len {
+        return Err(anyhow!("Key element longer than {} bytes", len));
+    }
+    let mut padded = Zeroizing::new(vec![0u8; len - value.len()]);
+    padded.extend_from_slice(value);
+    Ok(padded)
+}
+
+fn rsa_import_key(key: rsa::RsaPrivateKey) -> Result<ImportKey> {
+    use rsa::traits::{PrivateKeyParts, PublicKeyParts};
+
+    let algorithm = match key.size() * 8 {
+        2048 => KeyAlgorithm::Rsa2048,
+        3072 => KeyAlgorithm::Rsa3072,
+        4096 => KeyAlgorithm::Rsa4096,
+        bits => {
+            return Err(
+                Error::InvalidParams(format!("Unsupported RSA key size: {} bits", bits)).into(),
+            )
+        }
+    };
+    let primes = key.primes();
+    if primes.len() != 2 {
+        return Err(
+            Error::InvalidParams("Multi-prime RSA keys are not supported".to_string()).into(),
+        );
+    }
+
+    let prime_len = key.size() / 2;
+    Ok(ImportKey {
+        algorithm,
+        public_key: PublicKeyMaterial::Rsa {
+            modulus: key.n().to_bytes_be(),
+            exponent: key.e().to_bytes_be(),
+        },
+        rsa_prime_len: prime_len,
+        secrets: vec![
+            Zeroizing::new(key.e().to_bytes_be()),
+            Zeroizing::new(primes[0].to_bytes_be()),
+            Zeroizing::new(primes[1].to_bytes_be()),
+        ],
+    })
+}
+
+fn ec_import_key<C>(key: SecretKey<C>, algorithm: KeyAlgorithm) -> ImportKey
+where
+    C: CurveArithmetic,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    FieldBytesSize<C>: ModulusSize,
+{
+    ImportKey {
+        algorithm,
+        public_key: PublicKeyMaterial::Ec {
+            point: key.public_key().to_encoded_point(false).as_bytes().to_vec(),
+        },
+        rsa_prime_len: 0,
+        secrets: vec![Zeroizing::new(key.to_bytes().to_vec())],
+    }
+}
+
+fn ed25519_import_key(key: ed25519_dalek::SigningKey) -> ImportKey {
+    ImportKey {
+        algorithm: KeyAlgorithm::Ed25519,
+        public_key: PublicKeyMaterial::Ec {
+            point: key.verifying_key().to_bytes().to_vec(),
+        },
+        rsa_prime_len: 0,
+        secrets: vec![Zeroizing::new(key.to_bytes().to_vec())],
+    }
+}
+
+/// Decode an RSA, NIST P-256/P-384 or Ed25519 private key for import
+///
+/// Accepts the same PEM and hex PKCS#8 inputs as PIV key import.
+fn decode_import_key(input: &str) -> Result<ImportKey> {
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::pkcs8::DecodePrivateKey;
+
+    let (label, der) = piv::decode_private_key_der(input)?;
+    let unsupported = || {
+        Error::InvalidParams(
+            "Unsupported private key: expected RSA, P-256, P-384 or Ed25519".to_string(),
+        )
+        .into()
+    };
+    match label.as_str() {
+        "PRIVATE KEY" => {
+            if let Ok(key) = rsa::RsaPrivateKey::from_pkcs8_der(&der) {
+                rsa_import_key(key)
+            } else if let Ok(key) = p256::SecretKey::from_pkcs8_der(&der) {
+                Ok(ec_import_key(key, KeyAlgorithm::NistP256))
+            } else if let Ok(key) = p384::SecretKey::from_pkcs8_der(&der) {
+                Ok(ec_import_key(key, KeyAlgorithm::NistP384))
+            } else if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_der(&der) {
+                Ok(ed25519_import_key(key))
+            } else {
+                Err(unsupported())
+            }
+        }
+        "RSA PRIVATE KEY" => rsa::RsaPrivateKey::from_pkcs1_der(&der)
+            .map_err(|e| Error::InvalidParams(format!("Invalid RSA private key: {}", e)).into())
+            .and_then(rsa_import_key),
+        "EC PRIVATE KEY" => {
+            if let Ok(key) = p256::SecretKey::from_sec1_der(&der) {
+                Ok(ec_import_key(key, KeyAlgorithm::NistP256))
+            } else if let Ok(key) = p384::SecretKey::from_sec1_der(&der) {
+                Ok(ec_import_key(key, KeyAlgorithm::NistP384))
+            } else {
+                Err(unsupported())
+            }
+        }
+        "ENCRYPTED PRIVATE KEY" => Err(Error::InvalidParams(
+            "Encrypted private keys are not supported, decrypt the key first".to_string(),
+        )
+        .into()),
+        other => Err(Error::InvalidParams(format!(
+            "Expected a PRIVATE KEY PEM block, found {}",
+            other
+        ))
+        .into()),
+    }
+}
+
+/// Build the extended header list (4D) carrying a private key
+///
+/// RSA keys use the standard e, p, q format, with e padded to the exponent
+/// size from the slot's algorithm attributes.
+fn build_extended_header_list(
+    slot: OpenPgpKeySlot,
+    key: &ImportKey,
+    exponent_bits: u16,
+) -> Result<Zeroizing<Vec<u8>>> {
+    let elements: Vec<(u8, Zeroizing<Vec<u8>>)> = if key.algorithm.rsa_bits().is_some() {
+        let exponent_len = usize::from(exponent_bits).div_ceil(8);
+        vec![
+            (
+                TAG_IMPORT_RSA_EXPONENT,
+                padded_secret(&key.secrets[0], exponent_len)?,
+            ),
+            (
+                TAG_IMPORT_RSA_PRIME_P,
+                padded_secret(&key.secrets[1], key.rsa_prime_len)?,
+            ),
+            (
+                TAG_IMPORT_RSA_PRIME_Q,
+                padded_secret(&key.secrets[2], key.rsa_prime_len)?,
+            ),
+        ]
+    } else {
+        vec![(TAG_IMPORT_EC_PRIVATE, key.secrets[0].clone())]
+    };
+
+    let mut template = Vec::new();
+    let data_len = elements.iter().map(|(_, value)| value.len()).sum();
+    let mut data = Zeroizing::new(Vec::with_capacity(data_len));
+    for (tag, value) in &elements {
+        template.push(*tag);
+        template.extend(tlv::encode_length(value.len()));
+        data.extend_from_slice(value);
+    }
+
+    // Sized up front so the secrets are never left behind by a reallocation
+    let crt = tlv::encode(&[slot.control_reference()], &[]);
+    let template = tlv::encode(&TAG_PRIVATE_KEY_TEMPLATE, &template);
+    let data_header = [&TAG_PRIVATE_KEY_DATA[..], &tlv::encode_length(data.len())].concat();
+    let list_len = crt.len() + template.len() + data_header.len() + data.len();
+    let list_header = [&TAG_EXTENDED_HEADER_LIST[..], &tlv::encode_length(list_len)].concat();
+
+    let mut list = Zeroizing::new(Vec::with_capacity(list_header.len() + list_len));
+    list.extend(list_header);
+    list.extend(crt);
+    list.extend(template);
+    list.extend(data_header);
+    list.extend_from_slice(&data);
+    Ok(list)
+}
+
+fn build_put_data_apdu(tag: u16, data: &[u8]) -> Vec<u8> {
+    let [p1, p2] = tag.to_be_bytes();
+    build_password_apdu(INS_PUT_DATA, p1, p2, data)
+}
+
+fn build_generate_apdu(slot: OpenPgpKeySlot) -> Vec<u8> {
+    vec![
+        0x00,
+        INS_GENERATE_ASYMMETRIC,
+        GENERATE_KEY_PAIR,
+        0x00,
+        0x02,
+        slot.control_reference(),
+        0x00,
+        0x00,
+    ]
+}
+
+/// Select the application, verify PW3 and read the current status
+fn prepare_admin(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    admin_pin: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<OpenPgpStatus> {
+    select_application(device_manager, device_id, activity_log)?;
+    verify_password(
+        device_manager,
+        device_id,
+        PasswordType::Admin,
+        admin_pin,
+        activity_log,
+    )?;
+    let data = get_data(
+        device_manager,
+        device_id,
+        DO_APPLICATION_RELATED_DATA,
+        "Application Related Data",
+        activity_log,
+    )?;
+    parse_application_data(&data)
+}
+
+/// Write the algorithm attributes of a slot unless it already uses `algorithm`
+///
+/// Returns the RSA exponent size in effect afterwards (zero for EC keys).
+fn write_algorithm_attributes(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    slot: OpenPgpKeySlot,
+    algorithm: KeyAlgorithm,
+    status: &OpenPgpStatus,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<u16> {
+    algorithm.check_slot(slot)?;
+    let current = status.keys[slot.index()].algorithm.as_ref();
+    let attributes = algorithm.attributes(slot, current);
+
+    if current.and_then(KeyAlgorithm::from_attributes) != Some(algorithm) {
+        transmit(
+            device_manager,
+            device_id,
+            &build_put_data_apdu(slot.data_object(DO_ALGORITHM_ATTRIBUTES), &attributes),
+            &format!("PUT DATA (Algorithm Attributes {:?})", slot),
+            activity_log,
+        )?;
+    }
+    match parse_algorithm_attributes(&attributes) {
+        OpenPgpAlgorithm::Rsa { exponent_bits, .. } => Ok(exponent_bits),
+        _ => Ok(0),
+    }
+}
+
+/// Only the signature slot can sign, so only its key can certify a User ID
+fn check_certification(
+    slot: OpenPgpKeySlot,
+    certification: Option<&SelfCertification>,
+) -> Result<()> {
+    let Some(certification) = certification else {
+        return Ok(());
+    };
+    if slot != OpenPgpKeySlot::Signature {
+        return Err(Error::InvalidParams(format!(
+            "Only the signature key can be self-certified, not the {:?} key",
+            slot
+        ))
+        .into());
+    }
+    if certification.user_id.is_empty() {
+        return Err(Error::InvalidParams("User ID must not be empty".to_string()).into());
+    }
+    Ok(())
+}
+
+/// Sign a positive certification of `user_id` with the signature key
+///
+/// Returns the signature packet body. PW1 is verified first, as
+/// PSO:COMPUTE DIGITAL SIGNATURE requires.
+fn self_certify(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    algorithm: KeyAlgorithm,
+    key_body: &[u8],
+    fingerprint: &[u8; FINGERPRINT_LEN],
+    certification: &SelfCertification,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<Vec<u8>> {
+    let hash_algorithm = algorithm.signature_hash();
+    let created = key_timestamp(None)?;
+    let hashed_part = certification_hashed_part(
+        algorithm.algorithm_id(OpenPgpKeySlot::Signature),
+        hash_algorithm,
+        fingerprint,
+        created,
+    );
+    let digest = certification_digest(
+        key_body,
+        certification.user_id,
+        &hashed_part,
+        hash_algorithm,
+    );
+
+    verify_password(
+        device_manager,
+        device_id,
+        PasswordType::Signing,
+        certification.pin,
+        activity_log,
+    )?;
+    let signature = transmit(
+        device_manager,
+        device_id,
+        &build_sign_apdu(&signature_input(algorithm, hash_algorithm, &digest)),
+        "PSO: COMPUTE DIGITAL SIGNATURE (Self-Certification)",
+        activity_log,
+    )?;
+    signature_body(algorithm, &hashed_part, fingerprint, &digest, &signature)
+}
+
+/// A key now held in a slot, with the creation time it is published under
+struct SlotKey<'a> {
+    slot: OpenPgpKeySlot,
+    algorithm: KeyAlgorithm,
+    public_key: &'a PublicKeyMaterial,
+    timestamp: u32,
+}
+
+/// Compute the packet and fingerprint, and store fingerprint and time on the card
+///
+/// With a `certification` the User ID and its self-signature follow the key
+/// packet in the armored output, which makes it importable.
+fn finish_key(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    key: SlotKey,
+    certification: Option<&SelfCertification>,
+    activity_log: Vec<ApduLog>,
+) -> Result<OpenPgpKeyResult> {
+    let SlotKey {
+        slot,
+        algorithm,
+        public_key,
+        timestamp,
+    } = key;
+    let mut activity_log = activity_log;
+    let body = public_key_body(algorithm, slot, public_key, timestamp)?;
+    let fingerprint = fingerprint(&body);
+
+    transmit(
+        device_manager,
+        device_id,
+        &build_put_data_apdu(slot.data_object(DO_FINGERPRINT), &fingerprint),
+        &format!("PUT DATA (Fingerprint {:?})", slot),
+        &mut activity_log,
+    )?;
+    transmit(
+        device_manager,
+        device_id,
+        &build_put_data_apdu(
+            slot.data_object(DO_GENERATION_TIME),
+            &timestamp.to_be_bytes(),
+        ),
+        &format!("PUT DATA (Generation Time {:?})", slot),
+        &mut activity_log,
+    )?;
+
+    let signature = match certification {
+        Some(certification) => Some(self_certify(
+            device_manager,
+            device_id,
+            algorithm,
+            &body,
+            &fingerprint,
+            certification,
+            &mut activity_log,
+        )?),
+        None => None,
+    };
+    let user_id = certification.map(|c| c.user_id);
+    let packets = key_packets(&body, user_id.zip(signature.as_deref()));
+
+    Ok(OpenPgpKeyResult {
+        slot,
+        algorithm,
+        fingerprint: hex::encode(fingerprint),
+        generation_time: format_timestamp(&timestamp.to_be_bytes()).unwrap_or_default(),
+        public_key_packet: hex::encode(packet(PACKET_TAG_PUBLIC_KEY, &body)),
+        user_id: user_id.map(str::to_string),
+        public_key_armored: armor_public_key(&packets),
+        activity_log,
+    })
+}
+
+/// Key creation time, defaulting to now
+fn key_timestamp(timestamp: Option<u32>) -> Result<u32> {
+    match timestamp {
+        Some(0) => {
+            Err(Error::InvalidParams("Key creation time must not be zero".to_string()).into())
+        }
+        Some(timestamp) => Ok(timestamp),
+        None => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|_| anyhow!("System clock is before 1970"))?;
+            u32::try_from(now.as_secs()).map_err(|_| anyhow!("System clock is past 2106"))
+        }
+    }
+}
+
+/// Change the algorithm attributes of a slot
+pub fn set_algorithm(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    slot: OpenPgpKeySlot,
+    algorithm: KeyAlgorithm,
+    admin_pin: &str,
+) -> Result<Vec<ApduLog>> {
+    log::debug!("Setting OpenPGP {:?} slot to {:?}...", slot, algorithm);
+
+    let mut activity_log = Vec::new();
+    let status = prepare_admin(device_manager, device_id, admin_pin, &mut activity_log)?;
+    write_algorithm_attributes(
+        device_manager,
+        device_id,
+        slot,
+        algorithm,
+        &status,
+        &mut activity_log,
+    )?;
+
+    log::info!("OpenPGP {:?} slot set to {:?}", slot, algorithm);
+    Ok(activity_log)
+}
+
+/// Generate a key pair on the card
+///
+/// Without `algorithm` the slot's current attributes are used. The
+/// fingerprint and generation time are written back to the card so that
+/// GnuPG recognises the key. A signature key can be self-certified with a
+/// User ID, see `finish_key`.
+pub fn generate_key(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    slot: OpenPgpKeySlot,
+    algorithm: Option<KeyAlgorithm>,
+    admin_pin: &str,
+    timestamp: Option<u32>,
+    certification: Option<&SelfCertification>,
+) -> Result<OpenPgpKeyResult> {
+    let timestamp = key_timestamp(timestamp)?;
+    check_certification(slot, certification)?;
+    log::debug!("Generating OpenPGP {:?} key...", slot);
+
+    let mut activity_log = Vec::new();
+    let status = prepare_admin(device_manager, device_id, admin_pin, &mut activity_log)?;
+    let algorithm = match algorithm {
+        Some(algorithm) => {
+            write_algorithm_attributes(
+                device_manager,
+                device_id,
+                slot,
+                algorithm,
+                &status,
+                &mut activity_log,
+            )?;
+            algorithm
+        }
+        None => status.keys[slot.index()]
+            .algorithm
+            .as_ref()
+            .and_then(KeyAlgorithm::from_attributes)
+            .ok_or_else(|| {
+                Error::InvalidParams(format!(
+                    "The {:?} slot uses an unsupported algorithm, pass one explicitly",
+                    slot
+                ))
+            })?,
+    };
+
+    let response = transmit(
+        device_manager,
+        device_id,
+        &build_generate_apdu(slot),
+        &format!("GENERATE ASYMMETRIC KEY PAIR ({:?})", slot),
+        &mut activity_log,
+    )?;
+    let public_key = parse_public_key_template(&response, algorithm)?;
+
+    let result = finish_key(
+        device_manager,
+        device_id,
+        SlotKey {
+            slot,
+            algorithm,
+            public_key: &public_key,
+            timestamp,
+        },
+        certification,
+        activity_log,
+    )?;
+    log::info!("OpenPGP {:?} key generated: {}", slot, result.fingerprint);
+    Ok(result)
+}
+
+/// Import a private key into a slot
+///
+/// `timestamp` should be the key's original creation time so the fingerprint
+/// matches the existing OpenPGP key. A signature key can be self-certified
+/// with a User ID, see `finish_key`.
+pub fn import_key(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    slot: OpenPgpKeySlot,
+    private_key: &str,
+    admin_pin: &str,
+    timestamp: Option<u32>,
+    certification: Option<&SelfCertification>,
+) -> Result<OpenPgpKeyResult> {
+    let timestamp = key_timestamp(timestamp)?;
+    check_certification(slot, certification)?;
+    let key = decode_import_key(private_key)?;
+    key.algorithm.check_slot(slot)?;
+    log::debug!(
+        "Importing {:?} key into OpenPGP {:?} slot...",
+        key.algorithm,
+        slot
+    );
+
+    let mut activity_log = Vec::new();
+    let status = prepare_admin(device_manager, device_id, admin_pin, &mut activity_log)?;
+    let exponent_bits = write_algorithm_attributes(
+        device_manager,
+        device_id,
+        slot,
+        key.algorithm,
+        &status,
+        &mut activity_log,
+    )?;
+
+    let data = build_extended_header_list(slot, &key, exponent_bits)?;
+    let apdus = Zeroizing::new(apdu::build_chained_apdus(
+        INS_PUT_DATA_ODD,
+        0x3F,
+        0xFF,
+        &data,
+    ));
+    apdu::transmit_chain(
+        device_manager,
+        device_id,
+        &apdus,
+        Redact::Command,
+        &format!("PUT DATA (Import Key {:?})", slot),
+        &mut activity_log,
+    )?;
+
+    let result = finish_key(
+        device_manager,
+        device_id,
+        SlotKey {
+            slot,
+            algorithm: key.algorithm,
+            public_key: &key.public_key,
+            timestamp,
+        },
+        certification,
+        activity_log,
+    )?;
+    log::info!("OpenPGP {:?} key imported: {}", slot, result.fingerprint);
+    Ok(result)
+}
+
+/// Exhaust PW1 or PW3 with wrong VERIFY attempts
+///
+/// Skipped when the PW status already shows no retries left.
+fn block_password(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    password: PasswordType,
+    retries: Option<u8>,
+    progress: &mut ProgressReporter,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<()> {
+    let (name, stage) = match password {
+        PasswordType::Admin => ("PW3", "blockAdminPin"),
+        _ => ("PW1", "blockPin"),
+    };
+    if retries == Some(0) {
+        progress.report(stage, format!("{} already blocked", name), activity_log);
+        return Ok(());
+    }
+
+    let send_wrong = |guess: &str, attempt: u32, activity_log: &mut Vec<ApduLog>| {
+        transmit_password(
+            device_manager,
+            device_id,
+            &build_verify_apdu(password, guess),
+            &format!("Block {} (attempt {})", name, attempt + 1),
+            password.retry_counter(),
+            activity_log,
+        )
+        .map(|_| ())
+    };
+    apdu::block_with_wrong_guesses(name, stage, send_wrong, progress, activity_log)
+}
+
+/// Whether SELECT failed because the application is already terminated
+fn is_terminated(error: &anyhow::Error) -> bool {
+    matches!(
+        crate::error::classify(error),
+        Some(Error::StatusWord {
+            sw1: 0x62,
+            sw2: 0x85
+        })
+    )
+}
+
+/// Differences from a freshly reset card
+fn default_state_warnings(status: &OpenPgpStatus) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for key in &status.keys {
+        if key.fingerprint.is_some() {
+            warnings.push(format!("{:?} slot still holds a key", key.slot));
+        }
+    }
+    match &status.pw_status {
+        Some(pw_status) => {
+            if pw_status.pw1_retries == 0 {
+                warnings.push("PW1 is still blocked".to_string());
+            }
+            if pw_status.pw3_retries == 0 {
+                warnings.push("PW3 is still blocked".to_string());
+            }
+        }
+        None => warnings.push("PW status could not be read".to_string()),
+    }
+    warnings
+}
+
+/// Reset the OpenPGP application to factory defaults
+///
+/// TERMINATE DF needs PW3 blocked, so PW1 and PW3 are exhausted first unless
+/// the application is already terminated. ACTIVATE FILE then reinitialises
+/// it. This erases all keys and cardholder data, which is why `confirmed`
+/// must be set. Each step is passed to `on_step` as it completes.
+pub fn reset(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    confirmed: bool,
+    on_step: &mut dyn FnMut(&ProgressStep),
+) -> Result<OpenPgpResetResult> {
+    if !confirmed {
+        return Err(Error::InvalidParams(
+            "Resetting OpenPGP erases all keys and cardholder data; confirm to proceed".to_string(),
+        )
+        .into());
+    }
+    log::warn!("Resetting OpenPGP application on device {}", device_id);
+
+    let mut activity_log = Vec::new();
+    let mut progress = ProgressReporter::new(on_step);
+
+    match select_application(device_manager, device_id, &mut activity_log) {
+        Ok(()) => {
+            progress.report(
+                "select",
+                "OpenPGP application selected".to_string(),
+                &activity_log,
+            );
+            let pw_status = read_pw_status(device_manager, device_id, &mut activity_log);
+            block_password(
+                device_manager,
+                device_id,
+                PasswordType::User,
+                pw_status.as_ref().map(|s| s.pw1_retries),
+                &mut progress,
+                &mut activity_log,
+            )?;
+            block_password(
+                device_manager,
+                device_id,
+                PasswordType::Admin,
+                pw_status.as_ref().map(|s| s.pw3_retries),
+                &mut progress,
+                &mut activity_log,
+            )?;
+
+            transmit(
+                device_manager,
+                device_id,
+                &[0x00, INS_TERMINATE_DF, 0x00, 0x00],
+                "TERMINATE DF",
+                &mut activity_log,
+            )?;
+            progress.report(
+                "terminate",
+                "OpenPGP application terminated".to_string(),
+                &activity_log,
+            );
+        }
+        Err(e) if is_terminated(&e) => {
+            progress.report(
+                "select",
+                "OpenPGP application is already terminated".to_string(),
+                &activity_log,
+            );
+        }
+        Err(e) => return Err(e),
+    }
+
+    transmit(
+        device_manager,
+        device_id,
+        &[0x00, INS_ACTIVATE_FILE, 0x00, 0x00],
+        "ACTIVATE FILE",
+        &mut activity_log,
+    )?;
+    progress.report(
+        "activate",
+        "OpenPGP application activated".to_string(),
+        &activity_log,
+    );
+
+    select_application(device_manager, device_id, &mut activity_log)?;
+    let status = read_status(device_manager, device_id, &mut activity_log)?;
+    let warnings = default_state_warnings(&status);
+    let message = if warnings.is_empty() {
+        "Defaults restored".to_string()
+    } else {
+        format!("Reset finished with warnings: {}", warnings.join("; "))
+    };
+    progress.report("verify", message, &activity_log);
+
+    log::info!("OpenPGP application reset on device {}", device_id);
+    Ok(OpenPgpResetResult {
+        status,
+        default_pin: DEFAULT_PW1.to_string(),
+        default_admin_pin: DEFAULT_PW3.to_string(),
+        warnings,
+        steps: progress.into_steps(),
+        activity_log,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn application_data() -> Vec<u8> {
+        let aid = hex::decode("d2760001240103040006123456780000").unwrap();
+        let mut fingerprints = vec![0xAB; FINGERPRINT_LEN];
+        fingerprints.extend([0x00; FINGERPRINT_LEN * 2]);
+        let mut timestamps = 1_700_000_000u32.to_be_bytes().to_vec();
+        timestamps.extend([0x00; TIMESTAMP_LEN * 2]);
+
+        let discretionary = Tlv::nested(
+            &TAG_DISCRETIONARY,
+            &[
+                Tlv::new(&[0xC1], &[0x01, 0x08, 0x00, 0x00, 0x20, 0x00]),
+                Tlv::new(&[0xC2], &hex::decode("122b060104019755010501").unwrap()),
+                Tlv::new(&[0xC3], &hex::decode("162b06010401da470f01ff").unwrap()),
+                Tlv::new(&TAG_PW_STATUS, &[0x00, 0x7F, 0x7F, 0x7F, 0x03, 0x00, 0x03]),
+                Tlv::new(&TAG_FINGERPRINTS, &fingerprints),
+                Tlv::new(&TAG_GENERATION_TIMES, &timestamps),
+                Tlv::new(&[0xD6], &[0x02, 0x20]),
+                Tlv::new(&[0xD7], &[0x00, 0x20]),
+            ],
+        );
+        tlv::encode_all(&[Tlv::new(&TAG_AID, &aid), discretionary])
+    }
+
+    #[test]
+    fn test_build_get_data_apdu() {
+        assert_eq!(
+            build_get_data_apdu(DO_URL),
+            vec![0x00, 0xCA, 0x5F, 0x50, 0x00]
+        );
+        assert_eq!(
+            build_get_data_apdu(DO_APPLICATION_RELATED_DATA),
+            vec![0x00, 0xCA, 0x00, 0x6E, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_parse_application_data() {
+        let status = parse_application_data(&application_data()).unwrap();
+        assert_eq!(status.version, "3.4");
+        assert_eq!(status.manufacturer_id, "0006");
+        assert_eq!(status.manufacturer.as_deref(), Some("Yubico"));
+        assert_eq!(status.serial_number, "12345678");
+
+        let pw = status.pw_status.unwrap();
+        assert!(!pw.pw1_valid_for_multiple_signatures);
+        assert_eq!(
+            (pw.pw1_retries, pw.reset_code_retries, pw.pw3_retries),
+            (3, 0, 3)
+        );
+
+        let [sig, dec, aut] = status.keys.as_slice() else {
+            panic!("expected three keys");
+        };
+        assert_eq!(sig.fingerprint, Some("ab".repeat(FINGERPRINT_LEN)));
+        assert_eq!(sig.generation_time.as_deref(), Some("2023-11-14T22:13:20Z"));
+        assert_eq!(
+            sig.algorithm,
+            Some(OpenPgpAlgorithm::Rsa {
+                modulus_bits: 2048,
+                exponent_bits: 32,
+                import_format: 0,
+            })
+        );
+        assert_eq!(sig.touch, Some(TouchSetting::Fixed));
+
+        assert_eq!(dec.fingerprint, None);
+        assert_eq!(dec.generation_time, None);
+        assert_eq!(
+            dec.algorithm,
+            Some(OpenPgpAlgorithm::Ecdh {
+                curve: "cv25519".to_string(),
+                with_public_key: false,
+            })
+        );
+        assert_eq!(dec.touch, Some(TouchSetting::Off));
+
+        assert_eq!(
+            aut.algorithm,
+            Some(OpenPgpAlgorithm::Eddsa {
+                curve: "ed25519".to_string(),
+                with_public_key: true,
+            })
+        );
+        assert_eq!(aut.touch, None);
+    }
+
+    #[test]
+    fn test_parse_application_data_requires_aid() {
+        let data = tlv::encode(&TAG_AID, &[0xA0, 0x00, 0x00, 0x03, 0x08]);
+        assert!(parse_application_data(&data).is_err());
+        assert!(parse_pw_status(&[0x00, 0x7F]).is_err());
+    }
+
+    #[test]
+    fn test_parse_cardholder_and_counter() {
+        let mut status = parse_application_data(&application_data()).unwrap();
+        let data = tlv::encode_all(&[
+            Tlv::new(&TAG_NAME, b"Doe<<Jane<Marie"),
+            Tlv::new(&TAG_LANGUAGE, b"ende"),
+            Tlv::new(&TAG_SEX, b"2"),
+        ]);
+        apply_cardholder_data(&mut status, &data).unwrap();
+        assert_eq!(status.cardholder_name.as_deref(), Some("Jane Marie Doe"));
+        assert_eq!(status.languages, vec!["en", "de"]);
+        assert_eq!(status.sex.as_deref(), Some("female"));
+
+        let template = tlv::encode(&TAG_SIGNATURE_COUNTER, &[0x00, 0x01, 0x02]);
+        assert_eq!(parse_signature_counter(&template).unwrap(), Some(0x0102));
+        assert_eq!(
+            parse_algorithm_attributes(&[0x13]),
+            OpenPgpAlgorithm::Unknown {
+                raw: "13".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_password_apdus() {
+        assert_eq!(
+            build_verify_apdu(PasswordType::Signing, "123456"),
+            b"\x00\x20\x00\x81\x06123456".to_vec()
+        );
+        assert_eq!(build_verify_apdu(PasswordType::Admin, "12345678")[3], PW3);
+        assert_eq!(
+            build_change_reference_apdu(PW1_SIGN, "123456", "654321"),
+            b"\x00\x24\x00\x81\x0c123456654321".to_vec()
+        );
+        assert_eq!(
+            build_reset_retry_counter_apdu(Some("12345678"), "123456"),
+            b"\x00\x2c\x00\x81\x0e12345678123456".to_vec()
+        );
+        assert_eq!(
+            build_reset_retry_counter_apdu(None, "123456"),
+            b"\x00\x2c\x02\x81\x06123456".to_vec()
+        );
+        assert_eq!(
+            build_set_reset_code_apdu(""),
+            vec![0x00, 0xDA, 0x00, 0xD3, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_wrong_password_error() {
+        let rejected = || anyhow::Error::from(Error::from_status_word(0x69, 0x82));
+        assert!(matches!(
+            crate::error::classify(&wrong_password_error(rejected(), || Some(2))),
+            Some(Error::PinInvalid { retries: Some(2) })
+        ));
+        assert!(matches!(
+            crate::error::classify(&wrong_password_error(rejected(), || Some(0))),
+            Some(Error::PinBlocked)
+        ));
+        // Without the PW status the 63Cx count is used
+        let error = wrong_password_error(Error::from_status_word(0x63, 0xC1).into(), || None);
+        assert!(matches!(
+            crate::error::classify(&error),
+            Some(Error::PinInvalid { retries: Some(1) })
+        ));
+        let error = wrong_password_error(rejected(), || None);
+        assert!(matches!(
+            crate::error::classify(&error),
+            Some(Error::PinInvalid { retries: None })
+        ));
+        // Anything else is not about the password
+        let error = wrong_password_error(Error::from_status_word(0x6A, 0x80).into(), || Some(0));
+        assert!(!matches!(
+            crate::error::classify(&error),
+            Some(Error::PinInvalid { .. } | Error::PinBlocked)
+        ));
+    }
+
+    #[test]
+    fn test_block_password_on_6982() {
+        // A card that answers every wrong PIN with 6982 and only shows the
+        // count in the PW status, then 6983 once it is blocked
+        let mut counter = 3u8;
+        let mut sent = 0;
+        let card = |_: &str, _: u32, _: &mut Vec<ApduLog>| -> Result<()> {
+            sent += 1;
+            if counter == 0 {
+                return Err(Error::from_status_word(0x69, 0x83).into());
+            }
+            counter -= 1;
+            Err(wrong_password_error(
+                Error::from_status_word(0x69, 0x82).into(),
+                || Some(counter),
+            ))
+        };
+        let mut messages = Vec::new();
+        let mut on_step = |step: &ProgressStep| messages.push(step.message.clone());
+        let mut progress = ProgressReporter::new(&mut on_step);
+        apdu::block_with_wrong_guesses("PW1", "blockPin", card, &mut progress, &mut Vec::new())
+            .unwrap();
+        assert_eq!(sent, 3);
+        drop(progress);
+        assert_eq!(messages.last().unwrap(), "PW1 blocked");
+
+        // Without a readable count it keeps going until 6983, however long
+        let mut counter = 20u8;
+        let card = |_: &str, _: u32, _: &mut Vec<ApduLog>| -> Result<()> {
+            if counter == 0 {
+                return Err(Error::from_status_word(0x69, 0x83).into());
+            }
+            counter -= 1;
+            Err(wrong_password_error(
+                Error::from_status_word(0x69, 0x82).into(),
+                || None,
+            ))
+        };
+        let mut on_step = |_: &ProgressStep| {};
+        let mut progress = ProgressReporter::new(&mut on_step);
+        apdu::block_with_wrong_guesses(
+            "PW3",
+            "blockAdminPin",
+            card,
+            &mut progress,
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        // Other errors stop the attempt
+        let card = |_: &str, _: u32, _: &mut Vec<ApduLog>| -> Result<()> {
+            Err(Error::from_status_word(0x6A, 0x80).into())
+        };
+        let mut progress = ProgressReporter::new(&mut on_step);
+        assert!(apdu::block_with_wrong_guesses(
+            "PW1",
+            "blockPin",
+            card,
+            &mut progress,
+            &mut Vec::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_check_password_length() {
+        assert!(check_password_length("PIN", "123456", PW1_MIN_LEN, Some(127)).is_ok());
+        assert!(check_password_length("PIN", "12345", PW1_MIN_LEN, None).is_err());
+        assert!(check_password_length("Admin PIN", "1234567", PW3_MIN_LEN, None).is_err());
+        assert!(check_password_length("PIN", "123456789", PW1_MIN_LEN, Some(8)).is_err());
+        assert_eq!(PasswordType::from_name("Admin"), Some(PasswordType::Admin));
+        assert_eq!(PasswordType::from_name("sign"), Some(PasswordType::Signing));
+        assert_eq!(PasswordType::from_name("so"), None);
+    }
+
+    // RFC 8032 test 1 Ed25519 seed as hex PKCS#8
+    const ED25519_PKCS8: &str = "302e020100300506032b657004220420\
+                                 9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    #[test]
+    fn test_default_state_warnings() {
+        let mut status = parse_application_data(&application_data()).unwrap();
+        status.keys[2].fingerprint = None;
+        assert_eq!(
+            default_state_warnings(&status),
+            vec!["Signature slot still holds a key".to_string()]
+        );
+
+        status.keys[0].fingerprint = None;
+        status.pw_status.as_mut().unwrap().pw3_retries = 0;
+        assert_eq!(
+            default_state_warnings(&status),
+            vec!["PW3 is still blocked".to_string()]
+        );
+
+        let terminated: anyhow::Error = Error::from_status_word(0x62, 0x85).into();
+        assert!(is_terminated(&terminated));
+        assert!(!is_terminated(&Error::PinBlocked.into()));
+    }
+
+    #[test]
+    fn test_mpi_and_packet_length() {
+        assert_eq!(mpi(&[0x00, 0x01]), vec![0x00, 0x01, 0x01]);
+        assert_eq!(mpi(&[0x40, 0xFF]), vec![0x00, 0x0F, 0x40, 0xFF]);
+        assert_eq!(mpi(&[0x00]), vec![0x00, 0x00]);
+        assert_eq!(packet_length(191), vec![0xBF]);
+        assert_eq!(packet_length(192), vec![0xC0, 0x00]);
+        assert_eq!(packet_length(8383), vec![0xDF, 0xFF]);
+        assert_eq!(packet_length(8384), vec![0xFF, 0x00, 0x00, 0x20, 0xC0]);
+    }
+
+    #[test]
+    fn test_crc24() {
+        assert_eq!(crc24(b"123456789"), 0x21CF02);
+        assert_eq!(crc24(b""), CRC24_INIT);
+    }
+
+    #[test]
+    fn test_ed25519_public_key_packet() {
+        let key = decode_import_key(ED25519_PKCS8).unwrap();
+        assert_eq!(key.algorithm, KeyAlgorithm::Ed25519);
+
+        let body = public_key_body(
+            key.algorithm,
+            OpenPgpKeySlot::Signature,
+            &key.public_key,
+            0x5F00_0000,
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(&body),
+            "045f00000016092b06010401da470f01010740\
+             d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+        assert_eq!(
+            hex::encode(fingerprint(&body)),
+            "32dc34731a202c31876fa1e6c4ad9e415bc6ac3f"
+        );
+
+        let packet = key_packets(&body, None);
+        assert_eq!(&packet[..2], &[0xC6, 0x33]);
+        let armored = armor_public_key(&packet);
+        assert!(armored.starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----\n\nxjMEXwAAABYJ"));
+        assert!(armored.ends_with("\n=v/Z5\n-----END PGP PUBLIC KEY BLOCK-----\n"));
+    }
+
+    #[test]
+    fn test_self_certified_key_packets() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let key = decode_import_key(ED25519_PKCS8).unwrap();
+        let body = public_key_body(
+            key.algorithm,
+            OpenPgpKeySlot::Signature,
+            &key.public_key,
+            0x5F00_0000,
+        )
+        .unwrap();
+        let fingerprint = fingerprint(&body);
+        let hash_algorithm = key.algorithm.signature_hash();
+        let hashed_part =
+            certification_hashed_part(ALGORITHM_EDDSA, hash_algorithm, &fingerprint, 0x5F00_0001);
+        assert_eq!(
+            hex::encode(&hashed_part),
+            "04131608002005025f000001021b031621\
+             0432dc34731a202c31876fa1e6c4ad9e415bc6ac3f"
+        );
+        let user_id = "Test User <test@example.com>";
+        let digest = certification_digest(&body, user_id, &hashed_part, hash_algorithm);
+        let input = signature_input(key.algorithm, hash_algorithm, &digest);
+        assert_eq!(input, digest);
+
+        // Sign as the card would with the same key
+        let seed: [u8; 32] = key.secrets[0].as_slice().try_into().unwrap();
+        let signature = SigningKey::from_bytes(&seed).sign(&input).to_bytes();
+        let signature = signature_body(
+            key.algorithm,
+            &hashed_part,
+            &fingerprint,
+            &digest,
+            &signature,
+        )
+        .unwrap();
+        let unhashed = &signature[hashed_part.len()..];
+        assert_eq!(&unhashed[..4], &[0x00, 0x0A, 0x09, SUBPACKET_ISSUER]);
+        assert_eq!(&unhashed[4..12], &fingerprint[12..]);
+        assert_eq!(&unhashed[12..14], &digest[..2]);
+        assert!(
+            signature_body(key.algorithm, &hashed_part, &fingerprint, &digest, &[0; 63]).is_err()
+        );
+
+        // Public key, User ID, then the self-signature
+        let packets = key_packets(&body, Some((user_id, &signature)));
+        let mut tags = Vec::new();
+        let mut rest = &packets[..];
+        while !rest.is_empty() {
+            let len = usize::from(rest[1]);
+            assert!(len < 192);
+            tags.push(rest[0]);
+            rest = &rest[2 + len..];
+        }
+        assert_eq!(
+            tags,
+            vec![
+                PACKET_TAG_PUBLIC_KEY,
+                PACKET_TAG_USER_ID,
+                PACKET_TAG_SIGNATURE
+            ]
+        );
+        assert_eq!(&packets[0x37..0x37 + user_id.len()], user_id.as_bytes());
+    }
+
+    #[test]
+    fn test_signature_input() {
+        let digest = [0xAB; 32];
+        let input = signature_input(KeyAlgorithm::Rsa2048, HASH_SHA256, &digest);
+        assert_eq!(&input[..19], &DIGEST_INFO_SHA256);
+        assert_eq!(&input[19..], &digest);
+        assert_eq!(KeyAlgorithm::NistP384.signature_hash(), HASH_SHA384);
+        assert_eq!(
+            build_sign_apdu(&[0x01, 0x02]),
+            vec![0x00, 0x2A, 0x9E, 0x9A, 0x02, 0x01, 0x02, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_cv25519_public_key_body_has_kdf_parameters() {
+        let point = PublicKeyMaterial::Ec {
+            point: vec![0x11; 32],
+        };
+        let body = public_key_body(
+            KeyAlgorithm::Cv25519,
+            OpenPgpKeySlot::Decryption,
+            &point,
+            0x5F00_0000,
+        )
+        .unwrap();
+        assert_eq!(body[5], ALGORITHM_ECDH);
+        assert_eq!(&body[body.len() - 4..], &[0x03, 0x01, 0x08, 0x07]);
+        assert_eq!(
+            hex::encode(fingerprint(&body)),
+            "7dc49b5ebd175ea1dbff038abd95533d8cf7a3bc"
+        );
+    }
+
+    #[test]
+    fn test_key_algorithm_attributes() {
+        let rsa = KeyAlgorithm::from_name("RSA-4096").unwrap();
+        assert_eq!(
+            rsa.attributes(OpenPgpKeySlot::Signature, None),
+            vec![0x01, 0x10, 0x00, 0x00, 0x20, 0x00]
+        );
+        let current = OpenPgpAlgorithm::Rsa {
+            modulus_bits: 2048,
+            exponent_bits: 17,
+            import_format: 0,
+        };
+        assert_eq!(
+            rsa.attributes(OpenPgpKeySlot::Signature, Some(&current)),
+            vec![0x01, 0x10, 0x00, 0x00, 0x11, 0x00]
+        );
+        assert_eq!(
+            KeyAlgorithm::from_attributes(&current),
+            Some(KeyAlgorithm::Rsa2048)
+        );
+
+        let p256 = KeyAlgorithm::from_name("p256").unwrap();
+        assert_eq!(
+            p256.attributes(OpenPgpKeySlot::Decryption, None)[0],
+            ALGORITHM_ECDH
+        );
+        assert_eq!(
+            hex::encode(p256.attributes(OpenPgpKeySlot::Authentication, None)),
+            "132a8648ce3d030107"
+        );
+        assert_eq!(
+            KeyAlgorithm::from_attributes(&parse_algorithm_attributes(
+                &KeyAlgorithm::Ed25519.attributes(OpenPgpKeySlot::Signature, None)
+            )),
+            Some(KeyAlgorithm::Ed25519)
+        );
+    }
+
+    #[test]
+    fn test_key_algorithm_slots() {
+        assert!(KeyAlgorithm::Ed25519
+            .check_slot(OpenPgpKeySlot::Authentication)
+            .is_ok());
+        assert!(KeyAlgorithm::Ed25519
+            .check_slot(OpenPgpKeySlot::Decryption)
+            .is_err());
+        assert!(KeyAlgorithm::Cv25519
+            .check_slot(OpenPgpKeySlot::Decryption)
+            .is_ok());
+        assert!(KeyAlgorithm::Cv25519
+            .check_slot(OpenPgpKeySlot::Signature)
+            .is_err());
+        assert_eq!(
+            OpenPgpKeySlot::from_name("enc"),
+            Some(OpenPgpKeySlot::Decryption)
+        );
+        assert_eq!(OpenPgpKeySlot::from_name("9a"), None);
+        assert_eq!(
+            build_generate_apdu(OpenPgpKeySlot::Authentication),
+            vec![0x00, 0x47, 0x80, 0x00, 0x02, 0xA4, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_build_extended_header_list() {
+        let key = decode_import_key(ED25519_PKCS8).unwrap();
+        let list = build_extended_header_list(OpenPgpKeySlot::Authentication, &key, 0).unwrap();
+        assert_eq!(hex::encode(&list[..12]), "4d2aa4007f480292205f4820");
+        assert_eq!(&list[12..], &key.secrets[0][..]);
+
+        let rsa = ImportKey {
+            algorithm: KeyAlgorithm::Rsa2048,
+            public_key: PublicKeyMaterial::Rsa {
+                modulus: vec![0xFF; 256],
+                exponent: vec![0x01, 0x00, 0x01],
+            },
+            rsa_prime_len: 128,
+            secrets: vec![
+                Zeroizing::new(vec![0x01, 0x00, 0x01]),
+                Zeroizing::new(vec![0xAA; 128]),
+                Zeroizing::new(vec![0xBB; 127]),
+            ],
+        };
+        let list = build_extended_header_list(OpenPgpKeySlot::Signature, &rsa, 32).unwrap();
+        let outer = tlv::parse(&list).unwrap();
+        let inner = tlv::parse(&outer[0].value).unwrap();
+        assert_eq!(inner[0].tag, vec![0xB6]);
+        assert_eq!(
+            inner[1].value,
+            vec![0x91, 0x04, 0x92, 0x81, 0x80, 0x93, 0x81, 0x80]
+        );
+        assert_eq!(inner[2].value.len(), 4 + 128 + 128);
+        assert_eq!(&inner[2].value[..4], &[0x00, 0x01, 0x00, 0x01]);
+        assert_eq!(inner[2].value[4 + 128], 0x00);
+    }
+
+    #[test]
+    fn test_decode_import_key_rejects_unsupported() {
+        // X25519 PKCS#8
+        let x25519 = "302e020100300506032b656e04220420\
+                      77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a";
+        assert!(decode_import_key(x25519).is_err());
+        assert!(key_timestamp(Some(0)).is_err());
+        assert_eq!(key_timestamp(Some(42)).unwrap(), 42);
+    }
+}
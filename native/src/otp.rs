@@ -0,0 +1,789 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+use crate::apdu::{self, ApduLog, Redact};
+use crate::device::{DeviceManager, DeviceType};
+use crate::error::Error;
+use crate::transport::{self, FEATURE_REPORT_SIZE};
+
+// OTP application AID, shared by Feitian and Yubico-compatible keys
+const OTP_AID: [u8; 7] = [0xA0, 0x00, 0x00, 0x05, 0x27, 0x20, 0x01];
+
+// Instruction carrying a slot command over CCID
+const INS_CONFIG: u8 = 0x01;
+
+// Slot commands
+const CMD_CONFIG_1: u8 = 0x01;
+const CMD_CONFIG_2: u8 = 0x03;
+const CMD_SWAP: u8 = 0x06;
+
+// Configuration layout
+const FIXED_SIZE: usize = 16;
+const UID_SIZE: usize = 6;
+const KEY_SIZE: usize = 16;
+const ACCESS_CODE_SIZE: usize = 6;
+const CONFIG_SIZE: usize = 52;
+const HMAC_KEY_SIZE: usize = 20;
+const SCAN_CODES_SIZE: usize = FIXED_SIZE + UID_SIZE + KEY_SIZE;
+
+// Status: firmware version (3), program sequence, touch level (2, LE)
+const STATUS_SIZE: usize = 6;
+const CONFIG1_VALID: u8 = 0x01;
+const CONFIG2_VALID: u8 = 0x02;
+const CONFIG1_TOUCH: u8 = 0x04;
+const CONFIG2_TOUCH: u8 = 0x08;
+
+// Ticket flags
+const TKTFLAG_APPEND_CR: u8 = 0x20;
+const TKTFLAG_OATH_HOTP: u8 = 0x40;
+const TKTFLAG_CHAL_RESP: u8 = 0x40;
+
+// Configuration flags
+const CFGFLAG_SHORT_TICKET: u8 = 0x02;
+const CFGFLAG_OATH_HOTP8: u8 = 0x02;
+const CFGFLAG_HMAC_LT64: u8 = 0x04;
+const CFGFLAG_CHAL_BTN_TRIG: u8 = 0x08;
+const CFGFLAG_CHAL_HMAC: u8 = 0x22;
+
+// Extended flags
+const EXTFLAG_SERIAL_API_VISIBLE: u8 = 0x04;
+const EXTFLAG_FAST_TRIG: u8 = 0x10;
+const EXTFLAG_ALLOW_UPDATE: u8 = 0x20;
+
+// Keyboard-HID framing: 64-byte payload, command, CRC and filler, sent as
+// seven data bytes plus a sequence byte per feature report
+const FRAME_PAYLOAD_SIZE: usize = 64;
+const FRAME_SIZE: usize = 70;
+const REPORT_DATA_SIZE: usize = FEATURE_REPORT_SIZE - 1;
+const SLOT_WRITE_FLAG: u8 = 0x80;
+
+const READY_TIMEOUT_MS: u64 = 1000;
+const PROGRAM_TIMEOUT_MS: u64 = 3000;
+const POLL_INTERVAL_MS: u64 = 10;
+
+// Modifier bit of a US keyboard scan code
+const SHIFT: u8 = 0x80;
+
+/// Reported state of one slot
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OtpSlotStatus {
+    pub slot: u8,
+    pub configured: bool,
+    pub touch_triggered: bool,
+}
+
+/// OTP status
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OtpStatus {
+    pub version: String,
+    /// Incremented by every successful configuration
+    pub program_sequence: u8,
+    pub slots: Vec<OtpSlotStatus>,
+}
+
+/// OTP status read from the device
+///
+/// The activity log only holds APDUs; keyboard-HID exchanges leave it empty.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OtpStatusResult {
+    pub status: OtpStatus,
+    pub activity_log: Vec<ApduLog>,
+}
+
+/// An OTP slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpSlot {
+    One,
+    Two,
+}
+
+impl OtpSlot {
+    pub fn from_number(number: u64) -> Option<Self> {
+        match number {
+            1 => Some(OtpSlot::One),
+            2 => Some(OtpSlot::Two),
+            _ => None,
+        }
+    }
+
+    fn number(self) -> u8 {
+        match self {
+            OtpSlot::One => 1,
+            OtpSlot::Two => 2,
+        }
+    }
+
+    fn config_command(self) -> u8 {
+        match self {
+            OtpSlot::One => CMD_CONFIG_1,
+            OtpSlot::Two => CMD_CONFIG_2,
+        }
+    }
+}
+
+/// What to program into a slot
+pub enum OtpSlotConfig {
+    /// Typed password, US keyboard layout
+    StaticPassword {
+        password: Zeroizing<String>,
+        append_enter: bool,
+    },
+    Hotp {
+        secret: Zeroizing<Vec<u8>>,
+        digits: u8,
+        /// Initial moving factor, a multiple of 16
+        counter: u32,
+        append_enter: bool,
+    },
+    /// HMAC-SHA1 challenge-response
+    ChallengeResponse {
+        key: Zeroizing<Vec<u8>>,
+        require_touch: bool,
+    },
+}
+
+/// Access codes for a slot command
+#[derive(Debug, Clone, Default)]
+pub struct OtpAccessCodes {
+    /// Code protecting the slot now
+    pub current: Option<[u8; ACCESS_CODE_SIZE]>,
+    /// Code to protect the slot with after programming
+    pub new: Option<[u8; ACCESS_CODE_SIZE]>,
+}
+
+/// Parse a 6-byte access code given as hex
+pub fn parse_access_code(value: &str) -> Result<[u8; ACCESS_CODE_SIZE]> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            Error::InvalidParams(format!(
+                "Access code must be {} hex characters",
+                ACCESS_CODE_SIZE * 2
+            ))
+            .into()
+        })
+}
+
+/// CRC-16 (ISO 13239) as used by the OTP protocol
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= u16::from(*byte);
+        for _ in 0..8 {
+            let carry = crc & 1;
+            crc >>= 1;
+            if carry != 0 {
+                crc ^= 0x8408;
+            }
+        }
+    }
+    crc
+}
+
+fn parse_status(data: &[u8]) -> Result<OtpStatus> {
+    // Version 0.x means nothing answered with an OTP status
+    if data.len() < STATUS_SIZE || data[0] == 0 {
+        return Err(Error::MalformedResponse(format!(
+            "Invalid OTP status: {}",
+            hex::encode_upper(data)
+        ))
+        .into());
+    }
+    let touch = data[4];
+    Ok(OtpStatus {
+        version: format!("{}.{}.{}", data[0], data[1], data[2]),
+        program_sequence: data[3],
+        slots: vec![
+            OtpSlotStatus {
+                slot: 1,
+                configured: touch & CONFIG1_VALID != 0,
+                touch_triggered: touch & CONFIG1_TOUCH != 0,
+            },
+            OtpSlotStatus {
+                slot: 2,
+                configured: touch & CONFIG2_VALID != 0,
+                touch_triggered: touch & CONFIG2_TOUCH != 0,
+            },
+        ],
+    })
+}
+
+/// Check the program sequence moved on
+///
+/// The device ignores commands with a wrong access code, so an unchanged
+/// sequence is the only sign of rejection. Removing the last configuration
+/// resets the sequence to zero.
+fn check_applied(before: &OtpStatus, after: &OtpStatus) -> Result<()> {
+    if after.program_sequence == before.program_sequence.wrapping_add(1) {
+        return Ok(());
+    }
+    if after.program_sequence == 0 && after.slots.iter().all(|slot| !slot.configured) {
+        return Ok(());
+    }
+    Err(
+        Error::OperationDenied("Configuration was not applied; check the access code".to_string())
+            .into(),
+    )
+}
+
+/// US keyboard scan code for a character
+fn scan_code(c: char) -> Option<u8> {
+    let code = match c {
+        'a'..='z' => 0x04 + (c as u8 - b'a'),
+        'A'..='Z' => SHIFT | (0x04 + (c as u8 - b'A')),
+        '1'..='9' => 0x1E + (c as u8 - b'1'),
+        '0' => 0x27,
+        '\n' => 0x28,
+        '\t' => 0x2B,
+        ' ' => 0x2C,
+        '-' => 0x2D,
+        '=' => 0x2E,
+        '[' => 0x2F,
+        ']' => 0x30,
+        '\\' => 0x32,
+        ';' => 0x33,
+        '\'' => 0x34,
+        '`' => 0x35,
+        ',' => 0x36,
+        '.' => 0x37,
+        '/' => 0x38,
+        '!' => SHIFT | 0x1E,
+        '@' => SHIFT | 0x1F,
+        '#' => SHIFT | 0x20,
+        '$' => SHIFT | 0x21,
+        '%' => SHIFT | 0x22,
+        '^' => SHIFT | 0x23,
+        '&' => SHIFT | 0x24,
+        '*' => SHIFT | 0x25,
+        '(' => SHIFT | 0x26,
+        ')' => SHIFT | 0x27,
+        '_' => SHIFT | 0x2D,
+        '+' => SHIFT | 0x2E,
+        '{' => SHIFT | 0x2F,
+        '}' => SHIFT | 0x30,
+        '|' => SHIFT | 0x32,
+        ':' => SHIFT | 0x33,
+        '"' => SHIFT | 0x34,
+        '~' => SHIFT | 0x35,
+        '<' => SHIFT | 0x36,
+        '>' => SHIFT | 0x37,
+        '?' => SHIFT | 0x38,
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Slot configuration fields before serialization
+#[derive(Default)]
+struct ConfigFields {
+    fixed: Vec<u8>,
+    uid: [u8; UID_SIZE],
+    key: [u8; KEY_SIZE],
+    ext_flags: u8,
+    tkt_flags: u8,
+    cfg_flags: u8,
+}
+
+impl Drop for ConfigFields {
+    fn drop(&mut self) {
+        use zeroize::Zeroize;
+        self.fixed.zeroize();
+        self.uid.zeroize();
+        self.key.zeroize();
+    }
+}
+
+impl ConfigFields {
+    /// Place a 20-byte HMAC key across the key and uid fields
+    fn with_hmac_key(key: &[u8]) -> Result<Self> {
+        if key.is_empty() || key.len() > HMAC_KEY_SIZE {
+            return Err(Error::InvalidParams(format!(
+                "HMAC-SHA1 key must be 1 to {} bytes",
+                HMAC_KEY_SIZE
+            ))
+            .into());
+        }
+        // HMAC zero-pads short keys, so padding here keeps the same codes
+        let mut padded = Zeroizing::new([0u8; HMAC_KEY_SIZE]);
+        padded[..key.len()].copy_from_slice(key);
+
+        let mut fields = ConfigFields::default();
+        fields.ext_flags = EXTFLAG_SERIAL_API_VISIBLE | EXTFLAG_ALLOW_UPDATE;
+        fields.key.copy_from_slice(&padded[..KEY_SIZE]);
+        fields.uid[..HMAC_KEY_SIZE - KEY_SIZE].copy_from_slice(&padded[KEY_SIZE..]);
+        Ok(fields)
+    }
+
+    fn from_config(config: &OtpSlotConfig) -> Result<Self> {
+        let invalid =
+            |message: &str| -> anyhow::Error { Error::InvalidParams(message.to_string()).into() };
+        match config {
+            OtpSlotConfig::StaticPassword {
+                password,
+                append_enter,
+            } => {
+                let mut codes = Zeroizing::new(Vec::with_capacity(password.len()));
+                for c in password.chars() {
+                    codes.push(scan_code(c).ok_or_else(|| {
+                        invalid("Password contains characters a US keyboard cannot type")
+                    })?);
+                }
+                if codes.is_empty() || codes.len() > SCAN_CODES_SIZE {
+                    return Err(Error::InvalidParams(format!(
+                        "Password must be 1 to {} characters",
+                        SCAN_CODES_SIZE
+                    ))
+                    .into());
+                }
+                let mut padded = Zeroizing::new([0u8; SCAN_CODES_SIZE]);
+                padded[..codes.len()].copy_from_slice(&codes);
+
+                let mut fields = ConfigFields::default();
+                fields.fixed = padded[..FIXED_SIZE].to_vec();
+                fields.ext_flags =
+                    EXTFLAG_SERIAL_API_VISIBLE | EXTFLAG_ALLOW_UPDATE | EXTFLAG_FAST_TRIG;
+                if *append_enter {
+                    fields.tkt_flags = TKTFLAG_APPEND_CR;
+                }
+                fields.cfg_flags = CFGFLAG_SHORT_TICKET;
+                fields
+                    .uid
+                    .copy_from_slice(&padded[FIXED_SIZE..FIXED_SIZE + UID_SIZE]);
+                fields.key.copy_from_slice(&padded[FIXED_SIZE + UID_SIZE..]);
+                Ok(fields)
+            }
+            OtpSlotConfig::Hotp {
+                secret,
+                digits,
+                counter,
+                append_enter,
+            } => {
+                if *digits != 6 && *digits != 8 {
+                    return Err(invalid("HOTP digits must be 6 or 8"));
+                }
+                if counter % 16 != 0 || *counter > 0xFFFF0 {
+                    return Err(invalid(
+                        "HOTP counter must be a multiple of 16 no greater than 1048560",
+                    ));
+                }
+                let mut fields = Self::with_hmac_key(secret)?;
+                fields.uid[4..].copy_from_slice(&((*counter >> 4) as u16).to_be_bytes());
+                fields.ext_flags |= EXTFLAG_FAST_TRIG;
+                fields.tkt_flags = TKTFLAG_OATH_HOTP;
+                if *append_enter {
+                    fields.tkt_flags |= TKTFLAG_APPEND_CR;
+                }
+                if *digits == 8 {
+                    fields.cfg_flags = CFGFLAG_OATH_HOTP8;
+                }
+                Ok(fields)
+            }
+            OtpSlotConfig::ChallengeResponse { key, require_touch } => {
+                let mut fields = Self::with_hmac_key(key)?;
+                fields.tkt_flags = TKTFLAG_CHAL_RESP;
+                fields.cfg_flags = CFGFLAG_CHAL_HMAC | CFGFLAG_HMAC_LT64;
+                if *require_touch {
+                    fields.cfg_flags |= CFGFLAG_CHAL_BTN_TRIG;
+                }
+                Ok(fields)
+            }
+        }
+    }
+
+    /// Serialize with the slot's new access code and the trailing CRC
+    fn to_bytes(&self, access_code: Option<&[u8; ACCESS_CODE_SIZE]>) -> Zeroizing<Vec<u8>> {
+        let mut config = Zeroizing::new(Vec::with_capacity(CONFIG_SIZE));
+        config.extend_from_slice(&self.fixed);
+        config.resize(FIXED_SIZE, 0);
+        config.extend_from_slice(&self.uid);
+        config.extend_from_slice(&self.key);
+        config.extend_from_slice(access_code.unwrap_or(&[0; ACCESS_CODE_SIZE]));
+        config.push(self.fixed.len() as u8);
+        config.push(self.ext_flags);
+        config.push(self.tkt_flags);
+        config.push(self.cfg_flags);
+        config.extend_from_slice(&[0, 0]);
+        let crc = !crc16(&config);
+        config.extend_from_slice(&crc.to_le_bytes());
+        config
+    }
+}
+
+/// Frame a command for the keyboard-HID interface
+fn format_frame(command: u8, payload: &[u8]) -> Zeroizing<Vec<u8>> {
+    let mut frame = Zeroizing::new(vec![0u8; FRAME_PAYLOAD_SIZE]);
+    frame[..payload.len()].copy_from_slice(payload);
+    let crc = crc16(&frame);
+    frame.push(command);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame.resize(FRAME_SIZE, 0);
+    frame
+}
+
+fn hid_read_status(device: &hidapi::HidDevice) -> Result<OtpStatus> {
+    let report = transport::get_feature_report(device)?;
+    parse_status(&report[1..=STATUS_SIZE])
+}
+
+/// Wait until the device has consumed the previous report
+fn hid_await_ready(device: &hidapi::HidDevice, timeout_ms: u64) -> Result<()> {
+    let start = Instant::now();
+    loop {
+        let report = transport::get_feature_report(device)?;
+        if report[FEATURE_REPORT_SIZE - 1] & SLOT_WRITE_FLAG == 0 {
+            return Ok(());
+        }
+        if start.elapsed() > Duration::from_millis(timeout_ms) {
+            return Err(
+                Error::Timeout(format!("OTP interface still busy after {}ms", timeout_ms)).into(),
+            );
+        }
+        std::thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+    }
+}
+
+/// Send a command over keyboard HID, returning the status before and after
+fn hid_write(
+    device: &hidapi::HidDevice,
+    command: u8,
+    payload: &[u8],
+) -> Result<(OtpStatus, OtpStatus)> {
+    let before = hid_read_status(device)?;
+    let frame = format_frame(command, payload);
+
+    let last = FRAME_SIZE / REPORT_DATA_SIZE - 1;
+    for (seq, chunk) in frame.chunks(REPORT_DATA_SIZE).enumerate() {
+        // All-zero reports in the middle of the frame are implied
+        if seq != 0 && seq != last && chunk.iter().all(|b| *b == 0) {
+            continue;
+        }
+        let mut report = Zeroizing::new([0u8; FEATURE_REPORT_SIZE]);
+        report[..REPORT_DATA_SIZE].copy_from_slice(chunk);
+        report[REPORT_DATA_SIZE] = SLOT_WRITE_FLAG | seq as u8;
+        hid_await_ready(device, READY_TIMEOUT_MS)?;
+        transport::send_feature_report(device, &report)?;
+    }
+
+    hid_await_ready(device, PROGRAM_TIMEOUT_MS)?;
+    Ok((before, hid_read_status(device)?))
+}
+
+fn ccid_select(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<OtpStatus> {
+    let response = apdu::transmit(
+        device_manager,
+        device_id,
+        &apdu::build_select_apdu(&OTP_AID),
+        Redact::Nothing,
+        "SELECT OTP",
+        activity_log,
+    )?;
+    if response.is_empty() {
+        return Err(Error::MalformedResponse("OTP application not found".to_string()).into());
+    }
+    parse_status(&response)
+}
+
+/// Send a command over CCID, returning the status before and after
+fn ccid_write(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    command: u8,
+    payload: &[u8],
+    command_name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<(OtpStatus, OtpStatus)> {
+    let before = ccid_select(device_manager, device_id, activity_log)?;
+
+    let mut apdu = Zeroizing::new(vec![0x00, INS_CONFIG, command, 0x00, payload.len() as u8]);
+    apdu.extend_from_slice(payload);
+    let response = apdu::transmit(
+        device_manager,
+        device_id,
+        &apdu,
+        Redact::Command,
+        command_name,
+        activity_log,
+    )?;
+    Ok((before, parse_status(&response)?))
+}
+
+/// Send a slot command followed by the current access code
+fn write_config(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    command: u8,
+    config: &[u8],
+    access_code: Option<&[u8; ACCESS_CODE_SIZE]>,
+    command_name: &str,
+    activity_log: &mut Vec<ApduLog>,
+) -> Result<OtpStatus> {
+    let mut payload = Zeroizing::new(Vec::with_capacity(CONFIG_SIZE + ACCESS_CODE_SIZE));
+    payload.extend_from_slice(config);
+    payload.extend_from_slice(access_code.unwrap_or(&[0; ACCESS_CODE_SIZE]));
+
+    let (before, after) = match device_manager.device_type(device_id)? {
+        DeviceType::Hid => device_manager
+            .with_hid_device(device_id, |device| hid_write(device, command, &payload))?,
+        DeviceType::Ccid => ccid_write(
+            device_manager,
+            device_id,
+            command,
+            &payload,
+            command_name,
+            activity_log,
+        )?,
+    };
+    check_applied(&before, &after)?;
+    Ok(after)
+}
+
+/// Read the OTP status over keyboard HID or CCID
+pub fn get_status(device_manager: &DeviceManager, device_id: &str) -> Result<OtpStatusResult> {
+    log::debug!("Reading OTP status...");
+
+    let mut activity_log = Vec::new();
+    let status = match device_manager.device_type(device_id)? {
+        DeviceType::Hid => device_manager.with_hid_device(device_id, hid_read_status)?,
+        DeviceType::Ccid => ccid_select(device_manager, device_id, &mut activity_log)?,
+    };
+
+    log::info!(
+        "OTP status: version {}, program sequence {}",
+        status.version,
+        status.program_sequence
+    );
+    Ok(OtpStatusResult {
+        status,
+        activity_log,
+    })
+}
+
+/// Program a slot
+pub fn program_slot(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    slot: OtpSlot,
+    config: &OtpSlotConfig,
+    access_codes: &OtpAccessCodes,
+) -> Result<OtpStatusResult> {
+    log::debug!("Programming OTP slot {}...", slot.number());
+
+    let fields = ConfigFields::from_config(config)?;
+    let mut activity_log = Vec::new();
+    let status = write_config(
+        device_manager,
+        device_id,
+        slot.config_command(),
+        &fields.to_bytes(access_codes.new.as_ref()),
+        access_codes.current.as_ref(),
+        &format!("CONFIGURE SLOT {}", slot.number()),
+        &mut activity_log,
+    )?;
+
+    log::info!("Programmed OTP slot {}", slot.number());
+    Ok(OtpStatusResult {
+        status,
+        activity_log,
+    })
+}
+
+/// Delete a slot's configuration
+pub fn delete_slot(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    slot: OtpSlot,
+    access_code: Option<&[u8; ACCESS_CODE_SIZE]>,
+) -> Result<OtpStatusResult> {
+    log::debug!("Deleting OTP slot {}...", slot.number());
+
+    let mut activity_log = Vec::new();
+    let status = write_config(
+        device_manager,
+        device_id,
+        slot.config_command(),
+        &[0; CONFIG_SIZE],
+        access_code,
+        &format!("DELETE SLOT {}", slot.number()),
+        &mut activity_log,
+    )?;
+
+    log::info!("Deleted OTP slot {}", slot.number());
+    Ok(OtpStatusResult {
+        status,
+        activity_log,
+    })
+}
+
+/// Swap the configurations of slots 1 and 2
+///
+/// A slot protected by an access code is only swapped when `access_code`
+/// matches. The swap command has no configuration of its own, so the code
+/// follows a blank one, where the key looks for it.
+pub fn swap_slots(
+    device_manager: &DeviceManager,
+    device_id: &str,
+    access_code: Option<&[u8; ACCESS_CODE_SIZE]>,
+) -> Result<OtpStatusResult> {
+    log::debug!("Swapping OTP slots...");
+
+    let config: &[u8] = match access_code {
+        Some(_) => &[0; CONFIG_SIZE],
+        None => &[],
+    };
+    let mut activity_log = Vec::new();
+    let status = write_config(
+        device_manager,
+        device_id,
+        CMD_SWAP,
+        config,
+        access_code,
+        "SWAP SLOTS",
+        &mut activity_log,
+    )?;
+
+    log::info!("Swapped OTP slots");
+    Ok(OtpStatusResult {
+        status,
+        activity_log,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16_residual() {
+        let config = ConfigFields::default().to_bytes(None);
+        assert_eq!(config.len(), CONFIG_SIZE);
+        // A block followed by its complemented CRC leaves the fixed residual
+        assert_eq!(crc16(&config), 0xF0B8);
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let status = parse_status(&[0x05, 0x04, 0x03, 0x07, 0x05, 0x00]).unwrap();
+        assert_eq!(status.version, "5.4.3");
+        assert_eq!(status.program_sequence, 7);
+        assert_eq!(
+            status.slots,
+            vec![
+                OtpSlotStatus {
+                    slot: 1,
+                    configured: true,
+                    touch_triggered: true
+                },
+                OtpSlotStatus {
+                    slot: 2,
+                    configured: false,
+                    touch_triggered: false
+                },
+            ]
+        );
+        assert!(parse_status(&[0x00; 6]).is_err());
+        assert!(parse_status(&[0x05, 0x04]).is_err());
+    }
+
+    #[test]
+    fn test_check_applied() {
+        let status = |seq: u8, touch: u8| parse_status(&[5, 4, 3, seq, touch, 0]).unwrap();
+        assert!(check_applied(&status(3, 1), &status(4, 3)).is_ok());
+        assert!(check_applied(&status(255, 1), &status(0, 1)).is_ok());
+        // Last slot deleted
+        assert!(check_applied(&status(3, 1), &status(0, 0)).is_ok());
+        // Wrong access code
+        assert!(check_applied(&status(3, 1), &status(3, 1)).is_err());
+    }
+
+    #[test]
+    fn test_static_password_config() {
+        let config = OtpSlotConfig::StaticPassword {
+            password: Zeroizing::new("aB1!".to_string()),
+            append_enter: true,
+        };
+        let bytes = ConfigFields::from_config(&config).unwrap().to_bytes(None);
+        assert_eq!(&bytes[..5], &[0x04, 0x85, 0x1E, 0x9E, 0x00]);
+        assert_eq!(bytes[44], FIXED_SIZE as u8);
+        assert_eq!(bytes[46], TKTFLAG_APPEND_CR);
+        assert_eq!(bytes[47], CFGFLAG_SHORT_TICKET);
+
+        let too_long = OtpSlotConfig::StaticPassword {
+            password: Zeroizing::new("x".repeat(SCAN_CODES_SIZE + 1)),
+            append_enter: false,
+        };
+        assert!(ConfigFields::from_config(&too_long).is_err());
+        let unmapped = OtpSlotConfig::StaticPassword {
+            password: Zeroizing::new("é".to_string()),
+            append_enter: false,
+        };
+        assert!(ConfigFields::from_config(&unmapped).is_err());
+    }
+
+    #[test]
+    fn test_hmac_configs() {
+        let secret: Vec<u8> = (1..=20).collect();
+        let hotp = OtpSlotConfig::Hotp {
+            secret: Zeroizing::new(secret.clone()),
+            digits: 8,
+            counter: 32,
+            append_enter: false,
+        };
+        let bytes = ConfigFields::from_config(&hotp)
+            .unwrap()
+            .to_bytes(Some(&[0xAA; ACCESS_CODE_SIZE]));
+        // uid holds key bytes 16..20 then counter / 16
+        assert_eq!(&bytes[16..22], &[17, 18, 19, 20, 0x00, 0x02]);
+        assert_eq!(&bytes[22..38], &secret[..16]);
+        assert_eq!(&bytes[38..44], &[0xAA; ACCESS_CODE_SIZE]);
+        assert_eq!(
+            (bytes[46], bytes[47]),
+            (TKTFLAG_OATH_HOTP, CFGFLAG_OATH_HOTP8)
+        );
+
+        let chal = OtpSlotConfig::ChallengeResponse {
+            key: Zeroizing::new(vec![0x01; 20]),
+            require_touch: true,
+        };
+        let bytes = ConfigFields::from_config(&chal).unwrap().to_bytes(None);
+        assert_eq!(bytes[46], TKTFLAG_CHAL_RESP);
+        assert_eq!(
+            bytes[47],
+            CFGFLAG_CHAL_HMAC | CFGFLAG_HMAC_LT64 | CFGFLAG_CHAL_BTN_TRIG
+        );
+
+        let bad_counter = OtpSlotConfig::Hotp {
+            secret: Zeroizing::new(secret),
+            digits: 6,
+            counter: 17,
+            append_enter: true,
+        };
+        assert!(ConfigFields::from_config(&bad_counter).is_err());
+        let long_key = OtpSlotConfig::ChallengeResponse {
+            key: Zeroizing::new(vec![0x01; 21]),
+            require_touch: false,
+        };
+        assert!(ConfigFields::from_config(&long_key).is_err());
+    }
+
+    #[test]
+    fn test_format_frame() {
+        let frame = format_frame(CMD_SWAP, &[0x01, 0x02]);
+        assert_eq!(frame.len(), FRAME_SIZE);
+        assert_eq!(frame[FRAME_PAYLOAD_SIZE], CMD_SWAP);
+        let crc = crc16(&frame[..FRAME_PAYLOAD_SIZE]);
+        assert_eq!(&frame[65..67], &crc.to_le_bytes());
+        assert_eq!(FRAME_SIZE % REPORT_DATA_SIZE, 0);
+        assert_eq!(
+            parse_access_code("0102030405AA").unwrap(),
+            [1, 2, 3, 4, 5, 0xAA]
+        );
+        assert!(parse_access_code("0102").is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use anyhow::Result;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::error::Error;
+
+// DigestInfo DER prefixes (RFC 8017 section 9.2, note 1)
+const DIGEST_INFO_SHA256: [u8; 19] = [
+    0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+const DIGEST_INFO_SHA384: [u8; 19] = [
+    0x30, 0x41, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05,
+    0x00, 0x04, 0x30,
+];
+const DIGEST_INFO_SHA512: [u8; 19] = [
+    0x30, 0x51, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03, 0x05,
+    0x00, 0x04, 0x40,
+];
+
+// PKCS#1 v1.5 needs at least 8 bytes of padding string
+const MIN_PADDING_LEN: usize = 8;
+
+// Final byte of an EMSA-PSS encoded message
+const PSS_TRAILER: u8 = 0xBC;
+
+/// Digest used for signing
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('-', "").as_str() {
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "sha384" => Some(HashAlgorithm::Sha384),
+            "sha512" => Some(HashAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            HashAlgorithm::Sha384 => Sha384::digest(data).to_vec(),
+            HashAlgorithm::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    pub fn output_len(self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha384 => 48,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+
+    fn digest_info_prefix(self) -> &'static [u8] {
+        match self {
+            HashAlgorithm::Sha256 => &DIGEST_INFO_SHA256,
+            HashAlgorithm::Sha384 => &DIGEST_INFO_SHA384,
+            HashAlgorithm::Sha512 => &DIGEST_INFO_SHA512,
+        }
+    }
+}
+
+/// RSA signature padding
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RsaPadding {
+    #[default]
+    Pkcs1,
+    Pss,
+}
+
+fn check_digest(hash: HashAlgorithm, digest: &[u8]) -> Result<()> {
+    if digest.len() != hash.output_len() {
+        return Err(Error::InvalidParams(format!(
+            "Digest must be {} bytes for {:?}, got {}",
+            hash.output_len(),
+            hash,
+            digest.len()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// EMSA-PKCS1-v1_5 encoding of a digest for a `key_len`-byte modulus
+pub fn pad_pkcs1v15_sign(hash: HashAlgorithm, digest: &[u8], key_len: usize) -> Result<Vec<u8>> {
+    check_digest(hash, digest)?;
+    let prefix = hash.digest_info_prefix();
+    let t_len = prefix.len() + digest.len();
+    if key_len < t_len + 3 + MIN_PADDING_LEN {
+        return Err(Error::InvalidParams("RSA key too small for digest".to_string()).into());
+    }
+
+    let mut block = vec![0x00, 0x01];
+    block.resize(key_len - t_len - 1, 0xFF);
+    block.push(0x00);
+    block.extend_from_slice(prefix);
+    block.extend_from_slice(digest);
+    Ok(block)
+}
+
+/// MGF1 mask generation with the given hash
+fn mgf1(hash: HashAlgorithm, seed: &[u8], len: usize) -> Vec<u8> {
+    let mut mask = Vec::with_capacity(len + hash.output_len());
+    let mut counter: u32 = 0;
+    while mask.len() < len {
+        let mut input = seed.to_vec();
+        input.extend_from_slice(&counter.to_be_bytes());
+        mask.extend_from_slice(&hash.digest(&input));
+        counter += 1;
+    }
+    mask.truncate(len);
+    mask
+}
+
+/// EMSA-PSS encoding (MGF1 with the same hash, salt length = digest length)
+pub fn pad_pss(hash: HashAlgorithm, digest: &[u8], key_bits: usize) -> Result<Vec<u8>> {
+    let mut salt = vec![0u8; hash.output_len()];
+    rand::thread_rng().fill_bytes(&mut salt);
+    encode_pss(hash, digest, key_bits, &salt)
+}
+
+fn encode_pss(hash: HashAlgorithm, digest: &[u8], key_bits: usize, salt: &[u8]) -> Result<Vec<u8>> {
+    check_digest(hash, digest)?;
+    let em_bits = key_bits - 1;
+    let em_len = em_bits.div_ceil(8);
+    let h_len = hash.output_len();
+    if em_len < h_len + salt.len() + 2 {
+        return Err(Error::InvalidParams("RSA key too small for PSS".to_string()).into());
+    }
+
+    // H = Hash(00*8 || mHash || salt)
+    let mut m_prime = vec![0u8; 8];
+    m_prime.extend_from_slice(digest);
+    m_prime.extend_from_slice(salt);
+    let h = hash.digest(&m_prime);
+
+    // DB = PS || 01 || salt, masked with MGF1(H)
+    let db_len = em_len - h_len - 1;
+    let mut db = vec![0u8; db_len - salt.len() - 1];
+    db.push(0x01);
+    db.extend_from_slice(salt);
+    for (byte, mask) in db.iter_mut().zip(mgf1(hash, &h, db_len)) {
+        *byte ^= mask;
+    }
+    db[0] &= 0xFF >> (8 * em_len - em_bits);
+
+    let mut em = db;
+    em.extend_from_slice(&h);
+    em.push(PSS_TRAILER);
+
+    // Left-pad to the modulus length when emBits is a multiple of 8
+    let key_len = key_bits.div_ceil(8);
+    let mut block = vec![0u8; key_len - em.len()];
+    block.extend_from_slice(&em);
+    Ok(block)
+}
+
+/// Strip EME-PKCS1-v1_5 (type 2) padding from a decrypted block
+pub fn unpad_pkcs1v15_encrypt(block: &[u8]) -> Result<Vec<u8>> {
+    let invalid = || Error::MalformedResponse("Invalid PKCS#1 v1.5 padding".to_string());
+    if block.len() < 2 + MIN_PADDING_LEN + 1 || block[0] != 0x00 || block[1] != 0x02 {
+        return Err(invalid().into());
+    }
+    let separator = block[2..]
+        .iter()
+        .position(|&b| b == 0x00)
+        .map(|i| i + 2)
+        .ok_or_else(invalid)?;
+    if separator < 2 + MIN_PADDING_LEN {
+        return Err(invalid().into());
+    }
+    Ok(block[separator + 1..].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::hazmat::rsa_decrypt_and_check;
+    use rsa::traits::PublicKeyParts;
+    use rsa::{BigUint, Pkcs1v15Sign, Pss, RsaPrivateKey, RsaPublicKey};
+
+    fn test_key() -> RsaPrivateKey {
+        RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap()
+    }
+
+    /// Apply the raw RSA private operation, as the card does
+    fn raw_sign(key: &RsaPrivateKey, block: &[u8]) -> Vec<u8> {
+        let m = BigUint::from_bytes_be(block);
+        let s = rsa_decrypt_and_check(key, None::<&mut rand::rngs::ThreadRng>, &m).unwrap();
+        let mut signature = s.to_bytes_be();
+        while signature.len() < key.size() {
+            signature.insert(0, 0);
+        }
+        signature
+    }
+
+    #[test]
+    fn test_pkcs1v15_signature_verifies() {
+        let key = test_key();
+        let public = RsaPublicKey::from(&key);
+        let digest = HashAlgorithm::Sha256.digest(b"challenge");
+
+        let block = pad_pkcs1v15_sign(HashAlgorithm::Sha256, &digest, key.size()).unwrap();
+        assert_eq!(block.len(), 128);
+        let signature = raw_sign(&key, &block);
+        public
+            .verify(Pkcs1v15Sign::new::<Sha256>(), &digest, &signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_pss_signature_verifies() {
+        let key = test_key();
+        let public = RsaPublicKey::from(&key);
+        let digest = HashAlgorithm::Sha384.digest(b"challenge");
+
+        let block = pad_pss(HashAlgorithm::Sha384, &digest, key.n().bits()).unwrap();
+        let signature = raw_sign(&key, &block);
+        public
+            .verify(Pss::new::<Sha384>(), &digest, &signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_digest_length_checked() {
+        assert!(pad_pkcs1v15_sign(HashAlgorithm::Sha256, &[0u8; 20], 256).is_err());
+        assert!(pad_pkcs1v15_sign(HashAlgorithm::Sha512, &[0u8; 64], 64).is_err());
+    }
+
+    #[test]
+    fn test_unpad_pkcs1v15_encrypt() {
+        let mut block = vec![0x00, 0x02];
+        block.extend_from_slice(&[0x5A; 10]);
+        block.push(0x00);
+        block.extend_from_slice(b"secret");
+        assert_eq!(unpad_pkcs1v15_encrypt(&block).unwrap(), b"secret");
+
+        block[1] = 0x01;
+        assert!(unpad_pkcs1v15_encrypt(&block).is_err());
+        assert!(unpad_pkcs1v15_encrypt(&[0x00, 0x02, 0x01, 0x00, 0x41]).is_err());
+    }
+}
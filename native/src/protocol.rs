@@ -2,6 +2,7 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 use crate::device::DeviceManager;
+use crate::otp;
 use crate::transport;
 
 /// Protocol support information for a device
@@ -11,6 +12,7 @@ pub struct ProtocolSupport {
     pub u2f: bool,
     pub piv: bool,
     pub openpgp: bool,
+    pub oath: bool,
     pub otp: bool,
     pub ndef: bool,
 }
@@ -247,36 +249,57 @@ fn detect_openpgp(device_manager: &DeviceManager, device_id: &str) -> bool {
     }
 }
 
-/// Detect OTP support
+/// Detect OATH support
 ///
-/// Tries vendor-specific OTP command via HID
-fn detect_otp(device_manager: &DeviceManager, device_id: &str) -> bool {
-    log::debug!("Detecting OTP support...");
+/// Tries to SELECT the YKOATH application via APDU
+fn detect_oath(device_manager: &DeviceManager, device_id: &str) -> bool {
+    log::debug!("Detecting OATH support...");
 
-    // Try Feitian vendor-specific OTP status command
-    // This is a simplified check - actual OTP detection may vary by device model
-    let mut packet = [0u8; 64];
-    packet[0..4].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]); // Broadcast CID
-    packet[4] = 0x83; // CMD_MSG
-    packet[5] = 0x00; // BCNTH
-    packet[6] = 0x05; // BCNTL = 5 bytes
-                      // Vendor command to check OTP status
-    packet[7] = 0x00; // CLA
-    packet[8] = 0x01; // INS (vendor-specific)
-    packet[9] = 0x00; // P1
-    packet[10] = 0x00; // P2
-    packet[11] = 0x00; // Le
+    // OATH application AID: A0 00 00 05 27 21 01
+    let oath_select = vec![
+        0x00, // CLA
+        0xA4, // INS (SELECT)
+        0x04, // P1 (Select by name)
+        0x00, // P2
+        0x07, // Lc (length of data)
+        0xA0, 0x00, 0x00, 0x05, 0x27, 0x21, 0x01, // OATH AID
+    ];
 
-    match device_manager.with_hid_device(device_id, |device| {
-        transport::send_hid(device, &packet[..64])?;
-        let response = transport::receive_hid(device, 1000)?;
+    match device_manager.with_ccid_card(device_id, |card| {
+        let response = transport::transmit_apdu(card, &oath_select)?;
         Ok(response)
     }) {
-        Ok(_response) => {
-            // If we get any response, assume OTP might be supported
-            // Real implementation would check response content
-            log::debug!("OTP detection: possible support (vendor command responded)");
-            false // Conservative: mark as unsupported unless we know for sure
+        Ok(response) => {
+            // Check for success status word (90 00)
+            if response.len() >= 2 {
+                let sw1 = response[response.len() - 2];
+                let sw2 = response[response.len() - 1];
+                if sw1 == 0x90 && sw2 == 0x00 {
+                    log::info!("OATH supported");
+                    return true;
+                }
+            }
+            log::debug!("OATH not supported (SELECT failed)");
+            false
+        }
+        Err(e) => {
+            log::debug!("OATH detection failed: {}", e);
+            false
+        }
+    }
+}
+
+/// Detect OTP support
+///
+/// Reads the OTP status: a feature report on the keyboard-HID interface, or
+/// the SELECT response of the OTP application over CCID
+fn detect_otp(device_manager: &DeviceManager, device_id: &str) -> bool {
+    log::debug!("Detecting OTP support...");
+
+    match otp::get_status(device_manager, device_id) {
+        Ok(result) => {
+            log::info!("OTP supported (version {})", result.status.version);
+            true
         }
         Err(e) => {
             log::debug!("OTP detection failed: {}", e);
@@ -329,6 +352,7 @@ fn detect_ndef(device_manager: &DeviceManager, device_id: &str) -> bool {
 /// Detect which protocols a device supports
 ///
 /// Probes the device with various protocol-specific commands to determine support.
+/// OTP keyboard interfaces are only probed for OTP.
 ///
 /// # Arguments
 /// * `device_manager` - Reference to the device manager
@@ -343,6 +367,19 @@ pub fn detect_protocols(
 ) -> Result<ProtocolSupport> {
     log::info!("Starting protocol detection for device: {}", device_id);
 
+    // The OTP keyboard interface answers nothing but OTP feature reports
+    if device_manager.is_otp_only(device_id) {
+        let support = ProtocolSupport {
+            otp: detect_otp(device_manager, device_id),
+            ..ProtocolSupport::default()
+        };
+        log::info!(
+            "Protocol detection complete: OTP-only interface, OTP={}",
+            support.otp
+        );
+        return Ok(support);
+    }
+
     // Note: Some detections may fail if device isn't the right type (HID vs CCID)
     // We catch errors and continue with other protocols
 
@@ -350,6 +387,7 @@ pub fn detect_protocols(
     let u2f = detect_u2f(device_manager, device_id);
     let piv = detect_piv(device_manager, device_id);
     let openpgp = detect_openpgp(device_manager, device_id);
+    let oath = detect_oath(device_manager, device_id);
     let otp = detect_otp(device_manager, device_id);
     let ndef = detect_ndef(device_manager, device_id);
 
@@ -358,16 +396,18 @@ pub fn detect_protocols(
         u2f,
         piv,
         openpgp,
+        oath,
         otp,
         ndef,
     };
 
     log::info!(
-        "Protocol detection complete: FIDO2={}, U2F={}, PIV={}, OpenPGP={}, OTP={}, NDEF={}",
+        "Protocol detection complete: FIDO2={}, U2F={}, PIV={}, OpenPGP={}, OATH={}, OTP={}, NDEF={}",
         support.fido2,
         support.u2f,
         support.piv,
         support.openpgp,
+        support.oath,
         support.otp,
         support.ndef
     );
@@ -386,6 +426,7 @@ mod tests {
         assert!(!support.u2f);
         assert!(!support.piv);
         assert!(!support.openpgp);
+        assert!(!support.oath);
         assert!(!support.otp);
         assert!(!support.ndef);
     }
@@ -397,6 +438,7 @@ mod tests {
             u2f: true,
             piv: false,
             openpgp: false,
+            oath: true,
             otp: true,
             ndef: false,
         };
@@ -405,6 +447,7 @@ mod tests {
         assert!(json.contains("\"fido2\":true"));
         assert!(json.contains("\"u2f\":true"));
         assert!(json.contains("\"piv\":false"));
+        assert!(json.contains("\"oath\":true"));
     }
 
     #[test]
@@ -416,6 +459,7 @@ mod tests {
         assert!(!support.u2f);
         assert!(!support.piv);
         assert!(!support.openpgp);
+        assert!(!support.oath);
         assert!(!support.otp);
         assert!(!support.ndef);
     }
@@ -0,0 +1,390 @@
+use anyhow::Result;
+
+use crate::error::Error;
+
+// Length octets: short form below 0x80, 0x80 = indefinite, 0x81..0x84 = long form
+const LENGTH_INDEFINITE: u8 = 0x80;
+const LENGTH_LONG_MAX_OCTETS: usize = 4;
+
+// Deepest nesting of indefinite-length objects before the input is rejected
+const MAX_INDEFINITE_DEPTH: usize = 16;
+
+// Tag octets
+const TAG_CONSTRUCTED: u8 = 0x20;
+const TAG_NUMBER_MASK: u8 = 0x1F;
+const TAG_MORE_OCTETS: u8 = 0x80;
+
+/// A single BER-TLV data object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tlv {
+    pub tag: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+impl Tlv {
+    pub fn new(tag: &[u8], value: &[u8]) -> Self {
+        Tlv {
+            tag: tag.to_vec(),
+            value: value.to_vec(),
+        }
+    }
+
+    /// Build an object whose value is the concatenated encoding of `children`
+    pub fn nested(tag: &[u8], children: &[Tlv]) -> Self {
+        Tlv {
+            tag: tag.to_vec(),
+            value: encode_all(children),
+        }
+    }
+
+    /// Whether the constructed bit (b6 of the first tag octet) is set
+    pub fn is_constructed(&self) -> bool {
+        self.tag.first().is_some_and(|t| t & TAG_CONSTRUCTED != 0)
+    }
+
+    /// Parse the value as a sequence of TLVs
+    ///
+    /// PIV wraps objects in primitive-tagged containers (53, 7E), so this does
+    /// not require the constructed bit.
+    pub fn children(&self) -> Result<Vec<Tlv>> {
+        parse(&self.value)
+    }
+
+    /// Encode with a definite length
+    pub fn encode(&self) -> Vec<u8> {
+        encode(&self.tag, &self.value)
+    }
+}
+
+/// Encode a length field in the shortest definite form
+pub fn encode_length(len: usize) -> Vec<u8> {
+    if len < 0x80 {
+        return vec![len as u8];
+    }
+    let bytes = (len as u64).to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    let mut out = vec![0x80 | (bytes.len() - skip) as u8];
+    out.extend_from_slice(&bytes[skip..]);
+    out
+}
+
+/// Encode a single TLV
+pub fn encode(tag: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut out = tag.to_vec();
+    out.extend_from_slice(&encode_length(value.len()));
+    out.extend_from_slice(value);
+    out
+}
+
+/// Encode a sequence of TLVs back to back
+pub fn encode_all(tlvs: &[Tlv]) -> Vec<u8> {
+    tlvs.iter().flat_map(Tlv::encode).collect()
+}
+
+fn malformed(message: &str, offset: usize) -> anyhow::Error {
+    Error::MalformedResponse(format!("Malformed TLV at offset {}: {}", offset, message)).into()
+}
+
+/// Read a tag starting at `offset`, returning it and the next offset
+fn read_tag(data: &[u8], offset: usize) -> Result<(Vec<u8>, usize)> {
+    let first = *data
+        .get(offset)
+        .ok_or_else(|| malformed("missing tag", offset))?;
+    let mut tag = vec![first];
+    let mut i = offset + 1;
+
+    if first & TAG_NUMBER_MASK == TAG_NUMBER_MASK {
+        loop {
+            let byte = *data
+                .get(i)
+                .ok_or_else(|| malformed("truncated multi-byte tag", offset))?;
+            tag.push(byte);
+            i += 1;
+            if byte & TAG_MORE_OCTETS == 0 {
+                break;
+            }
+        }
+    }
+    Ok((tag, i))
+}
+
+/// Read a length at `offset`; `None` means indefinite
+fn read_length(data: &[u8], offset: usize) -> Result<(Option<usize>, usize)> {
+    let first = *data
+        .get(offset)
+        .ok_or_else(|| malformed("missing length", offset))?;
+
+    if first < 0x80 {
+        return Ok((Some(first as usize), offset + 1));
+    }
+    if first == LENGTH_INDEFINITE {
+        return Ok((None, offset + 1));
+    }
+
+    let octets = (first & 0x7F) as usize;
+    if octets > LENGTH_LONG_MAX_OCTETS {
+        return Err(malformed("length field too long", offset));
+    }
+    let bytes = data
+        .get(offset + 1..offset + 1 + octets)
+        .ok_or_else(|| malformed("truncated length", offset))?;
+    let length = bytes.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+    Ok((Some(length), offset + 1 + octets))
+}
+
+/// Parse one TLV at `offset`, returning it and the offset after it
+///
+/// `depth` counts the enclosing indefinite-length objects.
+fn parse_one(data: &[u8], offset: usize, depth: usize) -> Result<(Tlv, usize)> {
+    let (tag, i) = read_tag(data, offset)?;
+    let (length, i) = read_length(data, i)?;
+
+    match length {
+        Some(length) => {
+            let end = i
+                .checked_add(length)
+                .filter(|&end| end <= data.len())
+                .ok_or_else(|| malformed("value exceeds available data", offset))?;
+            Ok((
+                Tlv {
+                    tag,
+                    value: data[i..end].to_vec(),
+                },
+                end,
+            ))
+        }
+        None => {
+            if tag[0] & TAG_CONSTRUCTED == 0 {
+                return Err(malformed("indefinite length on primitive tag", offset));
+            }
+            if depth >= MAX_INDEFINITE_DEPTH {
+                return Err(malformed("indefinite-length nesting too deep", offset));
+            }
+            // Walk nested objects until the end-of-contents marker (00 00)
+            let mut j = i;
+            loop {
+                match data.get(j..j + 2) {
+                    Some([0x00, 0x00]) => {
+                        return Ok((
+                            Tlv {
+                                tag,
+                                value: data[i..j].to_vec(),
+                            },
+                            j + 2,
+                        ));
+                    }
+                    Some(_) => j = parse_one(data, j, depth + 1)?.1,
+                    None => return Err(malformed("missing end-of-contents", offset)),
+                }
+            }
+        }
+    }
+}
+
+/// Parse a sequence of TLVs, failing on any malformed or truncated object
+pub fn parse(data: &[u8]) -> Result<Vec<Tlv>> {
+    let mut result = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let (tlv, next) = parse_one(data, offset, 0)?;
+        result.push(tlv);
+        offset = next;
+    }
+    Ok(result)
+}
+
+/// First object with `tag` in a parsed sequence
+pub fn find<'a>(tlvs: &'a [Tlv], tag: &[u8]) -> Option<&'a Tlv> {
+    tlvs.iter().find(|tlv| tlv.tag == tag)
+}
+
+/// Value at a nested tag path such as `[&[0x53], &[0x70]]`
+pub fn find_path(data: &[u8], path: &[&[u8]]) -> Result<Option<Vec<u8>>> {
+    let mut current = data.to_vec();
+    for tag in path {
+        let tlvs = parse(&current)?;
+        match find(&tlvs, tag) {
+            Some(tlv) => current = tlv.value.clone(),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+/// Value at a slash-separated hex tag path such as `"53/70"` or `"7F49/86"`
+pub fn query(data: &[u8], path: &str) -> Result<Option<Vec<u8>>> {
+    let tags = path
+        .split('/')
+        .map(|part| {
+            hex::decode(part.trim())
+                .ok()
+                .filter(|tag| !tag.is_empty())
+                .ok_or_else(|| Error::InvalidParams(format!("Invalid TLV path: {}", path)))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let tags: Vec<&[u8]> = tags.iter().map(Vec::as_slice).collect();
+    find_path(data, &tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse_simple() {
+        let result = parse(&[0x53, 0x03, 0x01, 0x02, 0x03, 0x90, 0x00]).unwrap();
+        assert_eq!(
+            result,
+            vec![Tlv::new(&[0x53], &[1, 2, 3]), Tlv::new(&[0x90], &[])]
+        );
+    }
+
+    #[test]
+    fn test_long_lengths() {
+        assert_eq!(encode(&[0x80], &[]), vec![0x80, 0x00]);
+        let long = encode(&[0x53], &[0u8; 0x100]);
+        assert_eq!(&long[..4], &[0x53, 0x82, 0x01, 0x00]);
+        assert_eq!(encode_length(0x01_0000), vec![0x83, 0x01, 0x00, 0x00]);
+        assert_eq!(
+            encode_length(0x0100_0000),
+            vec![0x84, 0x01, 0x00, 0x00, 0x00]
+        );
+
+        let mut data = vec![0x53, 0x84, 0x00, 0x00, 0x00, 0x02, 0xAA, 0xBB];
+        assert_eq!(parse(&data).unwrap()[0].value, vec![0xAA, 0xBB]);
+        data[1] = 0x85;
+        assert!(parse(&data).is_err());
+    }
+
+    #[test]
+    fn test_multi_byte_tags() {
+        let data = [0x5F, 0xC1, 0x05, 0x01, 0xAA, 0x7F, 0x49, 0x00];
+        let result = parse(&data).unwrap();
+        assert_eq!(result[0].tag, vec![0x5F, 0xC1, 0x05]);
+        assert_eq!(result[1].tag, vec![0x7F, 0x49]);
+        assert!(result[1].is_constructed());
+        assert!(!result[0].is_constructed());
+    }
+
+    #[test]
+    fn test_indefinite_length() {
+        // 7C 80 { 80 01 AA, 7F49 80 { 86 01 BB } 00 00 } 00 00
+        let data = [
+            0x7C, 0x80, 0x80, 0x01, 0xAA, 0x7F, 0x49, 0x80, 0x86, 0x01, 0xBB, 0x00, 0x00, 0x00,
+            0x00,
+        ];
+        let result = parse(&data).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(query(&data, "7C/80").unwrap(), Some(vec![0xAA]));
+        assert_eq!(query(&data, "7C/7F49/86").unwrap(), Some(vec![0xBB]));
+
+        // Primitive tags cannot use the indefinite form
+        assert!(parse(&[0x53, 0x80, 0x00, 0x00]).is_err());
+        // Missing end-of-contents
+        assert!(parse(&[0x7C, 0x80, 0x80, 0x01, 0xAA]).is_err());
+    }
+
+    #[test]
+    fn test_indefinite_length_depth_limit() {
+        let nested = |depth: usize| {
+            let mut data = [0x7C, 0x80].repeat(depth);
+            data.extend([0x00, 0x00].repeat(depth));
+            data
+        };
+        assert!(parse(&nested(MAX_INDEFINITE_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_INDEFINITE_DEPTH + 1)).is_err());
+
+        // Deep enough to overflow the stack without the limit
+        let headers = [0x7C, 0x80].repeat(1_000_000);
+        let err = parse(&headers).unwrap_err();
+        assert!(matches!(
+            crate::error::classify(&err),
+            Some(Error::MalformedResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        assert!(parse(&[0x53, 0x05, 0x01]).is_err());
+        assert!(parse(&[0x5F, 0xC1]).is_err());
+        assert!(parse(&[0x53]).is_err());
+        assert!(parse(&[0x53, 0x82, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_query() {
+        let certificate = Tlv::nested(
+            &[0x53],
+            &[Tlv::new(&[0x70], &[0x30, 0x00]), Tlv::new(&[0x71], &[0x00])],
+        );
+        let data = certificate.encode();
+        assert_eq!(query(&data, "53/70").unwrap(), Some(vec![0x30, 0x00]));
+        assert_eq!(query(&data, "53/71").unwrap(), Some(vec![0x00]));
+        assert_eq!(query(&data, "53/FE").unwrap(), None);
+        assert_eq!(query(&data, "7E/4F").unwrap(), None);
+        assert!(query(&data, "53/zz").is_err());
+        assert!(query(&data, "53//70").is_err());
+    }
+
+    fn tag_strategy() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            // Single-byte tags (low five bits not all set)
+            any::<u8>()
+                .prop_filter("single-byte tag", |t| t & TAG_NUMBER_MASK
+                    != TAG_NUMBER_MASK)
+                .prop_map(|t| vec![t]),
+            // Two- and three-byte tags such as 7F49 and 5FC105
+            (0x00u8..0x80).prop_map(|b| vec![0x7F, b]),
+            (0x80u8..=0xFF, 0x00u8..0x80).prop_map(|(b1, b2)| vec![0x5F, b1, b2]),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_encode_parse_round_trip(
+            objects in prop::collection::vec(
+                (tag_strategy(), prop::collection::vec(any::<u8>(), 0..600)),
+                0..6,
+            )
+        ) {
+            let tlvs: Vec<Tlv> = objects.iter().map(|(tag, value)| Tlv::new(tag, value)).collect();
+            prop_assert_eq!(parse(&encode_all(&tlvs)).unwrap(), tlvs);
+        }
+
+        #[test]
+        fn prop_nested_query(
+            outer in tag_strategy(),
+            inner in tag_strategy(),
+            value in prop::collection::vec(any::<u8>(), 0..300),
+        ) {
+            let data = Tlv::nested(&outer, &[Tlv::new(&inner, &value)]).encode();
+            let path = format!("{}/{}", hex::encode(&outer), hex::encode(&inner));
+            prop_assert_eq!(query(&data, &path).unwrap(), Some(value));
+        }
+
+        #[test]
+        fn prop_length_round_trip(len in 0usize..0x1_0000_0000) {
+            let encoded = encode_length(len);
+            let (decoded, next) = read_length(&encoded, 0).unwrap();
+            prop_assert_eq!(decoded, Some(len));
+            prop_assert_eq!(next, encoded.len());
+        }
+
+        #[test]
+        fn prop_parse_never_panics(data in prop::collection::vec(any::<u8>(), 0..64)) {
+            let _ = parse(&data);
+        }
+
+        #[test]
+        fn prop_truncation_is_an_error(
+            tag in tag_strategy(),
+            value in prop::collection::vec(any::<u8>(), 1..300),
+            cut in 1usize..300,
+        ) {
+            let encoded = encode(&tag, &value);
+            let cut = cut.min(encoded.len() - 1);
+            prop_assert!(parse(&encoded[..encoded.len() - cut]).is_err());
+        }
+    }
+}
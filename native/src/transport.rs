@@ -1,4 +1,6 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
+
+use crate::error::Error;
 
 /// Send raw HID packet (64 bytes standard)
 ///
@@ -11,10 +13,11 @@ use anyhow::{anyhow, Result};
 /// * `Err` - If the packet is too large or write fails
 pub fn send_hid(device: &hidapi::HidDevice, data: &[u8]) -> Result<usize> {
     if data.len() > 64 {
-        return Err(anyhow!(
+        return Err(Error::InvalidParams(format!(
             "HID packet too large: {} bytes (max 64)",
             data.len()
-        ));
+        ))
+        .into());
     }
 
     // Pad to 64 bytes
@@ -23,7 +26,7 @@ pub fn send_hid(device: &hidapi::HidDevice, data: &[u8]) -> Result<usize> {
 
     let bytes_written = device
         .write(&padded)
-        .map_err(|e| anyhow!("Failed to write HID packet: {}", e))?;
+        .map_err(|e| Error::from_hid(e, "Failed to write HID packet"))?;
 
     log::debug!("Sent HID packet: {} bytes", bytes_written);
     log::trace!("HID data: {:02X?}", &padded[..data.len()]);
@@ -44,10 +47,10 @@ pub fn receive_hid(device: &hidapi::HidDevice, timeout_ms: i32) -> Result<Vec<u8
     let mut buffer = vec![0u8; 64];
     let bytes_read = device
         .read_timeout(&mut buffer, timeout_ms)
-        .map_err(|e| anyhow!("Failed to read HID packet: {}", e))?;
+        .map_err(|e| Error::from_hid(e, "Failed to read HID packet"))?;
 
     if bytes_read == 0 {
-        return Err(anyhow!("HID read timeout after {}ms", timeout_ms));
+        return Err(Error::Timeout(format!("HID read timeout after {}ms", timeout_ms)).into());
     }
 
     buffer.truncate(bytes_read);
@@ -57,6 +60,63 @@ pub fn receive_hid(device: &hidapi::HidDevice, timeout_ms: i32) -> Result<Vec<u8
     Ok(buffer)
 }
 
+/// Size of an OTP keyboard-interface feature report
+pub const FEATURE_REPORT_SIZE: usize = 8;
+
+/// Read an 8-byte feature report (report ID 0)
+///
+/// # Arguments
+/// * `device` - Reference to an open HID device
+///
+/// # Returns
+/// * `Ok([u8; 8])` - Report data without the report ID
+/// * `Err` - If the interface has no feature report or the read fails
+pub fn get_feature_report(device: &hidapi::HidDevice) -> Result<[u8; FEATURE_REPORT_SIZE]> {
+    let mut buffer = [0u8; FEATURE_REPORT_SIZE + 1];
+    let bytes_read = device
+        .get_feature_report(&mut buffer)
+        .map_err(|e| Error::from_hid(e, "Failed to read HID feature report"))?;
+
+    // Some platforms strip the report ID, others include it
+    let data = match bytes_read {
+        n if n == FEATURE_REPORT_SIZE + 1 => &buffer[1..],
+        FEATURE_REPORT_SIZE => &buffer[..FEATURE_REPORT_SIZE],
+        n => {
+            return Err(Error::MalformedResponse(format!(
+                "Feature report is {} bytes (expected {})",
+                n, FEATURE_REPORT_SIZE
+            ))
+            .into())
+        }
+    };
+    log::trace!("HID feature report: {:02X?}", data);
+
+    let mut report = [0u8; FEATURE_REPORT_SIZE];
+    report.copy_from_slice(data);
+    Ok(report)
+}
+
+/// Write an 8-byte feature report (report ID 0)
+///
+/// # Arguments
+/// * `device` - Reference to an open HID device
+/// * `report` - Report data without the report ID
+///
+/// # Returns
+/// * `Ok(())` - If the report was written
+/// * `Err` - If the write fails
+pub fn send_feature_report(
+    device: &hidapi::HidDevice,
+    report: &[u8; FEATURE_REPORT_SIZE],
+) -> Result<()> {
+    let mut buffer = [0u8; FEATURE_REPORT_SIZE + 1];
+    buffer[1..].copy_from_slice(report);
+    device
+        .send_feature_report(&buffer)
+        .map_err(|e| Error::from_hid(e, "Failed to write HID feature report"))?;
+    Ok(())
+}
+
 /// Transmit APDU to smart card
 ///
 /// # Arguments
@@ -72,10 +132,11 @@ pub fn receive_hid(device: &hidapi::HidDevice, timeout_ms: i32) -> Result<Vec<u8
 /// implementing a timeout mechanism at a higher level to prevent indefinite blocking.
 pub fn transmit_apdu(card: &pcsc::Card, apdu: &[u8]) -> Result<Vec<u8>> {
     if apdu.len() < 4 {
-        return Err(anyhow!(
+        return Err(Error::InvalidParams(format!(
             "Invalid APDU: too short ({} bytes, minimum 4)",
             apdu.len()
-        ));
+        ))
+        .into());
     }
 
     log::debug!("Transmitting APDU: {} bytes", apdu.len());
@@ -87,17 +148,18 @@ pub fn transmit_apdu(card: &pcsc::Card, apdu: &[u8]) -> Result<Vec<u8>> {
     // Transmit the APDU with a timeout
     let response_data = card
         .transmit(apdu, &mut response)
-        .map_err(|e| anyhow!("Failed to transmit APDU: {}", e))?;
+        .map_err(|e| Error::from_pcsc(e, "Failed to transmit APDU"))?;
 
     // Copy the response data to a new vector
     let response = response_data.to_vec();
 
     // Check that we have at least status word (2 bytes)
     if response.len() < 2 {
-        return Err(anyhow!(
+        return Err(Error::MalformedResponse(format!(
             "APDU response too short: {} bytes (expected at least 2 for SW)",
             response.len()
-        ));
+        ))
+        .into());
     }
 
     // Extract status word (last 2 bytes)
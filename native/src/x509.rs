@@ -0,0 +1,922 @@
+use anyhow::{anyhow, bail, Result};
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::{Pkcs1v15Sign, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use x509_cert::attr::Attributes;
+use x509_cert::der::asn1::{Any, BitString, Ia5String, OctetString, UintRef, Utf8StringRef};
+use x509_cert::der::oid::ObjectIdentifier;
+use x509_cert::der::{Decode, Encode, Reader, SliceReader};
+use x509_cert::ext::pkix::name::{GeneralName, OtherName};
+use x509_cert::ext::pkix::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, KeyUsages, SubjectAltName,
+};
+use x509_cert::ext::Extension;
+use x509_cert::name::Name;
+use x509_cert::request::{CertReq, CertReqInfo, ExtensionReq};
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::{AlgorithmIdentifierOwned, SubjectPublicKeyInfoOwned};
+use x509_cert::time::{Time, Validity};
+use x509_cert::{Certificate, TbsCertificate, Version};
+
+use crate::error::Error;
+use crate::pkcs1::HashAlgorithm;
+
+// Public key algorithms
+const OID_RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const OID_EC_PUBLIC_KEY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+const OID_PRIME256V1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+const OID_SECP384R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.34");
+const OID_SECP521R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.35");
+const OID_ED25519: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+const OID_X25519: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.110");
+
+// Signature algorithms
+const OID_SHA256_WITH_RSA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.11");
+const OID_SHA384_WITH_RSA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.12");
+const OID_SHA512_WITH_RSA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.13");
+const OID_ECDSA_WITH_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+const OID_ECDSA_WITH_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+const OID_ECDSA_WITH_SHA512: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.4");
+
+// Extensions
+const OID_KEY_USAGE: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.15");
+const OID_EXTENDED_KEY_USAGE: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.37");
+const OID_SUBJECT_ALT_NAME: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.17");
+const OID_BASIC_CONSTRAINTS: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.19");
+
+// Microsoft User Principal Name (SAN otherName)
+const OID_UPN: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.311.20.2.3");
+
+// Extended key usages we can name
+const EXTENDED_KEY_USAGES: [(&str, &str); 7] = [
+    ("1.3.6.1.5.5.7.3.1", "serverAuth"),
+    ("1.3.6.1.5.5.7.3.2", "clientAuth"),
+    ("1.3.6.1.5.5.7.3.3", "codeSigning"),
+    ("1.3.6.1.5.5.7.3.4", "emailProtection"),
+    ("1.3.6.1.5.5.7.3.8", "timeStamping"),
+    ("1.3.6.1.5.5.7.3.9", "ocspSigning"),
+    ("1.3.6.1.4.1.311.20.2.2", "smartcardLogon"),
+];
+
+// DER NULL, the parameters of RSA signature algorithms
+const DER_NULL: [u8; 2] = [0x05, 0x00];
+
+/// Certificates expiring within this many days are flagged
+const EXPIRING_SOON_DAYS: i64 = 30;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Subject alternative names relevant to smart-card logon
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct SubjectAltNames {
+    pub upn: Vec<String>,
+    pub email: Vec<String>,
+    pub dns: Vec<String>,
+}
+
+/// Fields extracted from a DER X.509 certificate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateDetails {
+    pub subject: String,
+    pub issuer: String,
+    pub serial_number: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub key_algorithm: String,
+    pub key_size: Option<u32>,
+    pub fingerprint_sha256: String,
+    pub key_usage: Vec<String>,
+    pub extended_key_usage: Vec<String>,
+    pub subject_alt_names: SubjectAltNames,
+    pub expired: bool,
+    pub expiring_soon: bool,
+    pub days_until_expiry: i64,
+}
+
+/// Signature algorithm used when the card signs a request or certificate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureScheme {
+    RsaSha256,
+    EcdsaSha256,
+    EcdsaSha384,
+    Ed25519,
+}
+
+impl SignatureScheme {
+    /// Digest the card signs, or `None` when the scheme signs the message itself
+    pub fn hash(self) -> Option<HashAlgorithm> {
+        match self {
+            SignatureScheme::RsaSha256 | SignatureScheme::EcdsaSha256 => {
+                Some(HashAlgorithm::Sha256)
+            }
+            SignatureScheme::EcdsaSha384 => Some(HashAlgorithm::Sha384),
+            SignatureScheme::Ed25519 => None,
+        }
+    }
+
+    fn algorithm_identifier(self) -> Result<AlgorithmIdentifierOwned> {
+        let (oid, parameters) = match self {
+            SignatureScheme::RsaSha256 => (OID_SHA256_WITH_RSA, Some(Any::from_der(&DER_NULL)?)),
+            SignatureScheme::EcdsaSha256 => (OID_ECDSA_WITH_SHA256, None),
+            SignatureScheme::EcdsaSha384 => (OID_ECDSA_WITH_SHA384, None),
+            SignatureScheme::Ed25519 => (OID_ED25519, None),
+        };
+        Ok(AlgorithmIdentifierOwned { oid, parameters })
+    }
+}
+
+/// Fields of a self-signed certificate issued by the card
+#[derive(Debug, Clone)]
+pub struct CertificateProfile {
+    /// Subject (and issuer) DN in RFC 4514 form
+    pub subject: String,
+    pub alt_names: SubjectAltNames,
+    pub serial_number: Vec<u8>,
+    /// Validity bounds in Unix seconds
+    pub not_before: i64,
+    pub not_after: i64,
+    /// Key usage names as reported by `parse_certificate` ("digitalSignature", ...)
+    pub key_usage: Vec<String>,
+    /// Extended key usage names ("clientAuth", "smartcardLogon") or dotted OIDs
+    pub extended_key_usage: Vec<String>,
+}
+
+/// Parse a DER certificate, evaluating expiry against the current time
+pub fn parse_certificate(der: &[u8]) -> Result<CertificateDetails> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| anyhow!("System clock before Unix epoch: {}", e))?;
+    parse_certificate_at(der, now.as_secs() as i64)
+}
+
+/// Parse a DER certificate, evaluating expiry against `now` (Unix seconds)
+fn parse_certificate_at(der: &[u8], now: i64) -> Result<CertificateDetails> {
+    let certificate = decode_certificate(der)?;
+    let tbs = &certificate.tbs_certificate;
+
+    let (key_algorithm, key_size) = describe_public_key(tbs)?;
+
+    let mut key_usage = Vec::new();
+    let mut extended_key_usage = Vec::new();
+    let mut subject_alt_names = SubjectAltNames::default();
+
+    for extension in tbs.extensions.iter().flatten() {
+        let value = extension.extn_value.as_bytes();
+        if extension.extn_id == OID_KEY_USAGE {
+            let usage = KeyUsage::from_der(value)
+                .map_err(|e| Error::MalformedResponse(format!("Invalid key usage: {}", e)))?;
+            key_usage = usage
+                .0
+                .into_iter()
+                .map(key_usage_name)
+                .map(String::from)
+                .collect();
+        } else if extension.extn_id == OID_EXTENDED_KEY_USAGE {
+            let usage = ExtendedKeyUsage::from_der(value).map_err(|e| {
+                Error::MalformedResponse(format!("Invalid extended key usage: {}", e))
+            })?;
+            extended_key_usage = usage.0.iter().map(extended_key_usage_name).collect();
+        } else if extension.extn_id == OID_SUBJECT_ALT_NAME {
+            let names = SubjectAltName::from_der(value).map_err(|e| {
+                Error::MalformedResponse(format!("Invalid subject alternative name: {}", e))
+            })?;
+            subject_alt_names = collect_subject_alt_names(&names);
+        }
+    }
+
+    let not_after_secs = tbs.validity.not_after.to_unix_duration().as_secs() as i64;
+    let seconds_left = not_after_secs - now;
+    let days_until_expiry = seconds_left.div_euclid(SECONDS_PER_DAY);
+    let expired = seconds_left < 0;
+
+    Ok(CertificateDetails {
+        subject: tbs.subject.to_string(),
+        issuer: tbs.issuer.to_string(),
+        serial_number: hex::encode(tbs.serial_number.as_bytes()),
+        not_before: tbs.validity.not_before.to_string(),
+        not_after: tbs.validity.not_after.to_string(),
+        key_algorithm,
+        key_size,
+        fingerprint_sha256: hex::encode(Sha256::digest(der)),
+        key_usage,
+        extended_key_usage,
+        subject_alt_names,
+        expired,
+        expiring_soon: !expired && days_until_expiry < EXPIRING_SOON_DAYS,
+        days_until_expiry,
+    })
+}
+
+fn decode_certificate(der: &[u8]) -> Result<Certificate> {
+    Certificate::from_der(der)
+        .map_err(|e| Error::MalformedResponse(format!("Invalid X.509 certificate: {}", e)).into())
+}
+
+/// Raw extension values (contents of extnValue) keyed by dotted OID
+pub fn extensions(der: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let certificate = decode_certificate(der)?;
+    Ok(certificate
+        .tbs_certificate
+        .extensions
+        .iter()
+        .flatten()
+        .map(|extension| {
+            (
+                extension.extn_id.to_string(),
+                extension.extn_value.as_bytes().to_vec(),
+            )
+        })
+        .collect())
+}
+
+/// DER SubjectPublicKeyInfo of a certificate
+pub fn subject_public_key_info(der: &[u8]) -> Result<Vec<u8>> {
+    let certificate = decode_certificate(der)?;
+    certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|e| anyhow!("Failed to encode public key: {}", e))
+}
+
+/// Check that `der` names `issuer_der` as its issuer and carries its signature
+pub fn verify_issued_by(der: &[u8], issuer_der: &[u8]) -> Result<()> {
+    let certificate = decode_certificate(der)?;
+    let issuer = decode_certificate(issuer_der)?;
+    if certificate.tbs_certificate.issuer != issuer.tbs_certificate.subject {
+        bail!(
+            "Certificate issuer \"{}\" does not match \"{}\"",
+            certificate.tbs_certificate.issuer,
+            issuer.tbs_certificate.subject
+        );
+    }
+
+    let tbs = certificate
+        .tbs_certificate
+        .to_der()
+        .map_err(|e| anyhow!("Failed to encode TBSCertificate: {}", e))?;
+    verify_signature(
+        &issuer.tbs_certificate.subject_public_key_info,
+        &certificate.signature_algorithm.oid,
+        &tbs,
+        certificate.signature.raw_bytes(),
+    )
+}
+
+/// Parse a distinguished name in RFC 4514 form ("CN=Alice,O=Example")
+fn parse_name(name: &str) -> Result<Name> {
+    if name.trim().is_empty() {
+        return Err(Error::InvalidParams("Subject name is empty".to_string()).into());
+    }
+    Name::from_str(name)
+        .map_err(|e| Error::InvalidParams(format!("Invalid subject name {}: {}", name, e)).into())
+}
+
+/// Subject alternative name extension, or `None` when there are no names
+fn subject_alt_name_extension(names: &SubjectAltNames) -> Result<Option<Extension>> {
+    let invalid = |e: x509_cert::der::Error| {
+        Error::InvalidParams(format!("Invalid subject alternative name: {}", e))
+    };
+    let mut general_names = Vec::new();
+    for upn in &names.upn {
+        let value = Utf8StringRef::new(upn).map_err(invalid)?;
+        general_names.push(GeneralName::OtherName(OtherName {
+            type_id: OID_UPN,
+            value: Any::encode_from(&value).map_err(invalid)?,
+        }));
+    }
+    for email in &names.email {
+        let email = Ia5String::new(email).map_err(invalid)?;
+        general_names.push(GeneralName::Rfc822Name(email));
+    }
+    for dns in &names.dns {
+        let dns = Ia5String::new(dns).map_err(invalid)?;
+        general_names.push(GeneralName::DnsName(dns));
+    }
+    if general_names.is_empty() {
+        return Ok(None);
+    }
+
+    let value = SubjectAltName(general_names).to_der()?;
+    Ok(Some(Extension {
+        extn_id: OID_SUBJECT_ALT_NAME,
+        critical: false,
+        extn_value: OctetString::new(value)?,
+    }))
+}
+
+/// Encode a PKCS#10 CertificationRequestInfo for the data the card signs
+pub fn certification_request_info(
+    subject: &str,
+    public_key: &[u8],
+    alt_names: &SubjectAltNames,
+) -> Result<Vec<u8>> {
+    let public_key = SubjectPublicKeyInfoOwned::from_der(public_key)
+        .map_err(|e| Error::InvalidParams(format!("Invalid public key: {}", e)))?;
+
+    let mut attributes = Attributes::new();
+    if let Some(extension) = subject_alt_name_extension(alt_names)? {
+        attributes.insert(ExtensionReq(vec![extension]).try_into()?)?;
+    }
+
+    let info = CertReqInfo {
+        version: Default::default(),
+        subject: parse_name(subject)?,
+        public_key,
+        attributes,
+    };
+    Ok(info.to_der()?)
+}
+
+/// Assemble a PKCS#10 request from its encoded info and the card's signature
+pub fn certification_request(
+    info: &[u8],
+    scheme: SignatureScheme,
+    signature: &[u8],
+) -> Result<Vec<u8>> {
+    let request = CertReq {
+        info: CertReqInfo::from_der(info)?,
+        algorithm: scheme.algorithm_identifier()?,
+        signature: BitString::from_bytes(signature)?,
+    };
+    Ok(request.to_der()?)
+}
+
+/// Check a PKCS#10 request is signed by the key it contains
+pub fn verify_certification_request(request: &[u8]) -> Result<()> {
+    let request = CertReq::from_der(request)
+        .map_err(|e| Error::MalformedResponse(format!("Invalid certificate request: {}", e)))?;
+    let info = request.info.to_der()?;
+    verify_signature(
+        &request.info.public_key,
+        &request.algorithm.oid,
+        &info,
+        request.signature.raw_bytes(),
+    )
+}
+
+fn unix_time(seconds: i64) -> Result<Time> {
+    let seconds = u64::try_from(seconds)
+        .map_err(|_| Error::InvalidParams("Validity before 1970".to_string()))?;
+    Time::try_from(UNIX_EPOCH + Duration::from_secs(seconds))
+        .map_err(|e| Error::InvalidParams(format!("Invalid validity time: {}", e)).into())
+}
+
+fn key_usage_from_name(name: &str) -> Result<KeyUsages> {
+    [
+        KeyUsages::DigitalSignature,
+        KeyUsages::NonRepudiation,
+        KeyUsages::KeyEncipherment,
+        KeyUsages::DataEncipherment,
+        KeyUsages::KeyAgreement,
+        KeyUsages::KeyCertSign,
+        KeyUsages::CRLSign,
+        KeyUsages::EncipherOnly,
+        KeyUsages::DecipherOnly,
+    ]
+    .into_iter()
+    .find(|usage| key_usage_name(*usage).eq_ignore_ascii_case(name))
+    .ok_or_else(|| Error::InvalidParams(format!("Unknown key usage: {}", name)).into())
+}
+
+fn extended_key_usage_from_name(name: &str) -> Result<ObjectIdentifier> {
+    let dotted = EXTENDED_KEY_USAGES
+        .iter()
+        .find(|(_, known)| known.eq_ignore_ascii_case(name))
+        .map(|(oid, _)| *oid)
+        .unwrap_or(name);
+    ObjectIdentifier::new(dotted)
+        .map_err(|_| Error::InvalidParams(format!("Unknown extended key usage: {}", name)).into())
+}
+
+fn extension<T: Encode>(extn_id: ObjectIdentifier, critical: bool, value: &T) -> Result<Extension> {
+    Ok(Extension {
+        extn_id,
+        critical,
+        extn_value: OctetString::new(value.to_der()?)?,
+    })
+}
+
+/// Encode the TBSCertificate of a self-signed end-entity certificate
+pub fn self_signed_tbs_certificate(
+    profile: &CertificateProfile,
+    public_key: &[u8],
+    scheme: SignatureScheme,
+) -> Result<Vec<u8>> {
+    if profile.not_after <= profile.not_before {
+        return Err(
+            Error::InvalidParams("Certificate must expire after it starts".to_string()).into(),
+        );
+    }
+    let public_key = SubjectPublicKeyInfoOwned::from_der(public_key)
+        .map_err(|e| Error::InvalidParams(format!("Invalid public key: {}", e)))?;
+    let name = parse_name(&profile.subject)?;
+
+    let mut extensions = vec![extension(
+        OID_BASIC_CONSTRAINTS,
+        true,
+        &BasicConstraints {
+            ca: false,
+            path_len_constraint: None,
+        },
+    )?];
+    if !profile.key_usage.is_empty() {
+        let mut usage = KeyUsage(Default::default());
+        for name in &profile.key_usage {
+            usage.0 |= key_usage_from_name(name)?;
+        }
+        extensions.push(extension(OID_KEY_USAGE, true, &usage)?);
+    }
+    if !profile.extended_key_usage.is_empty() {
+        let usage = profile
+            .extended_key_usage
+            .iter()
+            .map(|name| extended_key_usage_from_name(name))
+            .collect::<Result<Vec<_>>>()?;
+        extensions.push(extension(
+            OID_EXTENDED_KEY_USAGE,
+            false,
+            &ExtendedKeyUsage(usage),
+        )?);
+    }
+    if let Some(alt_names) = subject_alt_name_extension(&profile.alt_names)? {
+        extensions.push(alt_names);
+    }
+
+    let tbs = TbsCertificate {
+        version: Version::V3,
+        serial_number: SerialNumber::new(&profile.serial_number)
+            .map_err(|e| Error::InvalidParams(format!("Invalid serial number: {}", e)))?,
+        signature: scheme.algorithm_identifier()?,
+        issuer: name.clone(),
+        validity: Validity {
+            not_before: unix_time(profile.not_before)?,
+            not_after: unix_time(profile.not_after)?,
+        },
+        subject: name,
+        subject_public_key_info: public_key,
+        issuer_unique_id: None,
+        subject_unique_id: None,
+        extensions: Some(extensions),
+    };
+    Ok(tbs.to_der()?)
+}
+
+/// Assemble a certificate from its encoded TBSCertificate and the card's signature
+pub fn certificate(tbs: &[u8], scheme: SignatureScheme, signature: &[u8]) -> Result<Vec<u8>> {
+    let certificate = Certificate {
+        tbs_certificate: TbsCertificate::from_der(tbs)?,
+        signature_algorithm: scheme.algorithm_identifier()?,
+        signature: BitString::from_bytes(signature)?,
+    };
+    Ok(certificate.to_der()?)
+}
+
+/// Verify an RSA PKCS#1 v1.5 or ECDSA (P-256/P-384) signature over `message`
+fn verify_signature(
+    spki: &SubjectPublicKeyInfoOwned,
+    algorithm: &ObjectIdentifier,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    let (hash, rsa) = match *algorithm {
+        OID_SHA256_WITH_RSA => (HashAlgorithm::Sha256, true),
+        OID_SHA384_WITH_RSA => (HashAlgorithm::Sha384, true),
+        OID_SHA512_WITH_RSA => (HashAlgorithm::Sha512, true),
+        OID_ECDSA_WITH_SHA256 => (HashAlgorithm::Sha256, false),
+        OID_ECDSA_WITH_SHA384 => (HashAlgorithm::Sha384, false),
+        OID_ECDSA_WITH_SHA512 => (HashAlgorithm::Sha512, false),
+        _ => bail!("Unsupported signature algorithm {}", algorithm),
+    };
+    let digest = hash.digest(message);
+    let key = spki.subject_public_key.raw_bytes();
+    let invalid = || anyhow!("Certificate signature is invalid");
+
+    if rsa {
+        if spki.algorithm.oid != OID_RSA_ENCRYPTION {
+            bail!("Issuer key is not an RSA key");
+        }
+        let public = RsaPublicKey::from_pkcs1_der(key)
+            .map_err(|e| Error::MalformedResponse(format!("Invalid RSA public key: {}", e)))?;
+        let scheme = match hash {
+            HashAlgorithm::Sha256 => Pkcs1v15Sign::new::<Sha256>(),
+            HashAlgorithm::Sha384 => Pkcs1v15Sign::new::<Sha384>(),
+            HashAlgorithm::Sha512 => Pkcs1v15Sign::new::<Sha512>(),
+        };
+        return public
+            .verify(scheme, &digest, signature)
+            .map_err(|_| invalid());
+    }
+
+    let malformed = |e: p256::ecdsa::Error| {
+        Error::MalformedResponse(format!("Invalid ECDSA key or signature: {}", e))
+    };
+    match ec_curve(spki) {
+        Some(OID_PRIME256V1) => {
+            let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(key).map_err(malformed)?;
+            let signature = p256::ecdsa::Signature::from_der(signature).map_err(malformed)?;
+            key.verify_prehash(&digest, &signature)
+                .map_err(|_| invalid())
+        }
+        Some(OID_SECP384R1) => {
+            let key = p384::ecdsa::VerifyingKey::from_sec1_bytes(key).map_err(malformed)?;
+            let signature = p384::ecdsa::Signature::from_der(signature).map_err(malformed)?;
+            key.verify_prehash(&digest, &signature)
+                .map_err(|_| invalid())
+        }
+        _ => bail!("Unsupported issuer key for ECDSA"),
+    }
+}
+
+/// Named curve of an EC public key
+fn ec_curve(spki: &SubjectPublicKeyInfoOwned) -> Option<ObjectIdentifier> {
+    if spki.algorithm.oid != OID_EC_PUBLIC_KEY {
+        return None;
+    }
+    spki.algorithm
+        .parameters
+        .as_ref()
+        .and_then(|params| params.decode_as::<ObjectIdentifier>().ok())
+}
+
+/// Name the subject public key algorithm and its size in bits
+fn describe_public_key(tbs: &x509_cert::TbsCertificate) -> Result<(String, Option<u32>)> {
+    let spki = &tbs.subject_public_key_info;
+    let algorithm = spki.algorithm.oid;
+
+    if algorithm == OID_RSA_ENCRYPTION {
+        let key = spki.subject_public_key.raw_bytes();
+        return Ok(("RSA".to_string(), Some(rsa_modulus_bits(key)?)));
+    }
+    if algorithm == OID_EC_PUBLIC_KEY {
+        return Ok(match ec_curve(spki) {
+            Some(oid) if oid == OID_PRIME256V1 => ("ECC P-256".to_string(), Some(256)),
+            Some(oid) if oid == OID_SECP384R1 => ("ECC P-384".to_string(), Some(384)),
+            Some(oid) if oid == OID_SECP521R1 => ("ECC P-521".to_string(), Some(521)),
+            Some(oid) => (format!("ECC {}", oid), None),
+            None => ("ECC".to_string(), None),
+        });
+    }
+    if algorithm == OID_ED25519 {
+        return Ok(("Ed25519".to_string(), Some(256)));
+    }
+    if algorithm == OID_X25519 {
+        return Ok(("X25519".to_string(), Some(256)));
+    }
+    Ok((algorithm.to_string(), None))
+}
+
+/// Bit length of the modulus in an RSAPublicKey
+fn rsa_modulus_bits(key: &[u8]) -> Result<u32> {
+    let modulus = SliceReader::new(key)
+        .and_then(|mut reader| {
+            reader.sequence(|seq| {
+                let modulus = seq.decode::<UintRef<'_>>()?;
+                let _exponent = seq.decode::<UintRef<'_>>()?;
+                Ok(modulus)
+            })
+        })
+        .map_err(|e| Error::MalformedResponse(format!("Invalid RSA public key: {}", e)))?;
+    let bytes = modulus.as_bytes();
+    let leading = bytes.first().map(|b| b.leading_zeros()).unwrap_or(0);
+    Ok(bytes.len() as u32 * 8 - leading)
+}
+
+fn key_usage_name(usage: KeyUsages) -> &'static str {
+    match usage {
+        KeyUsages::DigitalSignature => "digitalSignature",
+        KeyUsages::NonRepudiation => "nonRepudiation",
+        KeyUsages::KeyEncipherment => "keyEncipherment",
+        KeyUsages::DataEncipherment => "dataEncipherment",
+        KeyUsages::KeyAgreement => "keyAgreement",
+        KeyUsages::KeyCertSign => "keyCertSign",
+        KeyUsages::CRLSign => "cRLSign",
+        KeyUsages::EncipherOnly => "encipherOnly",
+        KeyUsages::DecipherOnly => "decipherOnly",
+    }
+}
+
+fn extended_key_usage_name(oid: &ObjectIdentifier) -> String {
+    let dotted = oid.to_string();
+    EXTENDED_KEY_USAGES
+        .iter()
+        .find(|(known, _)| *known == dotted)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or(dotted)
+}
+
+fn collect_subject_alt_names(names: &SubjectAltName) -> SubjectAltNames {
+    let mut result = SubjectAltNames::default();
+    for name in &names.0 {
+        match name {
+            GeneralName::Rfc822Name(email) => result.email.push(ia5_to_string(email)),
+            GeneralName::DnsName(dns) => result.dns.push(ia5_to_string(dns)),
+            GeneralName::OtherName(other) if other.type_id == OID_UPN => {
+                match other.value.decode_as::<Utf8StringRef<'_>>() {
+                    Ok(upn) => result.upn.push(upn.as_str().to_string()),
+                    Err(e) => log::warn!("Ignoring malformed UPN: {}", e),
+                }
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+fn ia5_to_string(value: &Ia5String) -> String {
+    value.as_str().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Self-signed P-256 certificate with smart-card logon extensions
+    const TEST_CERT_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIB5jCCAYugAwIBAgIDGis8MAoGCCqGSM49BAMCMC8xFjAUBgNVBAMMDUFsaWNl
+IEV4YW1wbGUxFTATBgNVBAoMDEV4YW1wbGUgQ29ycDAeFw0yNjEwMTgxNDEzMzda
+Fw0zNjEwMTUxNDEzMzdaMC8xFjAUBgNVBAMMDUFsaWNlIEV4YW1wbGUxFTATBgNV
+BAoMDEV4YW1wbGUgQ29ycDBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABBrDeGZ3
+ZcAFGXNVNPCtwLq4loDITJemgIxjtMlZMyaZM8/S1TCEQMRgxd+nSbcU3wiYGfPV
+VCxUpcT99yjwa6yjgZUwgZIwDgYDVR0PAQH/BAQDAgOIMB8GA1UdJQQYMBYGCCsG
+AQUFBwMCBgorBgEEAYI3FAICMEAGA1UdEQQ5MDeBEWFsaWNlQGV4YW1wbGUuY29t
+oCIGCisGAQQBgjcUAgOgFAwSYWxpY2VAY29ycC5leGFtcGxlMB0GA1UdDgQWBBQW
+X0bU0JtpYCD0342lfaSfNQlYuDAKBggqhkjOPQQDAgNJADBGAiEA6+quBmMavICA
+mvSe5ieruErspErklAcm2XTOJvUAD1MCIQCHw7BdB/qYZZK6DXb+6ec+pZXIxMFf
+F4kmJCQdGDFlTg==
+-----END CERTIFICATE-----";
+
+    // RSA-2048 attestation CA and a P-256 leaf it issued, carrying firmware
+    // 5.4.3, serial 12345678, PIN once / touch always and form factor 0x83
+    const TEST_ATTESTATION_CA_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIDEjCCAfqgAwIBAgIBATANBgkqhkiG9w0BAQsFADAiMSAwHgYDVQQDDBdFeGFt
+cGxlIFBJViBBdHRlc3RhdGlvbjAeFw0yNjEwMTgxNDQxMTJaFw00NjEwMTMxNDQx
+MTJaMCIxIDAeBgNVBAMMF0V4YW1wbGUgUElWIEF0dGVzdGF0aW9uMIIBIjANBgkq
+hkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAzcW0BfFNkMMl0o0klth5cbr/BVOPU8h6
+LINBgPbrf86yxUdaOKKpHkSKu4e7mEdofBnnqtkPXPZioT1KCkoQ8754nDiy4en/
+52RgX3nvA72WL4Qp4f6AFEcq0NJXJtqeqIymx1K93TilYMdZxcWhMl5BmzIlrY+w
+WSnYJA4980Dxt0ZTRm7bc6KGTMmK/UOEbIQHloPeJ91R1gvdkp/xIxV2l2wp8tWR
+6xcduFnm/YG5ktobhbLyhlxpArAPszZjNdqY0Ky+9i2Ww9Wp3WlmC37bB9nz5//i
+KNKlg4hVq4heegeJXFA1kYCMlIS+s6xgKEr0az0YbpF5vDIev+QBTwIDAQABo1Mw
+UTAdBgNVHQ4EFgQUmy/uDoN9OT/yc4zELiyTWTRU9kIwHwYDVR0jBBgwFoAUmy/u
+DoN9OT/yc4zELiyTWTRU9kIwDwYDVR0TAQH/BAUwAwEB/zANBgkqhkiG9w0BAQsF
+AAOCAQEAqCgdxTeooycW2A6Mey0YB4L4qYYigBAa089hhCQ3offnHilbyGgAayFX
+SMWG0OTYrTHucyHqys/sXBJVa+SzQiizHVxu7oonD3pzwt4X7dtRVjuRb8huNKld
+gNecffHl/mr9wuuzxvQmQquYrzYsmNT6iWw8RuiQ9102z1KfhNS3m/vsga3I1Cr5
+F4pMoy6+igjsLitlfO0vrgQi1Qgh3yxIcYc/sCt63mP3JzDxtzt7XfsAZzB3OvGU
+Xb+QY4+zxSAASXst8+L6Dsr4lnFtpyAU31NBEALHsOSKhB/hTxmxSux46H7ipCyo
+Pv7dcPKmfBxc5v3ZBp9d+JdEpdPgwg==
+-----END CERTIFICATE-----";
+
+    const TEST_ATTESTATION_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIICiDCCAXCgAwIBAgICAQIwDQYJKoZIhvcNAQELBQAwIjEgMB4GA1UEAwwXRXhh
+bXBsZSBQSVYgQXR0ZXN0YXRpb24wHhcNMjYxMDE4MTQ0MTEyWhcNNDYxMDEzMTQ0
+MTEyWjAlMSMwIQYDVQQDDBpFeGFtcGxlIFBJViBBdHRlc3RhdGlvbiA5YTBZMBMG
+ByqGSM49AgEGCCqGSM49AwEHA0IABFZRJdLrrEPGzkubE215sSuqKurrI19z+xUN
+7SceZ5TZV7C9Ru8aVAPIuVbcoJYS443oBxa6yEe90mVEuiQl9U+jgY8wgYwwEQYK
+KwYBBAGCxAoDAwQDBQQDMBQGCisGAQQBgsQKAwcEBgIEALxhTjAQBgorBgEEAYLE
+CgMIBAICAjAPBgorBgEEAYLECgMJBAGDMB0GA1UdDgQWBBQ8bTDR5QrMNN1r4mTj
+MYcgGjPXNTAfBgNVHSMEGDAWgBSbL+4Og305P/JzjMQuLJNZNFT2QjANBgkqhkiG
+9w0BAQsFAAOCAQEAQhjst12RofTaxGW7rkrCJRBmcxJlapgnU0ziZSaU88Yn0A53
+49/j+r61YhEn5RqWVc6Cn6sC/ZGI29bW7a63j9n3LW/NfvXKeKd1LQiyAUz5jRMC
+KXocXSLxPbHwPahNa5h4hPPzALP9geN8kCzrZgE4UrBUhie1B3dOM43scbg3ZVHp
+z6YFOcyF3cZ/YjcATHCnaZ1JP5RN4CMBjW4uwiZahW//bkrxxDpRoXsC0k5LW6MG
+bwqzAYn+KvSvyljIxEcdCnQFEzPR3CGHm8pCxo0fvUyUU1ZkBHedZ7WeCGCBNm9d
+LrF8JdwyWHGKWSiWSfkdaHcBGfO94EWczazwFA==
+-----END CERTIFICATE-----";
+
+    // 2036-10-15T14:13:37Z
+    const TEST_CERT_NOT_AFTER: i64 = 2_107_692_817;
+
+    fn test_cert_der() -> Vec<u8> {
+        pem_rfc7468::decode_vec(TEST_CERT_PEM.as_bytes()).unwrap().1
+    }
+
+    #[test]
+    fn test_parse_certificate_fields() {
+        let details = parse_certificate_at(&test_cert_der(), 1_800_000_000).unwrap();
+        assert_eq!(details.subject, "O=Example Corp,CN=Alice Example");
+        assert_eq!(details.issuer, details.subject);
+        assert_eq!(details.serial_number, "1a2b3c");
+        assert_eq!(details.not_after, "2036-10-15T14:13:37Z");
+        assert_eq!(details.key_algorithm, "ECC P-256");
+        assert_eq!(details.key_size, Some(256));
+        assert_eq!(
+            details.fingerprint_sha256,
+            "606fd9768df8d15838d642a5702ef20e54867fd92b2c605dacd9fc5794ec847a"
+        );
+        assert_eq!(details.key_usage, vec!["digitalSignature", "keyAgreement"]);
+        assert_eq!(
+            details.extended_key_usage,
+            vec!["clientAuth", "smartcardLogon"]
+        );
+        assert_eq!(details.subject_alt_names.email, vec!["alice@example.com"]);
+        assert_eq!(details.subject_alt_names.upn, vec!["alice@corp.example"]);
+        assert!(!details.expired);
+        assert!(!details.expiring_soon);
+    }
+
+    #[test]
+    fn test_expiry_flags() {
+        let der = test_cert_der();
+
+        let soon = parse_certificate_at(&der, TEST_CERT_NOT_AFTER - 10 * SECONDS_PER_DAY).unwrap();
+        assert!(soon.expiring_soon);
+        assert!(!soon.expired);
+        assert_eq!(soon.days_until_expiry, 10);
+
+        let expired = parse_certificate_at(&der, TEST_CERT_NOT_AFTER + 1).unwrap();
+        assert!(expired.expired);
+        assert!(!expired.expiring_soon);
+    }
+
+    #[test]
+    fn test_rsa_modulus_bits() {
+        // SEQUENCE { INTEGER 00 C0 .. (2048 bits), INTEGER 65537 }
+        let mut key = vec![0x30, 0x82, 0x01, 0x0A, 0x02, 0x82, 0x01, 0x01, 0x00, 0xC0];
+        key.extend_from_slice(&[0x11; 255]);
+        key.extend_from_slice(&[0x02, 0x03, 0x01, 0x00, 0x01]);
+        assert_eq!(rsa_modulus_bits(&key).unwrap(), 2048);
+    }
+
+    fn pem_der(pem: &str) -> Vec<u8> {
+        pem_rfc7468::decode_vec(pem.as_bytes()).unwrap().1
+    }
+
+    #[test]
+    fn test_verify_issued_by() {
+        let ca = pem_der(TEST_ATTESTATION_CA_PEM);
+        let leaf = pem_der(TEST_ATTESTATION_PEM);
+        let self_signed = test_cert_der();
+
+        // RSA PKCS#1 v1.5 and ECDSA P-256 signatures
+        verify_issued_by(&leaf, &ca).unwrap();
+        verify_issued_by(&ca, &ca).unwrap();
+        verify_issued_by(&self_signed, &self_signed).unwrap();
+
+        // Wrong issuer name
+        assert!(verify_issued_by(&leaf, &self_signed).is_err());
+
+        // Corrupted signature (last byte of the certificate)
+        let mut tampered = leaf.clone();
+        *tampered.last_mut().unwrap() ^= 0x01;
+        assert!(verify_issued_by(&tampered, &ca).is_err());
+    }
+
+    #[test]
+    fn test_extensions_and_public_key() {
+        let leaf = pem_der(TEST_ATTESTATION_PEM);
+        let extensions = extensions(&leaf).unwrap();
+        let value = |oid: &str| {
+            extensions
+                .iter()
+                .find(|(id, _)| id == oid)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(value("1.3.6.1.4.1.41482.3.3"), Some(vec![5, 4, 3]));
+        assert_eq!(value("1.3.6.1.4.1.41482.3.9"), Some(vec![0x83]));
+        assert_eq!(value("1.3.6.1.4.1.41482.3.10"), None);
+
+        let spki = subject_public_key_info(&leaf).unwrap();
+        assert_eq!(spki.len(), 91);
+        assert_eq!(spki[0], 0x30);
+    }
+
+    #[test]
+    fn test_certification_request_round_trip() {
+        use p256::ecdsa::signature::hazmat::PrehashSigner;
+        use p256::pkcs8::EncodePublicKey;
+
+        let key = p256::ecdsa::SigningKey::random(&mut rand::thread_rng());
+        let public_key = key.verifying_key().to_public_key_der().unwrap();
+        let alt_names = SubjectAltNames {
+            upn: vec!["alice@corp.example".to_string()],
+            email: vec!["alice@example.com".to_string()],
+            dns: Vec::new(),
+        };
+
+        let info = certification_request_info(
+            "CN=Alice Example,O=Example Corp",
+            public_key.as_bytes(),
+            &alt_names,
+        )
+        .unwrap();
+        let digest = Sha256::digest(&info);
+        let signature: p256::ecdsa::Signature = key.sign_prehash(&digest).unwrap();
+        let der = signature.to_der();
+
+        let request =
+            certification_request(&info, SignatureScheme::EcdsaSha256, der.as_bytes()).unwrap();
+        verify_certification_request(&request).unwrap();
+
+        let parsed = CertReq::from_der(&request).unwrap();
+        assert_eq!(
+            parsed.info.subject.to_string(),
+            "CN=Alice Example,O=Example Corp"
+        );
+        assert_eq!(parsed.info.attributes.len(), 1);
+
+        // Signature over different data
+        let wrong =
+            certification_request(&info, SignatureScheme::EcdsaSha384, der.as_bytes()).unwrap();
+        assert!(verify_certification_request(&wrong).is_err());
+    }
+
+    #[test]
+    fn test_certification_request_info_validation() {
+        let public_key = subject_public_key_info(&test_cert_der()).unwrap();
+        let names = SubjectAltNames::default();
+        assert!(certification_request_info("", &public_key, &names).is_err());
+        assert!(certification_request_info("CN=Alice", &[0x30, 0x00], &names).is_err());
+
+        let unicode = SubjectAltNames {
+            email: vec!["älice@example.com".to_string()],
+            ..SubjectAltNames::default()
+        };
+        assert!(certification_request_info("CN=Alice", &public_key, &unicode).is_err());
+    }
+
+    fn test_profile() -> CertificateProfile {
+        CertificateProfile {
+            subject: "CN=Alice Example,O=Example Corp".to_string(),
+            alt_names: SubjectAltNames {
+                upn: vec!["alice@corp.example".to_string()],
+                ..SubjectAltNames::default()
+            },
+            serial_number: vec![0x4A, 0x01, 0x02],
+            not_before: 1_800_000_000,
+            not_after: 1_800_000_000 + 365 * SECONDS_PER_DAY,
+            key_usage: vec!["digitalSignature".to_string(), "keyAgreement".to_string()],
+            extended_key_usage: vec!["clientAuth".to_string(), "smartcardLogon".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_self_signed_certificate_round_trip() {
+        use p256::ecdsa::signature::hazmat::PrehashSigner;
+        use p256::pkcs8::EncodePublicKey;
+
+        let key = p256::ecdsa::SigningKey::random(&mut rand::thread_rng());
+        let public_key = key.verifying_key().to_public_key_der().unwrap();
+
+        let tbs = self_signed_tbs_certificate(
+            &test_profile(),
+            public_key.as_bytes(),
+            SignatureScheme::EcdsaSha256,
+        )
+        .unwrap();
+        let signature: p256::ecdsa::Signature = key.sign_prehash(&Sha256::digest(&tbs)).unwrap();
+        let der = certificate(
+            &tbs,
+            SignatureScheme::EcdsaSha256,
+            signature.to_der().as_bytes(),
+        )
+        .unwrap();
+        verify_issued_by(&der, &der).unwrap();
+
+        let details = parse_certificate_at(&der, 1_800_000_000).unwrap();
+        assert_eq!(details.subject, "CN=Alice Example,O=Example Corp");
+        assert_eq!(details.issuer, details.subject);
+        assert_eq!(details.serial_number, "4a0102");
+        assert_eq!(details.days_until_expiry, 365);
+        assert_eq!(details.key_usage, vec!["digitalSignature", "keyAgreement"]);
+        assert_eq!(
+            details.extended_key_usage,
+            vec!["clientAuth", "smartcardLogon"]
+        );
+        assert_eq!(details.subject_alt_names.upn, vec!["alice@corp.example"]);
+    }
+
+    #[test]
+    fn test_self_signed_profile_validation() {
+        let public_key = subject_public_key_info(&test_cert_der()).unwrap();
+        let build = |profile: &CertificateProfile| {
+            self_signed_tbs_certificate(profile, &public_key, SignatureScheme::EcdsaSha256)
+        };
+        assert!(build(&test_profile()).is_ok());
+
+        let mut backwards = test_profile();
+        backwards.not_after = backwards.not_before;
+        assert!(build(&backwards).is_err());
+
+        let mut unknown_usage = test_profile();
+        unknown_usage.key_usage.push("signEverything".to_string());
+        assert!(build(&unknown_usage).is_err());
+
+        // Extended key usages may also be given as dotted OIDs
+        let mut dotted = test_profile();
+        dotted.extended_key_usage = vec!["1.3.6.1.5.5.7.3.4".to_string()];
+        assert!(build(&dotted).is_ok());
+        dotted.extended_key_usage = vec!["notAnOid".to_string()];
+        assert!(build(&dotted).is_err());
+    }
+
+    #[test]
+    fn test_invalid_certificate() {
+        assert!(parse_certificate(&[0x30, 0x03, 0x02, 0x01, 0x01]).is_err());
+    }
+}
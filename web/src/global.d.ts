@@ -5,7 +5,13 @@ declare global {
       send: (command: string, params?: Record<string, unknown>) => Promise<{
         status: string
         result?: unknown
-        error?: { code: string; message: string }
+        error?: {
+          code: string
+          category?: 'transport' | 'protocol' | 'user' | 'invalidParams' | 'internal'
+          retryable?: boolean
+          message: string
+          details?: Record<string, unknown>
+        }
       }>
       isConnected: () => Promise<boolean>
       getVersion: () => Promise<string>
@@ -6,6 +6,36 @@ interface PivDiscovery {
   pin_usage_policy: string | null
 }
 
+interface PivSubjectAltNames {
+  upn: string[]
+  email: string[]
+  dns: string[]
+}
+
+interface PivFascn {
+  agency_code: string
+  system_code: string
+  credential_number: string
+  credential_series: string
+  individual_credential_issue: string
+  person_identifier: string
+  organizational_category: string
+  organizational_identifier: string
+  person_association: string
+  raw: string
+}
+
+interface PivChuid {
+  fascn: PivFascn | null
+  organizational_identifier: string | null
+  duns: string | null
+  guid: string | null
+  expiration_date: string | null
+  cardholder_uuid: string | null
+  issuer_signature: string | null
+  error_detection_code: boolean
+}
+
 interface PivCertificate {
   slot: string
   slot_name: string
@@ -16,13 +46,32 @@ interface PivCertificate {
   serial_number: string | null
   not_before: string | null
   not_after: string | null
+  key_algorithm: string | null
+  key_size: number | null
+  fingerprint_sha256: string | null
+  key_usage: string[]
+  extended_key_usage: string[]
+  subject_alt_names: PivSubjectAltNames
+  expired: boolean
+  expiring_soon: boolean
+}
+
+interface PivDataObject {
+  name: string
+  tag: string
+  present: boolean
+  size: number
+  pin_required: boolean
+  content: ({ kind: string } & Record<string, unknown>) | null
 }
 
 interface PivInfo {
   selected: boolean
   chuid: string | null
+  chuid_details: PivChuid | null
   discovery: PivDiscovery | null
   certificates: PivCertificate[]
+  data_objects: PivDataObject[]
 }
 
 interface ApduLog {
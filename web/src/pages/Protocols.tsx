@@ -6,6 +6,7 @@ interface ProtocolSupport {
   u2f: boolean
   piv: boolean
   openpgp: boolean
+  oath: boolean
   otp: boolean
   ndef: boolean
 }
@@ -154,6 +155,13 @@ export default function Protocols() {
       description: 'Email encryption and digital signatures',
       supported: protocols?.openpgp || false,
     },
+    {
+      id: 'oath',
+      name: 'OATH',
+      subtitle: 'TOTP / HOTP',
+      description: 'Authenticator codes stored on the key',
+      supported: protocols?.oath || false,
+    },
     {
       id: 'otp',
       name: 'OTP',
@@ -255,6 +263,7 @@ export default function Protocols() {
             <li><strong>U2F:</strong> CTAP1 version command via HID</li>
             <li><strong>PIV:</strong> SELECT APDU (A0 00 00 03 08) via CCID</li>
             <li><strong>OpenPGP:</strong> SELECT APDU (D2 76 00 01 24 01) via CCID</li>
+            <li><strong>OATH:</strong> SELECT APDU (A0 00 00 05 27 21 01) via CCID</li>
             <li><strong>OTP:</strong> Vendor-specific command via HID</li>
             <li><strong>NDEF:</strong> SELECT APDU (D2 76 00 00 85 01 01) via CCID</li>
           </ul>